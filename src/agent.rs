@@ -1,14 +1,23 @@
+use crate::checkpoint::RestoredCheckpoint;
+use crate::client::{ChatRequest, ChatResponse, LlmClient};
 use crate::config::Config;
+use crate::context::{self, CompactionStrategy};
+use crate::events::{AgentEvent, EventSink, NullSink, TokenUsage};
 use crate::file_state_manager::FileStateManager;
+use crate::git;
 use crate::prompt_builder;
-use crate::streaming_executor;
+use crate::reasoning::{self, ReasoningHistory};
+use crate::session::Session;
+use crate::text_tool_calls;
+use crate::tool_call_repair;
 use crate::tool_collection::ToolCollection;
-use anyhow::Result;
+use crate::usage::UsageTotals;
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
 use openrouter_api::models::tool::ToolCall;
-use openrouter_api::types::chat::{ChatCompletionRequest, Message};
-use openrouter_api::{OpenRouterClient, Ready};
+use openrouter_api::types::chat::Message;
+use std::ops::Range;
 use std::sync::{Arc, Mutex};
-use tokio::task::JoinHandle;
 
 #[derive(Debug)]
 pub struct PromptData {
@@ -17,10 +26,18 @@ pub struct PromptData {
     pub warnings: Vec<String>,
 }
 
+impl PromptData {
+    /// Appends text that is not a file, e.g. piped stdin, after the attached files.
+    pub fn attach(&mut self, name: &str, content: &str) {
+        self.final_prompt
+            .push_str(&prompt_builder::format_attachment(name, content));
+        self.file_summaries
+            .push(format!("[{name} ({} lines)]", content.lines().count()));
+    }
+}
+
 #[derive(Debug)]
 pub enum AgentOutput {
-    /// The agent has spawned an LLM task that needs to be awaited.
-    PendingLLM(JoinHandle<Result<Option<Message>>>),
     /// The agent has produced a text response for the user.
     Message(Message),
     /// The agent wants to execute one or more tools and requires
@@ -30,28 +47,150 @@ pub enum AgentOutput {
     Done,
 }
 
+/// Why `Agent::run_turn` returned control to the caller.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StopReason {
+    /// The model answered without requesting further tool calls.
+    Done,
+    /// The turn was stopped after the configured number of LLM calls.
+    MaxIterations(u8),
+    /// The approver cancelled the remaining tool calls.
+    Cancelled,
+}
+
+/// The decision of a `ToolApprover` about a single tool call.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Approval {
+    /// Execute the tool call.
+    Approved,
+    /// Skip this tool call and report the reason to the model. The turn continues.
+    Denied(String),
+    /// Skip this and all remaining tool calls and end the turn.
+    Cancelled,
+}
+
+/// Everything a `ToolApprover` needs to decide about a single tool call.
+pub struct ApprovalRequest<'a> {
+    pub tool_call: &'a ToolCall,
+    /// The human-readable dry run of the tool call.
+    pub preview: &'a str,
+    /// `true` if the configuration allows executing this call without confirmation,
+    /// i.e. `auto_execute` is enabled and the tool considers the call safe.
+    pub auto_approved: bool,
+}
+
+/// Decides whether the tool calls requested by the model may be executed.
+/// `Agent::run_turn` asks the approver once per tool call, in order.
+#[async_trait]
+pub trait ToolApprover: Send {
+    async fn approve(&mut self, request: ApprovalRequest<'_>) -> Result<Approval>;
+}
+
+/// Instructions for the architect, sent with every request of the architect phase.
+const ARCHITECT_PROMPT: &str = "You are the architect. Plan the change the user asked for, but do not make it: another model, the editor, will implement your plan with the file editing tools. You can only read files. Describe every edit precisely, with the file, the LIDs of the affected lines and the new content. If no change is needed, just answer the user.";
+
+/// The hand-off from the architect to the editor, appended to the requests of the editor.
+const EDITOR_PROMPT: &str = "You are the editor. Implement the plan above exactly, using the file tools. Do not change anything the plan does not ask for. If the plan needs no changes, answer briefly that there is nothing to do.";
+
+/// The phases of a turn in the architect/editor mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Phase {
+    /// `architect_model` plans the change with read-only tools.
+    Architect,
+    /// `editor_model` implements the plan with all tools.
+    Editor,
+}
+
+/// The `EventSink` of tool calls: forwards their events and collects the usage of the
+/// sub-agents they run.
+struct ToolEvents {
+    events: Arc<dyn EventSink>,
+    subagent_usage: Mutex<Vec<TokenUsage>>,
+}
+
+impl ToolEvents {
+    fn new(events: Arc<dyn EventSink>) -> Self {
+        Self {
+            events,
+            subagent_usage: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+impl EventSink for ToolEvents {
+    fn emit(&self, event: AgentEvent) {
+        if let AgentEvent::SubagentUsage { usage } = &event {
+            self.subagent_usage.lock().unwrap().push(*usage);
+        }
+        self.events.emit(event);
+    }
+}
+
+/// The result of a tool call that is not in the history yet.
+enum PendingResult {
+    /// A read-only call that was approved and will be executed with the others.
+    Approved(ToolCall),
+    /// The call was answered without executing it, e.g. because it was denied.
+    Answered(Message),
+}
+
 pub struct Agent {
-    pub client: Option<Arc<OpenRouterClient<Ready>>>,
+    pub client: Option<Arc<dyn LlmClient>>,
     pub config: Config,
     pub messages: Vec<Message>,
     pub tool_collection: Arc<ToolCollection>,
     pub file_state_manager: Arc<Mutex<FileStateManager>>,
+    /// The session the message history is persisted to, if any.
+    pub session: Option<Session>,
+    /// Whether `run_turn` streams LLM responses.
+    /// If `false`, responses are requested in one piece and no deltas are emitted.
+    pub streaming: bool,
+    /// Receives everything that happens during a turn. Defaults to `NullSink`.
+    pub events: Arc<dyn EventSink>,
+    /// Token usage and cost of the current or last turn.
+    pub turn_usage: UsageTotals,
+    /// Token usage and cost since the agent was created.
+    pub session_usage: UsageTotals,
+    /// Whether `run_turn` takes a checkpoint that `undo_turn` can restore. Disabled for
+    /// sub-agents, which share the `FileStateManager` of their parent.
+    pub take_checkpoints: bool,
+    /// The current phase of the architect/editor mode, if it is enabled.
+    phase: Option<Phase>,
+    /// The working tree before the running turn and its prompt, if `git_auto_commit`
+    /// commits the turn when it ends.
+    pending_commit: Option<(Result<git::RepoState>, String)>,
 }
 
 impl Agent {
     /// Creates a new `Agent` with its own state.
     pub fn new(
         config: Config,
-        client: Option<OpenRouterClient<Ready>>,
+        client: Option<Arc<dyn LlmClient>>,
         tool_collection: Arc<ToolCollection>,
     ) -> Self {
         Self {
-            client: client.map(Arc::new),
+            client,
             config,
             messages: Vec::new(),
             file_state_manager: Arc::new(Mutex::new(FileStateManager::new())),
             tool_collection,
+            session: None,
+            streaming: true,
+            events: Arc::new(NullSink),
+            turn_usage: UsageTotals::default(),
+            session_usage: UsageTotals::default(),
+            take_checkpoints: true,
+            phase: None,
+            pending_commit: None,
+        }
+    }
+
+    /// Persists the message history to the agent's session, if it has one.
+    pub fn save_session(&mut self) -> Result<()> {
+        if let Some(session) = &mut self.session {
+            session.save(&self.messages)?;
         }
+        Ok(())
     }
 
     /// Processes a raw user prompt, expanding file paths and generating context.
@@ -66,105 +205,714 @@ impl Agent {
         })
     }
 
-    /// Takes a user prompt, runs the LLM, and returns a handle to the streaming task.
-    pub fn step_streaming(&mut self, prompt: String) -> Result<AgentOutput> {
-        let request = self.prepare_request(prompt)?;
+    /// Runs a complete agent turn: LLM call, tool approval and tool execution are
+    /// repeated until the model answers without tool calls, the approver cancels,
+    /// or `max_iterations` LLM calls have been made.
+    ///
+    /// If `architect_model` is set, the turn has two phases: the architect plans the
+    /// change with read-only tools, then the editor implements the plan.
+    ///
+    /// An empty `prompt` continues from the current history, e.g. after tool results.
+    pub async fn run_turn(
+        &mut self,
+        prompt: String,
+        approver: &mut dyn ToolApprover,
+    ) -> Result<StopReason> {
+        if self.take_checkpoints {
+            let messages = self.messages.clone();
+            self.file_state_manager
+                .lock()
+                .unwrap()
+                .begin_checkpoint(messages, self.config.undo_depth);
+        }
+        // The working tree is checked before the turn, so changes of the user are never
+        // committed.
+        self.pending_commit = (self.take_checkpoints && self.config.git_auto_commit).then(|| {
+            let repo_state = std::env::current_dir()
+                .map_err(anyhow::Error::from)
+                .and_then(|dir| git::RepoState::read(&dir));
+            (repo_state, prompt.clone())
+        });
+        self.push_user_prompt(prompt);
+        self.turn_usage = UsageTotals::default();
 
-        if let Some(request) = request {
-            let client = self.client.as_ref().unwrap().clone();
-            let handle = tokio::spawn(async move {
-                streaming_executor::stream_and_collect_response(&client, request).await
-            });
-            Ok(AgentOutput::PendingLLM(handle))
-        } else {
-            Ok(AgentOutput::Done)
+        let result = self.run_phases(approver).await;
+        self.commit_turn(false);
+        result
+    }
+
+    /// Cleans up after a turn was aborted by dropping the future of `run_turn`: answers
+    /// its open tool calls, so the history stays valid for the next request, and commits
+    /// the files it wrote so far with `git_auto_commit`.
+    pub fn abort_turn(&mut self) {
+        self.cancel_pending_tool_calls();
+        self.commit_turn(true);
+    }
+
+    /// Runs the phases of a turn: a single one, or the architect and the editor phase.
+    async fn run_phases(&mut self, approver: &mut dyn ToolApprover) -> Result<StopReason> {
+        let mut iterations: u8 = 0;
+        if self.config.architect_model.is_none() {
+            return self.run_phase(None, approver, &mut iterations).await;
+        }
+        let stop_reason = self
+            .run_phase(Some(Phase::Architect), approver, &mut iterations)
+            .await?;
+        if stop_reason != StopReason::Done || !self.has_plan() {
+            return Ok(stop_reason);
         }
+
+        self.events.emit(AgentEvent::EditorStarted {
+            model: self.config.editor_model().to_string(),
+        });
+        self.run_phase(Some(Phase::Editor), approver, &mut iterations)
+            .await
     }
 
-    /// Takes a user prompt, runs the LLM, and returns the final `AgentOutput`.
-    pub async fn step_non_streaming(&mut self, prompt: String) -> Result<AgentOutput> {
-        let request = self.prepare_request(prompt)?;
-
-        if let Some(request) = request {
-            let client = self.client.as_ref().unwrap().clone();
-            let response =
-                streaming_executor::collect_response_non_streaming(&client, request).await?;
-            if let Some(message) = response {
-                self.messages.push(message.clone());
-                if let Some(tool_calls) = message.tool_calls {
-                    Ok(AgentOutput::ToolCalls(tool_calls))
-                } else {
-                    Ok(AgentOutput::Message(message))
-                }
+    /// Whether the architect's last message contains a plan for the editor.
+    fn has_plan(&self) -> bool {
+        self.messages
+            .last()
+            .filter(|message| message.role == "assistant")
+            .is_some_and(|message| {
+                !reasoning::strip_reasoning(&message.content)
+                    .trim()
+                    .is_empty()
+            })
+    }
+
+    /// Runs LLM calls and tool calls in the given phase until the model answers.
+    async fn run_phase(
+        &mut self,
+        phase: Option<Phase>,
+        approver: &mut dyn ToolApprover,
+        iterations: &mut u8,
+    ) -> Result<StopReason> {
+        self.phase = phase;
+        let result = self.run_iterations(approver, iterations).await;
+        self.phase = None;
+        result
+    }
+
+    async fn run_iterations(
+        &mut self,
+        approver: &mut dyn ToolApprover,
+        iterations: &mut u8,
+    ) -> Result<StopReason> {
+        loop {
+            if *iterations >= self.config.max_iterations {
+                return Ok(StopReason::MaxIterations(self.config.max_iterations));
+            }
+            *iterations += 1;
+
+            self.supersede_stale_file_views()?;
+            self.manage_context().await?;
+            let output = if self.streaming {
+                self.step(String::new()).await?
             } else {
-                Ok(AgentOutput::Done)
+                self.step_non_streaming(String::new()).await?
+            };
+            match output {
+                AgentOutput::ToolCalls(tool_calls) => {
+                    if !self.process_tool_calls(tool_calls, approver).await? {
+                        return Ok(StopReason::Cancelled);
+                    }
+                }
+                AgentOutput::Message(_) | AgentOutput::Done => return Ok(StopReason::Done),
             }
-        } else {
-            Ok(AgentOutput::Done)
         }
     }
 
+    /// Takes a user prompt, streams the LLM response as events, appends it to
+    /// the history and returns the resulting `AgentOutput`.
+    pub async fn step(&mut self, prompt: String) -> Result<AgentOutput> {
+        let Some(request) = self.prepare_request(prompt)? else {
+            return Ok(AgentOutput::Done);
+        };
+        let client = self.require_client()?;
+        let response = client
+            .chat_completion_stream(request, &*self.events)
+            .await?;
+        self.record_usage(response.usage, response.model.as_deref());
+        Ok(self.record_response(response))
+    }
+
+    /// Takes a user prompt, runs the LLM, and returns the final `AgentOutput`.
+    pub async fn step_non_streaming(&mut self, prompt: String) -> Result<AgentOutput> {
+        let Some(request) = self.prepare_request(prompt)? else {
+            return Ok(AgentOutput::Done);
+        };
+        let client = self.require_client()?;
+        let response = client.chat_completion(request, &*self.events).await?;
+        self.record_usage(response.usage, response.model.as_deref());
+        Ok(self.record_response(response))
+    }
+
     /// Executes a list of approved tool calls and returns the resulting
-    /// messages.
+    /// messages. Consecutive read-only calls run concurrently; the results keep
+    /// the order of the calls.
     pub async fn execute_tool_calls(&mut self, tool_calls: Vec<ToolCall>) -> Result<Vec<Message>> {
         let mut result_messages = Vec::new();
-        for tool_call in tool_calls {
-            let result_msg = self
-                .tool_collection
-                .execute_tool_call(&tool_call, &self.config, self.file_state_manager.clone())
-                .await;
-            self.messages.push(result_msg.clone());
-            result_messages.push(result_msg);
+        let mut remaining = tool_calls.as_slice();
+        while let Some(tool_call) = remaining.first() {
+            let read_only_count = remaining
+                .iter()
+                .take_while(|tool_call| self.tool_collection.is_read_only(tool_call))
+                .count();
+            let batch_messages = if read_only_count > 0 {
+                let messages = self
+                    .execute_concurrently(&remaining[..read_only_count])
+                    .await;
+                remaining = &remaining[read_only_count..];
+                messages
+            } else {
+                remaining = &remaining[1..];
+                vec![self.execute_tool_call(tool_call).await]
+            };
+            self.messages.extend(batch_messages.iter().cloned());
+            result_messages.extend(batch_messages);
         }
         Ok(result_messages)
     }
 
+    /// Returns the estimated number of tokens of the message history.
+    pub fn estimated_tokens(&self) -> u64 {
+        context::estimate_history_tokens(&self.messages)
+    }
+
+    /// Collapses the LIF views in the history whose file has changed since they were shown,
+    /// so the model only sees current LIDs.
+    pub fn supersede_stale_file_views(&mut self) -> Result<()> {
+        let current_hashes = self.file_state_manager.lock().unwrap().current_hashes();
+        if context::supersede_stale_views(&mut self.messages, &current_hashes) == 0 {
+            return Ok(());
+        }
+        // Earlier messages changed, so appending is not enough.
+        if let Some(session) = &mut self.session {
+            session.rewrite(&self.messages)?;
+        }
+        Ok(())
+    }
+
+    /// Compacts the history if it approaches `context_budget_tokens`, using the configured
+    /// `compaction` strategy. Placeholders are used as a fallback if summarizing fails or
+    /// is not enough.
+    pub async fn manage_context(&mut self) -> Result<()> {
+        let budget = self.config.context_budget_tokens;
+        if !context::needs_compaction(&self.messages, budget) {
+            return Ok(());
+        }
+        let tokens_before = self.estimated_tokens();
+
+        let summarizable_range = context::summarizable_range(&self.messages)
+            .filter(|_| self.config.compaction == CompactionStrategy::Summarize);
+        if let Some(range) = summarizable_range {
+            let summary = self.summarize(range.clone()).await;
+            match summary {
+                Ok(summary) => {
+                    let message = context::summary_message(&summary, &self.messages, range.clone());
+                    self.messages.splice(range, [message]);
+                }
+                Err(e) => self.events.emit(AgentEvent::Error {
+                    message: format!("Failed to summarize the history: {e}"),
+                }),
+            }
+        }
+        if context::needs_compaction(&self.messages, budget) {
+            let target = budget * context::COMPACTION_TARGET_PERCENT / 100;
+            context::compact_with_placeholders(&mut self.messages, target);
+        }
+
+        let tokens_after = self.estimated_tokens();
+        if tokens_after < tokens_before {
+            self.events.emit(AgentEvent::HistoryCompacted {
+                strategy: self.config.compaction,
+                tokens_before,
+                tokens_after,
+            });
+            // Earlier messages changed, so appending is not enough.
+            if let Some(session) = &mut self.session {
+                session.rewrite(&self.messages)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Undoes the last turn: restores the files it edited, removes the files it created
+    /// and rolls the history back to before the turn. If the turn was committed by
+    /// `git_auto_commit`, its commit is reverted instead. Returns `None` if there is no
+    /// turn to undo.
+    pub fn undo_turn(&mut self) -> Result<Option<RestoredCheckpoint>> {
+        let restored = {
+            let mut fsm = self.file_state_manager.lock().unwrap();
+            let Some(checkpoint) = fsm.checkpoints.pop() else {
+                return Ok(None);
+            };
+            match checkpoint.commit.clone() {
+                Some(commit) => {
+                    // Reverting keeps the changes the user made after the turn. A failed
+                    // revert is aborted, so the turn can still be undone later.
+                    if let Err(e) = git::revert(&std::env::current_dir()?, &commit) {
+                        fsm.checkpoints.push(checkpoint);
+                        return Err(e);
+                    }
+                    let mut restored = checkpoint.restore_cached_states(&mut fsm.open_files)?;
+                    restored.reverted_commit = Some(commit);
+                    restored
+                }
+                None => checkpoint.restore(&mut fsm.open_files)?,
+            }
+        };
+        self.messages = restored.messages.clone();
+        // The turn may have changed earlier messages, so truncating is not enough.
+        if let Some(session) = &mut self.session {
+            session.rewrite(&self.messages)?;
+        }
+        Ok(Some(restored))
+    }
+
+    /// Answers all tool calls of the last assistant message that have no result yet
+    /// with a cancellation notice. Call this after aborting a turn, so the history
+    /// stays valid for the next request.
+    pub fn cancel_pending_tool_calls(&mut self) {
+        let Some(assistant_index) = self.messages.iter().rposition(|m| m.role == "assistant")
+        else {
+            return;
+        };
+        let Some(tool_calls) = self.messages[assistant_index].tool_calls.clone() else {
+            return;
+        };
+
+        for tool_call in tool_calls {
+            let answered = self.messages[assistant_index + 1..].iter().any(|m| {
+                m.role == "tool" && m.tool_call_id.as_deref() == Some(tool_call.id.as_str())
+            });
+            if !answered {
+                self.push_tool_result(&tool_call, "Tool execution cancelled by user.".to_string());
+            }
+        }
+    }
+
     // --- Private Helper Functions ---
 
-    fn prepare_request(&mut self, prompt: String) -> Result<Option<ChatCompletionRequest>> {
+    /// Previews, approves and executes the tool calls of one assistant message.
+    /// Approved read-only calls are collected and executed concurrently before the
+    /// next mutating call, so every call still sees the effects of the calls before it.
+    /// Returns `false` if the approver cancelled the remaining tool calls.
+    async fn process_tool_calls(
+        &mut self,
+        tool_calls: Vec<ToolCall>,
+        approver: &mut dyn ToolApprover,
+    ) -> Result<bool> {
+        let tool_collection = Arc::clone(&self.tool_collection);
+        let mut pending_results = Vec::new();
+
+        for (index, tool_call) in tool_calls.iter().enumerate() {
+            let read_only = tool_collection.is_read_only(tool_call);
+            if !read_only && self.phase == Some(Phase::Architect) {
+                pending_results.push(PendingResult::Answered(tool_result(
+                    tool_call,
+                    "The architect can only use read-only tools. Describe the change in your plan instead.".to_string(),
+                )));
+                continue;
+            }
+            if !read_only {
+                self.flush_pending_results(&mut pending_results).await;
+            }
+
+            let preview = match tool_collection
+                .preview_tool_call(tool_call, &self.config, self.file_state_manager.clone())
+                .await
+            {
+                Ok(preview) => preview,
+                Err(e) => {
+                    let error_message = format!("Preview failed, skipping: {e}");
+                    self.events.emit(AgentEvent::Error {
+                        message: error_message.clone(),
+                    });
+                    // Inform the agent that this tool failed.
+                    pending_results.push(PendingResult::Answered(tool_result(
+                        tool_call,
+                        error_message,
+                    )));
+                    continue;
+                }
+            };
+
+            let auto_approved = self.config.auto_execute
+                && tool_collection
+                    .is_safe_for_auto_execute(tool_call, &self.config)
+                    .unwrap_or(false);
+
+            let approval = approver
+                .approve(ApprovalRequest {
+                    tool_call,
+                    preview: &preview,
+                    auto_approved,
+                })
+                .await?;
+
+            match approval {
+                Approval::Approved if read_only => {
+                    pending_results.push(PendingResult::Approved(tool_call.clone()));
+                }
+                Approval::Approved => {
+                    let result_msg = self.execute_tool_call(tool_call).await;
+                    self.messages.push(result_msg);
+                }
+                Approval::Denied(reason) => {
+                    pending_results.push(PendingResult::Answered(tool_result(tool_call, reason)));
+                }
+                Approval::Cancelled => {
+                    self.flush_pending_results(&mut pending_results).await;
+                    for remaining_tool_call in &tool_calls[index..] {
+                        self.push_tool_result(
+                            remaining_tool_call,
+                            "Tool execution cancelled by user.".to_string(),
+                        );
+                    }
+                    return Ok(false);
+                }
+            }
+        }
+        self.flush_pending_results(&mut pending_results).await;
+        Ok(true)
+    }
+
+    /// Executes the approved read-only calls concurrently and appends all pending results
+    /// to the history, in order.
+    async fn flush_pending_results(&mut self, pending_results: &mut Vec<PendingResult>) {
+        let approved: Vec<ToolCall> = pending_results
+            .iter()
+            .filter_map(|pending| match pending {
+                PendingResult::Approved(tool_call) => Some(tool_call.clone()),
+                PendingResult::Answered(_) => None,
+            })
+            .collect();
+        let mut results = self.execute_concurrently(&approved).await.into_iter();
+
+        for pending in pending_results.drain(..) {
+            let message = match pending {
+                PendingResult::Approved(_) => results.next(),
+                PendingResult::Answered(message) => Some(message),
+            };
+            self.messages.extend(message);
+        }
+    }
+
+    /// Executes the tool calls concurrently and returns their results in the same order.
+    /// Only use this for read-only calls: the tasks keep running if the turn is aborted.
+    async fn execute_concurrently(&mut self, tool_calls: &[ToolCall]) -> Vec<Message> {
+        let tool_events = Arc::new(ToolEvents::new(Arc::clone(&self.events)));
+        let handles: Vec<_> = tool_calls
+            .iter()
+            .cloned()
+            .map(|tool_call| {
+                let tool_collection = Arc::clone(&self.tool_collection);
+                let config = self.config.clone();
+                let fsm = self.file_state_manager.clone();
+                let events = Arc::clone(&tool_events);
+                tokio::spawn(async move {
+                    tool_collection
+                        .execute_tool_call(&tool_call, &config, fsm, &*events)
+                        .await
+                })
+            })
+            .collect();
+
+        let mut messages = Vec::new();
+        for (handle, tool_call) in handles.into_iter().zip(tool_calls) {
+            let message = handle
+                .await
+                .unwrap_or_else(|e| tool_result(tool_call, format!("Tool execution failed: {e}")));
+            messages.push(message);
+        }
+        self.add_subagent_usage(&tool_events);
+        messages
+    }
+
+    async fn execute_tool_call(&mut self, tool_call: &ToolCall) -> Message {
+        let tool_events = ToolEvents::new(Arc::clone(&self.events));
+        let message = self
+            .tool_collection
+            .execute_tool_call(
+                tool_call,
+                &self.config,
+                self.file_state_manager.clone(),
+                &tool_events,
+            )
+            .await;
+        self.add_subagent_usage(&tool_events);
+        message
+    }
+
+    /// Adds the usage of the sub-agents that ran in tool calls to the totals. It was
+    /// already priced by the sub-agents.
+    fn add_subagent_usage(&mut self, tool_events: &ToolEvents) {
+        for usage in tool_events.subagent_usage.lock().unwrap().drain(..) {
+            self.turn_usage.add(&usage);
+            self.session_usage.add(&usage);
+        }
+    }
+
+    /// Asks the model for a summary of the messages in `range`.
+    async fn summarize(&mut self, range: Range<usize>) -> Result<String> {
+        let client = self.require_client()?;
+        let request = ChatRequest {
+            model: self.config.model.clone(),
+            messages: vec![
+                Message {
+                    role: "system".to_string(),
+                    content: context::SUMMARY_SYSTEM_PROMPT.to_string(),
+                    name: None,
+                    tool_calls: None,
+                    tool_call_id: None,
+                },
+                Message {
+                    role: "user".to_string(),
+                    content: context::render_transcript(&self.messages[range]),
+                    name: None,
+                    tool_calls: None,
+                    tool_call_id: None,
+                },
+            ],
+            tools: Vec::new(),
+        };
+        let response = client.chat_completion(request, &*self.events).await?;
+        self.record_usage(response.usage, response.model.as_deref());
+        response
+            .message
+            .map(|message| message.content)
+            .filter(|summary| !summary.trim().is_empty())
+            .ok_or_else(|| anyhow!("The model returned an empty summary."))
+    }
+
+    fn push_tool_result(&mut self, tool_call: &ToolCall, content: String) {
+        self.messages.push(tool_result(tool_call, content));
+    }
+
+    /// Commits the files the turn wrote, if any. Reasons not to commit are reported as
+    /// events, since the turn itself succeeded.
+    fn commit_turn(&mut self, cancelled: bool) {
+        let Some((repo_state, prompt)) = self.pending_commit.take() else {
+            return;
+        };
+        let mut fsm = self.file_state_manager.lock().unwrap();
+        let Some(checkpoint) = fsm.checkpoints.last_mut() else {
+            return;
+        };
+        let written = checkpoint.written_files();
+        if written.is_empty() {
+            return;
+        }
+        let answer = if cancelled {
+            "The turn was cancelled before it finished.".to_string()
+        } else {
+            // Only the answers of this turn, without their stored reasoning.
+            self.messages
+                .get(checkpoint.messages.len()..)
+                .unwrap_or_default()
+                .iter()
+                .rev()
+                .filter(|m| m.role == "assistant")
+                .map(|m| reasoning::strip_reasoning(&m.content))
+                .find(|content| !content.trim().is_empty())
+                .unwrap_or_default()
+        };
+        let committed = repo_state.and_then(|repo_state| {
+            repo_state.commit_turn(&written, &prompt, &answer, self.config.editor_model())
+        });
+        match committed {
+            Ok(commit) => {
+                checkpoint.commit = Some(commit.clone());
+                self.events.emit(AgentEvent::TurnCommitted {
+                    commit,
+                    files: written,
+                });
+            }
+            Err(e) => self.events.emit(AgentEvent::AutoCommitSkipped {
+                reason: e.to_string(),
+            }),
+        }
+    }
+
+    fn push_user_prompt(&mut self, prompt: String) {
         if !prompt.is_empty() {
-            let user_message = Message {
+            self.messages.push(Message {
                 role: "user".to_string(),
                 content: prompt,
                 name: None,
                 tool_calls: None,
                 tool_call_id: None,
-            };
-            self.messages.push(user_message);
+            });
+        }
+    }
+
+    fn require_client(&self) -> Result<Arc<dyn LlmClient>> {
+        self.client
+            .clone()
+            .ok_or_else(|| anyhow!("The agent has no LLM client configured."))
+    }
+
+    /// The model that answers the next request.
+    fn current_model(&self) -> &str {
+        match self.phase {
+            Some(Phase::Architect) => self
+                .config
+                .architect_model
+                .as_deref()
+                .unwrap_or(&self.config.model),
+            Some(Phase::Editor) => self.config.editor_model(),
+            None => &self.config.model,
+        }
+    }
+
+    /// Prices the usage of a request with the configured `pricing` of the model that
+    /// answered, unless the API reported the cost, and adds it to the turn and session
+    /// totals.
+    fn record_usage(&mut self, usage: Option<TokenUsage>, model: Option<&str>) {
+        let Some(mut usage) = usage else {
+            return;
+        };
+        if usage.cost.is_none() {
+            let pricing = &self.config.pricing;
+            usage.cost = model
+                .and_then(|model| pricing.get(model))
+                .or_else(|| pricing.get(self.current_model()))
+                .map(|pricing| pricing.cost(&usage));
+        }
+        self.turn_usage.add(&usage);
+        self.session_usage.add(&usage);
+        self.events.emit(AgentEvent::Usage { usage });
+    }
+
+    /// Appends the LLM response to the history and converts it to an `AgentOutput`.
+    fn record_response(&mut self, response: ChatResponse) -> AgentOutput {
+        // OpenRouter reports versioned names, e.g. `openai/gpt-4.1-mini-2025-04-14`.
+        let other_model = response
+            .model
+            .as_ref()
+            .filter(|model| !model.starts_with(self.current_model()));
+        if let Some(model) = other_model {
+            self.events.emit(AgentEvent::AnsweredBy {
+                model: model.clone(),
+            });
+        }
+        let Some(mut message) = response.message else {
+            return AgentOutput::Done;
+        };
+        if self.config.uses_text_tool_calls(self.current_model()) && message.tool_calls.is_none() {
+            let (content, tool_calls) = text_tool_calls::parse_tool_calls(
+                &message.content,
+                &self.tool_collection.get_all_schemas(),
+                &format!("call_{}", self.messages.len()),
+            );
+            if !tool_calls.is_empty() {
+                message.content = content;
+                message.tool_calls = Some(tool_calls);
+            }
         }
+        if let Some(tool_calls) = &mut message.tool_calls {
+            let id_prefix = format!("call_{}", self.messages.len());
+            for repaired in tool_call_repair::normalize_tool_calls(tool_calls, &id_prefix) {
+                self.events.emit(AgentEvent::ToolCallRepaired {
+                    tool_call: repaired.tool_call,
+                    repairs: repaired.repairs,
+                });
+            }
+        }
+        match response.reasoning {
+            Some(reasoning) if self.config.reasoning_history != ReasoningHistory::Drop => {
+                let mut stored = message.clone();
+                stored.content = reasoning::with_reasoning(&message.content, &reasoning);
+                self.messages.push(stored);
+            }
+            _ => self.messages.push(message.clone()),
+        }
+        self.events.emit(AgentEvent::AssistantMessage {
+            message: message.clone(),
+        });
+        match message.tool_calls.filter(|calls| !calls.is_empty()) {
+            Some(tool_calls) => AgentOutput::ToolCalls(tool_calls),
+            None => AgentOutput::Message(message),
+        }
+    }
+
+    fn prepare_request(&mut self, prompt: String) -> Result<Option<ChatRequest>> {
+        self.push_user_prompt(prompt);
 
         if self.messages.is_empty() {
             return Ok(None);
         }
 
-        let tools = self.tool_collection.get_all_schemas();
-        let request = ChatCompletionRequest {
-            model: self.config.model.clone(),
-            messages: self.messages.clone(),
-            tools: Some(tools),
-            stream: Some(true), // This will be overridden in the non-streaming case
-            response_format: None,
-            provider: None,
-            models: None,
-            transforms: None,
+        let model = self.current_model().to_string();
+        let mut messages = self.request_messages();
+        let phase_prompt = match self.phase {
+            Some(Phase::Architect) => Some(ARCHITECT_PROMPT),
+            Some(Phase::Editor) => Some(EDITOR_PROMPT),
+            None => None,
+        };
+        if let Some(phase_prompt) = phase_prompt {
+            messages.push(Message {
+                role: "system".to_string(),
+                content: phase_prompt.to_string(),
+                name: None,
+                tool_calls: None,
+                tool_call_id: None,
+            });
+        }
+        let tools = if self.phase == Some(Phase::Architect) {
+            self.tool_collection.get_read_only_schemas()
+        } else {
+            self.tool_collection.get_all_schemas()
+        };
+        let request = if self.config.uses_text_tool_calls(&model) {
+            ChatRequest {
+                messages: text_tool_calls::to_text_messages(&messages, &tools),
+                model,
+                tools: Vec::new(),
+            }
+        } else {
+            ChatRequest {
+                model,
+                messages,
+                tools,
+            }
         };
 
         if self.config.print_messages {
-            println!();
-            println!(
-                "{}",
-                console::style("Messages being sent to API:")
-                    .yellow()
-                    .bold()
-            );
-            for message in &self.messages {
-                let message_json = serde_json::to_string_pretty(message)
-                    .unwrap_or_else(|e| format!("Failed to serialize message: {e}"));
-                println!("{message_json}");
-            }
-            println!();
+            self.events.emit(AgentEvent::RequestMessages {
+                messages: request.messages.clone(),
+            });
         }
 
         Ok(Some(request))
     }
+
+    /// The history as sent to the model, without the reasoning that is not kept.
+    fn request_messages(&self) -> Vec<Message> {
+        let last_assistant = self.messages.iter().rposition(|m| m.role == "assistant");
+        let mut messages = self.messages.clone();
+        for (index, message) in messages.iter_mut().enumerate() {
+            let keep = match self.config.reasoning_history {
+                ReasoningHistory::Drop => false,
+                ReasoningHistory::Last => Some(index) == last_assistant,
+                ReasoningHistory::All => true,
+            };
+            if message.role == "assistant" && !keep {
+                message.content = reasoning::strip_reasoning(&message.content);
+            }
+        }
+        messages
+    }
+}
+
+fn tool_result(tool_call: &ToolCall, content: String) -> Message {
+    Message {
+        role: "tool".to_string(),
+        content,
+        name: Some(tool_call.function_call.name.clone()),
+        tool_call_id: Some(tool_call.id.clone()),
+        tool_calls: None,
+    }
 }
@@ -0,0 +1,612 @@
+//! # Anthropic Messages API
+//!
+//! A native `LlmClient` for Anthropic's `/v1/messages` endpoint. The internal
+//! OpenAI-style `Message`s are translated to Anthropic content blocks and back:
+//!
+//! - `system` messages become the top-level `system` prompt.
+//! - Assistant tool calls become `tool_use` blocks, `tool` messages become `tool_result`
+//!   blocks inside a `user` message. Consecutive messages with the same role are merged,
+//!   since the API requires alternating roles.
+//! - `thinking` blocks have no place in the internal `Message`. They are kept by the
+//!   client, keyed by the id of the first tool call of their message, and sent back
+//!   unchanged, as the API requires during a tool-use loop.
+//!
+//! Prompt caching is enabled by marking the system prompt, the tool definitions and the
+//! last message as cache breakpoints.
+
+use crate::client::{ChatRequest, ChatResponse, LlmClient};
+use crate::events::{AgentEvent, EventSink, TokenUsage};
+use crate::sse::SseParser;
+use crate::streaming_executor::{self, RetryPolicy, TransientError};
+use anyhow::{Result, anyhow, bail};
+use async_trait::async_trait;
+use futures::StreamExt;
+use openrouter_api::models::tool::{FunctionCall, Tool as ApiTool, ToolCall};
+use openrouter_api::types::chat::Message;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+/// The output token limit for the answer itself. A thinking budget is added on top.
+const DEFAULT_MAX_TOKENS: u32 = 8192;
+
+pub struct AnthropicClient {
+    http: reqwest::Client,
+    base_url: String,
+    /// `None` for servers without authentication.
+    api_key: Option<String>,
+    thinking_budget_tokens: Option<u32>,
+    retry_policy: RetryPolicy,
+    /// Thinking blocks of assistant messages, keyed by the id of their first tool call.
+    thinking_blocks: Mutex<HashMap<String, Vec<Value>>>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ContentBlock {
+    Text {
+        text: String,
+    },
+    ToolUse {
+        id: String,
+        name: String,
+        input: Value,
+    },
+    Thinking {
+        thinking: String,
+        signature: String,
+    },
+    RedactedThinking {
+        data: String,
+    },
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct Usage {
+    #[serde(default)]
+    input_tokens: u64,
+    #[serde(default)]
+    output_tokens: u64,
+    #[serde(default)]
+    cache_creation_input_tokens: Option<u64>,
+    #[serde(default)]
+    cache_read_input_tokens: Option<u64>,
+}
+
+impl Usage {
+    fn to_token_usage(&self) -> TokenUsage {
+        let prompt_tokens = self.input_tokens
+            + self.cache_creation_input_tokens.unwrap_or(0)
+            + self.cache_read_input_tokens.unwrap_or(0);
+        TokenUsage {
+            prompt_tokens,
+            completion_tokens: self.output_tokens,
+            total_tokens: prompt_tokens + self.output_tokens,
+            cost: None,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct MessagesResponse {
+    content: Vec<ContentBlock>,
+    usage: Option<Usage>,
+    model: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct MessageStart {
+    usage: Option<Usage>,
+    model: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Delta {
+    TextDelta {
+        text: String,
+    },
+    InputJsonDelta {
+        partial_json: String,
+    },
+    ThinkingDelta {
+        thinking: String,
+    },
+    SignatureDelta {
+        signature: String,
+    },
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Deserialize, Debug)]
+struct ApiError {
+    #[serde(rename = "type")]
+    kind: String,
+    message: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum StreamEvent {
+    MessageStart {
+        message: MessageStart,
+    },
+    ContentBlockStart {
+        index: usize,
+        content_block: ContentBlock,
+    },
+    ContentBlockDelta {
+        index: usize,
+        delta: Delta,
+    },
+    MessageDelta {
+        usage: Option<Usage>,
+    },
+    MessageStop,
+    Error {
+        error: ApiError,
+    },
+    /// `content_block_stop` and `ping` carry nothing we need.
+    #[serde(other)]
+    Other,
+}
+
+impl AnthropicClient {
+    pub fn new(base_url: &str, api_key: Option<String>, http: reqwest::Client) -> Self {
+        Self {
+            http,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key,
+            thinking_budget_tokens: None,
+            retry_policy: RetryPolicy::default(),
+            thinking_blocks: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Enables extended thinking with the given token budget.
+    pub fn with_thinking_budget(mut self, budget_tokens: Option<u32>) -> Self {
+        self.thinking_budget_tokens = budget_tokens;
+        self
+    }
+
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Translates the request to the body of a `/v1/messages` call.
+    fn build_body(&self, request: &ChatRequest, stream: bool) -> Value {
+        let thinking_blocks = self.thinking_blocks.lock().unwrap();
+        let mut system = Vec::new();
+        let mut messages: Vec<Value> = Vec::new();
+
+        for message in &request.messages {
+            match message.role.as_str() {
+                "system" => system.push(json!({ "type": "text", "text": message.content })),
+                "assistant" => {
+                    let tool_calls = message.tool_calls.as_deref().unwrap_or_default();
+                    let mut content = Vec::new();
+                    if let Some(blocks) = tool_calls
+                        .first()
+                        .and_then(|tool_call| thinking_blocks.get(&tool_call.id))
+                    {
+                        content.extend(blocks.iter().cloned());
+                    }
+                    if !message.content.is_empty() {
+                        content.push(json!({ "type": "text", "text": message.content }));
+                    }
+                    for tool_call in tool_calls {
+                        let input =
+                            serde_json::from_str::<Value>(&tool_call.function_call.arguments)
+                                .ok()
+                                .filter(Value::is_object)
+                                .unwrap_or_else(|| json!({}));
+                        content.push(json!({
+                            "type": "tool_use",
+                            "id": tool_call.id,
+                            "name": tool_call.function_call.name,
+                            "input": input,
+                        }));
+                    }
+                    push_message(&mut messages, "assistant", content);
+                }
+                "tool" => push_message(
+                    &mut messages,
+                    "user",
+                    vec![json!({
+                        "type": "tool_result",
+                        "tool_use_id": message.tool_call_id.clone().unwrap_or_default(),
+                        "content": message.content,
+                    })],
+                ),
+                _ => {
+                    if !message.content.is_empty() {
+                        push_message(
+                            &mut messages,
+                            "user",
+                            vec![json!({ "type": "text", "text": message.content })],
+                        );
+                    }
+                }
+            }
+        }
+
+        let mut tools: Vec<Value> = request
+            .tools
+            .iter()
+            .map(|tool| match tool {
+                ApiTool::Function { function } => json!({
+                    "name": function.name,
+                    "description": function.description.clone().unwrap_or_default(),
+                    "input_schema": function.parameters,
+                }),
+            })
+            .collect();
+
+        // Cache breakpoints: everything up to and including these blocks is cached.
+        set_cache_control(system.last_mut());
+        set_cache_control(tools.last_mut());
+        set_cache_control(
+            messages
+                .last_mut()
+                .and_then(|message| message["content"].as_array_mut())
+                .and_then(|content| content.last_mut())
+                .filter(|block| {
+                    block["type"] != "thinking" && block["type"] != "redacted_thinking"
+                }),
+        );
+
+        let max_tokens = DEFAULT_MAX_TOKENS + self.thinking_budget_tokens.unwrap_or(0);
+        let mut body = json!({
+            "model": request.model,
+            "max_tokens": max_tokens,
+            "messages": messages,
+            "stream": stream,
+        });
+        if !system.is_empty() {
+            body["system"] = Value::Array(system);
+        }
+        if !tools.is_empty() {
+            body["tools"] = Value::Array(tools);
+        }
+        if let Some(budget_tokens) = self.thinking_budget_tokens {
+            body["thinking"] = json!({ "type": "enabled", "budget_tokens": budget_tokens });
+        }
+        body
+    }
+
+    async fn send(&self, body: &Value) -> Result<reqwest::Response> {
+        let mut request = self
+            .http
+            .post(format!("{}/messages", self.base_url))
+            .header("anthropic-version", ANTHROPIC_VERSION)
+            .json(body);
+        if let Some(api_key) = &self.api_key {
+            request = request.header("x-api-key", api_key);
+        }
+        let response = request.send().await?;
+
+        if !response.status().is_success() {
+            return Err(streaming_executor::error_from_response(response, "Anthropic API").await);
+        }
+        Ok(response)
+    }
+
+    /// Reads a streamed response, reports the deltas to `events` and collects it.
+    async fn stream_response(&self, body: &Value, events: &dyn EventSink) -> Result<ChatResponse> {
+        let mut stream = self.send(body).await?.bytes_stream();
+
+        let mut parser = SseParser::new();
+        let mut blocks: BTreeMap<usize, ContentBlock> = BTreeMap::new();
+        // The streamed JSON arguments of `tool_use` blocks, with their tool call index.
+        let mut tool_inputs: HashMap<usize, (u32, String)> = HashMap::new();
+        let mut usage = Usage::default();
+        let mut model = None;
+        let mut stopped = false;
+
+        while let Some(chunk) = stream.next().await {
+            for sse_event in parser.push(&chunk?) {
+                let event: StreamEvent = serde_json::from_str(&sse_event.data)
+                    .map_err(|e| anyhow!("Failed to parse stream event: {e}"))?;
+                match event {
+                    StreamEvent::MessageStart { message } => {
+                        if let Some(start_usage) = message.usage {
+                            usage = start_usage;
+                        }
+                        model = message.model;
+                    }
+                    StreamEvent::ContentBlockStart {
+                        index,
+                        content_block,
+                    } => {
+                        if let ContentBlock::ToolUse { id, name, .. } = &content_block {
+                            let tool_index = tool_inputs.len() as u32;
+                            tool_inputs.insert(index, (tool_index, String::new()));
+                            events.emit(AgentEvent::ToolCallDelta {
+                                index: tool_index,
+                                id: Some(id.clone()),
+                                name: Some(name.clone()),
+                                arguments: String::new(),
+                            });
+                        }
+                        blocks.insert(index, content_block);
+                    }
+                    StreamEvent::ContentBlockDelta { index, delta } => {
+                        match (blocks.get_mut(&index), delta) {
+                            (
+                                Some(ContentBlock::Text { text }),
+                                Delta::TextDelta { text: delta },
+                            ) => {
+                                events.emit(AgentEvent::TextDelta {
+                                    text: delta.clone(),
+                                });
+                                text.push_str(&delta);
+                            }
+                            (
+                                Some(ContentBlock::ToolUse { .. }),
+                                Delta::InputJsonDelta { partial_json },
+                            ) => {
+                                if let Some((tool_index, input)) = tool_inputs.get_mut(&index) {
+                                    events.emit(AgentEvent::ToolCallDelta {
+                                        index: *tool_index,
+                                        id: None,
+                                        name: None,
+                                        arguments: partial_json.clone(),
+                                    });
+                                    input.push_str(&partial_json);
+                                }
+                            }
+                            (
+                                Some(ContentBlock::Thinking { thinking, .. }),
+                                Delta::ThinkingDelta { thinking: delta },
+                            ) => {
+                                events.emit(AgentEvent::ReasoningDelta {
+                                    text: delta.clone(),
+                                });
+                                thinking.push_str(&delta);
+                            }
+                            (
+                                Some(ContentBlock::Thinking { signature, .. }),
+                                Delta::SignatureDelta { signature: delta },
+                            ) => signature.push_str(&delta),
+                            _ => {}
+                        }
+                    }
+                    StreamEvent::MessageDelta {
+                        usage: Some(delta_usage),
+                    } => usage.output_tokens = delta_usage.output_tokens,
+                    StreamEvent::MessageDelta { usage: None } | StreamEvent::Other => {}
+                    StreamEvent::MessageStop => stopped = true,
+                    StreamEvent::Error { error } => {
+                        let message =
+                            format!("Anthropic API error ({}): {}", error.kind, error.message);
+                        if matches!(
+                            error.kind.as_str(),
+                            "overloaded_error" | "api_error" | "rate_limit_error"
+                        ) {
+                            return Err(TransientError::new(message).into());
+                        }
+                        bail!(message);
+                    }
+                }
+            }
+        }
+        if !stopped {
+            return Err(TransientError::new("The stream ended unexpectedly.".to_string()).into());
+        }
+
+        for (index, (_, raw_input)) in tool_inputs {
+            if let Some(ContentBlock::ToolUse { input, .. }) = blocks.get_mut(&index) {
+                *input = if raw_input.trim().is_empty() {
+                    json!({})
+                } else {
+                    serde_json::from_str(&raw_input).unwrap_or(Value::String(raw_input))
+                };
+            }
+        }
+
+        let blocks: Vec<ContentBlock> = blocks.into_values().collect();
+        Ok(ChatResponse {
+            reasoning: reasoning(&blocks),
+            message: self.assemble_message(blocks),
+            usage: Some(usage.to_token_usage()),
+            model,
+        })
+    }
+
+    /// Converts the content blocks of a response to an assistant `Message` and
+    /// remembers its thinking blocks for the following requests.
+    fn assemble_message(&self, blocks: Vec<ContentBlock>) -> Option<Message> {
+        let mut content = String::new();
+        let mut tool_calls = Vec::new();
+        let mut thinking = Vec::new();
+
+        for block in blocks {
+            match block {
+                ContentBlock::Text { text } => content.push_str(&text),
+                ContentBlock::ToolUse { id, name, input } => {
+                    let arguments = match input {
+                        // Streamed arguments that are not valid JSON are kept as they
+                        // are, so the error can be reported to the model.
+                        Value::String(raw) => raw,
+                        input => input.to_string(),
+                    };
+                    tool_calls.push(ToolCall {
+                        id,
+                        kind: "function".to_string(),
+                        function_call: FunctionCall { name, arguments },
+                    });
+                }
+                ContentBlock::Thinking {
+                    thinking: text,
+                    signature,
+                } => thinking.push(json!({
+                    "type": "thinking",
+                    "thinking": text,
+                    "signature": signature,
+                })),
+                ContentBlock::RedactedThinking { data } => {
+                    thinking.push(json!({ "type": "redacted_thinking", "data": data }))
+                }
+                ContentBlock::Unknown => {}
+            }
+        }
+
+        // Thinking blocks only have to be sent back while tool calls are resolved.
+        if let Some(first_tool_call) = tool_calls.first().filter(|_| !thinking.is_empty()) {
+            self.thinking_blocks
+                .lock()
+                .unwrap()
+                .insert(first_tool_call.id.clone(), thinking);
+        }
+
+        if content.is_empty() && tool_calls.is_empty() {
+            return None;
+        }
+        Some(Message {
+            role: "assistant".to_string(),
+            content,
+            name: None,
+            tool_calls: if tool_calls.is_empty() {
+                None
+            } else {
+                Some(tool_calls)
+            },
+            tool_call_id: None,
+        })
+    }
+}
+
+/// The text of the thinking blocks, if there are any.
+fn reasoning(blocks: &[ContentBlock]) -> Option<String> {
+    let thinking: Vec<&str> = blocks
+        .iter()
+        .filter_map(|block| match block {
+            ContentBlock::Thinking { thinking, .. } => Some(thinking.as_str()),
+            _ => None,
+        })
+        .collect();
+    Some(thinking.join("\n\n")).filter(|text| !text.trim().is_empty())
+}
+
+/// Appends content blocks to the conversation, merging them into the previous
+/// message if it has the same role.
+fn push_message(messages: &mut Vec<Value>, role: &str, content: Vec<Value>) {
+    if content.is_empty() {
+        return;
+    }
+    let previous = messages
+        .last_mut()
+        .filter(|last| last["role"] == role)
+        .and_then(|last| last["content"].as_array_mut());
+    match previous {
+        Some(existing) => existing.extend(content),
+        None => messages.push(json!({ "role": role, "content": content })),
+    }
+}
+
+fn set_cache_control(block: Option<&mut Value>) {
+    if let Some(block) = block {
+        block["cache_control"] = json!({ "type": "ephemeral" });
+    }
+}
+
+#[async_trait]
+impl LlmClient for AnthropicClient {
+    async fn chat_completion(
+        &self,
+        request: ChatRequest,
+        events: &dyn EventSink,
+    ) -> Result<ChatResponse> {
+        let body = &self.build_body(&request, false);
+        let response: MessagesResponse =
+            streaming_executor::with_retries(&self.retry_policy, events, move || async move {
+                Ok(self.send(body).await?.json().await?)
+            })
+            .await?;
+
+        Ok(ChatResponse {
+            usage: response.usage.as_ref().map(Usage::to_token_usage),
+            reasoning: reasoning(&response.content),
+            message: self.assemble_message(response.content),
+            model: response.model,
+        })
+    }
+
+    async fn chat_completion_stream(
+        &self,
+        request: ChatRequest,
+        events: &dyn EventSink,
+    ) -> Result<ChatResponse> {
+        let body = &self.build_body(&request, true);
+        streaming_executor::with_retries(&self.retry_policy, events, move || async move {
+            self.stream_response(body, events).await
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scripted_client::message;
+
+    #[test]
+    fn test_build_body_merges_tool_results_and_marks_cache_breakpoints() {
+        let client = AnthropicClient::new("http://localhost/v1/", None, reqwest::Client::new());
+        let mut assistant = message("assistant", "");
+        assistant.tool_calls = Some(vec![
+            ToolCall {
+                id: "toolu_1".to_string(),
+                kind: "function".to_string(),
+                function_call: FunctionCall {
+                    name: "list_files".to_string(),
+                    arguments: r#"{"path": "."}"#.to_string(),
+                },
+            },
+            ToolCall {
+                id: "toolu_2".to_string(),
+                kind: "function".to_string(),
+                function_call: FunctionCall {
+                    name: "list_files".to_string(),
+                    arguments: r#"{"path": "src"}"#.to_string(),
+                },
+            },
+        ]);
+        let mut result_1 = message("tool", "a.txt");
+        result_1.tool_call_id = Some("toolu_1".to_string());
+        let mut result_2 = message("tool", "main.rs");
+        result_2.tool_call_id = Some("toolu_2".to_string());
+        let request = ChatRequest {
+            model: "claude-sonnet-4-5".to_string(),
+            messages: vec![
+                message("system", "be brief"),
+                message("user", "list files"),
+                assistant,
+                result_1,
+                result_2,
+            ],
+            tools: Vec::new(),
+        };
+
+        let body = client.build_body(&request, true);
+
+        assert_eq!(body["system"][0]["text"], "be brief");
+        assert_eq!(body["system"][0]["cache_control"]["type"], "ephemeral");
+        let messages = body["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[1]["content"][1]["type"], "tool_use");
+        assert_eq!(messages[1]["content"][1]["input"]["path"], "src");
+        let results = messages[2]["content"].as_array().unwrap();
+        assert_eq!(messages[2]["role"], "user");
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0]["tool_use_id"], "toolu_1");
+        assert!(results[0].get("cache_control").is_none());
+        assert_eq!(results[1]["cache_control"]["type"], "ephemeral");
+    }
+}
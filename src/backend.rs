@@ -1,34 +1,179 @@
-use serde::{Deserialize, Serialize};
+//! # Backends
+//!
+//! A backend is an API endpoint together with the way to authenticate to it. The
+//! built-in backends (`openrouter`, `ollama`, `openai`, `anthropic`) can be replaced
+//! and new ones added in `[backends.<name>]`, e.g. for vLLM, LM Studio, a llama.cpp
+//! server or a company gateway:
+//!
+//! ```toml
+//! [backends.gateway]
+//! base_url = "https://llm.example.com/v1/"
+//! api_key_command = "pass show llm-gateway"
+//! headers = { "X-Title" = "platform" }
+//! ```
 
-pub struct BackendConfig {
-    pub base_url: String,
-    pub api_key_env_var: Option<&'static str>,
-}
+use anyhow::{Context, Result, anyhow};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::process::Command;
+use std::time::Duration;
 
-#[derive(clap::ValueEnum, Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+/// The protocol a backend speaks.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
-pub enum Backend {
+pub enum Api {
+    /// The OpenAI `/chat/completions` API, spoken by most servers.
     #[default]
-    Openrouter,
-    Ollama,
     Openai,
+    /// The OpenAI API with OpenRouter's routing to fallback models.
+    Openrouter,
+    /// The Anthropic `/messages` API.
+    Anthropic,
 }
 
-impl Backend {
-    pub fn config(&self) -> BackendConfig {
-        match self {
-            Backend::Openrouter => BackendConfig {
-                base_url: "https://openrouter.ai/api/v1/".to_string(),
-                api_key_env_var: Some("OPENROUTER_API_KEY"),
-            },
-            Backend::Ollama => BackendConfig {
-                base_url: "http://localhost:11434/v1/".to_string(),
-                api_key_env_var: None,
-            },
-            Backend::Openai => BackendConfig {
-                base_url: "https://api.openai.com/v1/".to_string(),
-                api_key_env_var: Some("OPENAI_API_KEY"),
-            },
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct BackendConfig {
+    #[serde(default)]
+    pub api: Api,
+    pub base_url: String,
+    /// The environment variable that contains the API key.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key_env: Option<String>,
+    /// A shell command that prints the API key, e.g. `pass show openrouter`. Used if
+    /// `api_key_env` is not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key_command: Option<String>,
+    /// Headers sent with every request, e.g. OpenRouter's `HTTP-Referer` and `X-Title`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    /// The URL of a proxy for all requests.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<String>,
+}
+
+impl BackendConfig {
+    fn builtin(api: Api, base_url: &str, api_key_env: Option<&str>) -> Self {
+        Self {
+            api,
+            base_url: base_url.to_string(),
+            api_key_env: api_key_env.map(str::to_string),
+            api_key_command: None,
+            headers: BTreeMap::new(),
+            proxy: None,
+        }
+    }
+
+    /// Reads the API key. Returns `None` if the backend needs none, like a local server.
+    pub fn api_key(&self) -> Result<Option<String>> {
+        if let Some(env_var) = &self.api_key_env {
+            return match std::env::var(env_var) {
+                Ok(key) => Ok(Some(key)),
+                Err(_) => Err(anyhow!("environment variable {env_var} not set")),
+            };
         }
+        let Some(command) = &self.api_key_command else {
+            return Ok(None);
+        };
+        let output = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .output()
+            .with_context(|| format!("Failed to run the api_key_command '{command}'"))?;
+        if !output.status.success() {
+            return Err(anyhow!(
+                "The api_key_command '{command}' failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        // Password managers like `pass` print further lines after the key.
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let key = stdout.lines().next().unwrap_or("").trim();
+        if key.is_empty() {
+            return Err(anyhow!("The api_key_command '{command}' printed no key"));
+        }
+        Ok(Some(key.to_string()))
+    }
+
+    /// Builds an HTTP client that sends the configured headers through the proxy.
+    pub fn http_client(&self, timeout: Duration) -> Result<reqwest::Client> {
+        let mut headers = HeaderMap::new();
+        for (name, value) in &self.headers {
+            let name = HeaderName::from_bytes(name.as_bytes())
+                .with_context(|| format!("Invalid header name '{name}'"))?;
+            let value = HeaderValue::from_str(value)
+                .with_context(|| format!("Invalid value of the header '{name}'"))?;
+            headers.insert(name, value);
+        }
+        let mut builder = reqwest::Client::builder()
+            .timeout(timeout)
+            .default_headers(headers);
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(
+                reqwest::Proxy::all(proxy).with_context(|| format!("Invalid proxy '{proxy}'"))?,
+            );
+        }
+        Ok(builder.build()?)
+    }
+}
+
+/// The backends that are available without configuration.
+pub fn builtin_backends() -> BTreeMap<String, BackendConfig> {
+    BTreeMap::from([
+        (
+            "openrouter".to_string(),
+            BackendConfig::builtin(
+                Api::Openrouter,
+                "https://openrouter.ai/api/v1/",
+                Some("OPENROUTER_API_KEY"),
+            ),
+        ),
+        (
+            "ollama".to_string(),
+            BackendConfig::builtin(Api::Openai, "http://localhost:11434/v1/", None),
+        ),
+        (
+            "openai".to_string(),
+            BackendConfig::builtin(
+                Api::Openai,
+                "https://api.openai.com/v1/",
+                Some("OPENAI_API_KEY"),
+            ),
+        ),
+        (
+            "anthropic".to_string(),
+            BackendConfig::builtin(
+                Api::Anthropic,
+                "https://api.anthropic.com/v1/",
+                Some("ANTHROPIC_API_KEY"),
+            ),
+        ),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_api_key_from_command_and_none_for_local_servers() {
+        let backends = builtin_backends();
+        assert_eq!(backends["ollama"].api_key().unwrap(), None);
+
+        let backend: BackendConfig = toml::from_str(
+            r#"
+            base_url = "http://localhost:8000/v1/"
+            api_key_command = "printf 'secret\nlogin: me\n'"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(backend.api, Api::Openai);
+        assert_eq!(backend.api_key().unwrap(), Some("secret".to_string()));
+
+        let failing = BackendConfig {
+            api_key_command: Some("false".to_string()),
+            ..backend
+        };
+        assert!(failing.api_key().is_err());
     }
 }
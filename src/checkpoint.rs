@@ -0,0 +1,228 @@
+//! # Checkpoints
+//!
+//! Before each turn, the agent takes a checkpoint. The first time `edit_files` or
+//! `create_files` writes a file during the turn, the file's previous content and its
+//! cached `FileState` are saved in the checkpoint, as well as the directories that do not
+//! exist yet. Undoing the turn writes the saved contents back, removes the files and
+//! empty directories the turn created and restores the message history.
+//!
+//! Only the latest `undo_depth` checkpoints are kept, since each holds a copy of the
+//! history.
+//!
+//! The cached `FileState`s are restored as well, so the LIDs in the restored history
+//! are valid again.
+
+use crate::file_state::FileState;
+use anyhow::Result;
+use openrouter_api::types::chat::Message;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The state before a turn.
+#[derive(Debug, Clone)]
+pub struct Checkpoint {
+    /// The message history before the turn. It is a copy, because a turn may also
+    /// change earlier messages, e.g. when it collapses stale file views.
+    pub messages: Vec<Message>,
+    /// The files written during the turn, by absolute path.
+    files: BTreeMap<PathBuf, FileSnapshot>,
+    /// The directories created during the turn, by absolute path.
+    created_dirs: BTreeSet<PathBuf>,
+    /// The commit of the turn, if `git_auto_commit` committed it.
+    pub commit: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+struct FileSnapshot {
+    /// The content before the turn, `None` if the turn created the file.
+    content: Option<String>,
+    /// The cached state before the turn, if the file was open.
+    state: Option<FileState>,
+}
+
+/// What undoing a turn changed.
+#[derive(Debug, Default)]
+pub struct RestoredCheckpoint {
+    /// The message history before the turn.
+    pub messages: Vec<Message>,
+    /// Files whose content was written back.
+    pub restored_files: Vec<PathBuf>,
+    /// Files that the turn created and that were removed.
+    pub removed_files: Vec<PathBuf>,
+    /// The commit of the turn, if it was reverted instead.
+    pub reverted_commit: Option<String>,
+}
+
+impl Checkpoint {
+    pub fn new(messages: Vec<Message>) -> Self {
+        Self {
+            messages,
+            files: BTreeMap::new(),
+            created_dirs: BTreeSet::new(),
+            commit: None,
+        }
+    }
+
+    /// The files written during the turn.
+    pub fn written_files(&self) -> Vec<PathBuf> {
+        self.files.keys().cloned().collect()
+    }
+
+    /// Saves the current content and cached state of `path`, unless it was already saved
+    /// during this turn, and its parent directories that do not exist yet. Must be called
+    /// before the file is written.
+    pub fn snapshot(&mut self, path: &Path, open_files: &HashMap<String, FileState>) -> Result<()> {
+        // Files that do not exist yet cannot be canonicalized.
+        let path = fs::canonicalize(path).or_else(|_| std::path::absolute(path))?;
+        if self.files.contains_key(&path) {
+            return Ok(());
+        }
+        let content = if path.is_file() {
+            Some(fs::read_to_string(&path)?)
+        } else {
+            None
+        };
+        let state = open_files.get(path.to_string_lossy().as_ref()).cloned();
+        let missing_dirs = path.ancestors().skip(1).take_while(|dir| !dir.exists());
+        self.created_dirs
+            .extend(missing_dirs.map(Path::to_path_buf));
+        self.files.insert(path, FileSnapshot { content, state });
+        Ok(())
+    }
+
+    /// Writes the saved contents back, removes created files and restores the cached
+    /// states in `open_files`.
+    pub fn restore(
+        self,
+        open_files: &mut HashMap<String, FileState>,
+    ) -> Result<RestoredCheckpoint> {
+        self.restore_files(open_files, true)
+    }
+
+    /// Restores the cached states of the files whose content on disk is the saved one
+    /// again, e.g. after the commit of the turn was reverted. The other files are
+    /// reloaded when they are read next.
+    pub fn restore_cached_states(
+        self,
+        open_files: &mut HashMap<String, FileState>,
+    ) -> Result<RestoredCheckpoint> {
+        self.restore_files(open_files, false)
+    }
+
+    fn restore_files(
+        self,
+        open_files: &mut HashMap<String, FileState>,
+        write_files: bool,
+    ) -> Result<RestoredCheckpoint> {
+        let mut restored = RestoredCheckpoint {
+            messages: self.messages,
+            ..Default::default()
+        };
+        for (path, snapshot) in self.files {
+            let key = path.to_string_lossy().to_string();
+            let on_disk = if write_files {
+                match &snapshot.content {
+                    Some(content) => fs::write(&path, content)?,
+                    None if path.exists() => fs::remove_file(&path)?,
+                    None => {}
+                }
+                true
+            } else {
+                fs::read_to_string(&path).ok() == snapshot.content
+            };
+            match snapshot.state.filter(|_| on_disk) {
+                Some(state) => open_files.insert(key, state),
+                None => open_files.remove(&key),
+            };
+            match snapshot.content {
+                Some(_) => restored.restored_files.push(path),
+                None => restored.removed_files.push(path),
+            }
+        }
+        // The deepest first. Directories that contain other files are kept.
+        for dir in self.created_dirs.iter().rev() {
+            fs::remove_dir(dir).ok();
+        }
+        Ok(restored)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::Builder;
+
+    #[test]
+    fn test_restore_writes_back_edits_and_removes_created_files() {
+        let tmp_dir = Builder::new().prefix("test-checkpoint-").tempdir().unwrap();
+        let edited = tmp_dir.path().join("edited.txt");
+        let created = tmp_dir.path().join("created.txt");
+        fs::write(&edited, "before\n").unwrap();
+        let mut open_files = HashMap::new();
+
+        let mut checkpoint = Checkpoint::new(Vec::new());
+        checkpoint.snapshot(&edited, &open_files).unwrap();
+        fs::write(&edited, "after\n").unwrap();
+        checkpoint.snapshot(&created, &open_files).unwrap();
+        fs::write(&created, "new\n").unwrap();
+        // Only the first snapshot of a file counts.
+        checkpoint.snapshot(&edited, &open_files).unwrap();
+        let key = fs::canonicalize(&created).unwrap();
+        open_files.insert(
+            key.to_string_lossy().to_string(),
+            FileState::new(key, "new\n"),
+        );
+
+        let restored = checkpoint.restore(&mut open_files).unwrap();
+
+        assert_eq!(fs::read_to_string(&edited).unwrap(), "before\n");
+        assert!(!created.exists());
+        assert_eq!(restored.restored_files.len(), 1);
+        assert_eq!(restored.removed_files.len(), 1);
+        assert!(open_files.is_empty());
+    }
+
+    #[test]
+    fn test_restore_removes_created_directories_if_empty() {
+        let tmp_dir = Builder::new().prefix("test-checkpoint-").tempdir().unwrap();
+        let created = tmp_dir.path().join("a/b/created.txt");
+        let kept = tmp_dir.path().join("a/kept.txt");
+
+        let mut checkpoint = Checkpoint::new(Vec::new());
+        checkpoint.snapshot(&created, &HashMap::new()).unwrap();
+        fs::create_dir_all(created.parent().unwrap()).unwrap();
+        fs::write(&created, "new\n").unwrap();
+        // Written by the user, not by the turn.
+        fs::write(&kept, "mine\n").unwrap();
+
+        checkpoint.restore(&mut HashMap::new()).unwrap();
+
+        assert!(!tmp_dir.path().join("a/b").exists());
+        assert!(kept.exists());
+    }
+
+    #[test]
+    fn test_only_the_latest_checkpoints_are_kept() {
+        let mut manager = crate::file_state_manager::FileStateManager::new();
+        for turn in 0..5 {
+            manager.begin_checkpoint(
+                vec![Message {
+                    role: "user".to_string(),
+                    content: turn.to_string(),
+                    name: None,
+                    tool_calls: None,
+                    tool_call_id: None,
+                }],
+                3,
+            );
+        }
+
+        let turns: Vec<&str> = manager
+            .checkpoints
+            .iter()
+            .map(|checkpoint| checkpoint.messages[0].content.as_str())
+            .collect();
+        assert_eq!(turns, ["2", "3", "4"]);
+    }
+}
@@ -1,13 +1,55 @@
 use alors::config::ConfigLayer;
-use clap::Parser;
+use clap::{Parser, ValueEnum};
+use std::path::PathBuf;
+
+/// How the result of a non-interactive run is written to stdout.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// The final assistant message as plain text.
+    #[default]
+    Text,
+    /// A single JSON object describing the whole turn.
+    Json,
+    /// Every agent event as one JSON object per line, followed by the result object.
+    StreamJson,
+}
 
 /// A command-line interface for the `alors` agent.
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 pub struct Cli {
-    /// The prompt for the agent
+    /// The prompt for the agent, or `-` to read it from stdin
     pub prompt: Option<String>,
 
+    /// Read the prompt from a file.
+    #[arg(long, value_name = "PATH", conflicts_with = "prompt")]
+    pub prompt_file: Option<PathBuf>,
+
+    /// Apply the settings of `[profiles.<NAME>]` in the config file.
+    #[arg(long, value_name = "NAME")]
+    pub profile: Option<String>,
+
+    /// Resume the session with the given id.
+    #[arg(long, value_name = "ID", conflicts_with = "continue_session")]
+    pub resume: Option<String>,
+
+    /// Resume the latest session started in the current directory.
+    #[arg(long = "continue", short = 'c')]
+    pub continue_session: bool,
+
+    /// List stored sessions and exit.
+    #[arg(long)]
+    pub list_sessions: bool,
+
+    /// Run a single turn without user interaction and exit.
+    /// Tool calls that are not safe for automatic execution are denied.
+    #[arg(long, short = 'p', visible_alias = "non-interactive")]
+    pub print: bool,
+
+    /// The output format of a non-interactive run.
+    #[arg(long, value_enum, default_value_t, requires = "print")]
+    pub output_format: OutputFormat,
+
     #[command(flatten)]
     pub overrides: ConfigLayer,
 }
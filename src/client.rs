@@ -1,21 +1,231 @@
+//! # LLM Clients
+//!
+//! The agent talks to language models through the `LlmClient` trait, so the backend
+//! can be swapped without touching the agent loop. `initialize_client` builds the
+//! client for the API of the configured backend.
+
+use crate::anthropic::AnthropicClient;
+use crate::backend::Api;
 use crate::config::Config;
-use anyhow::{Result, bail};
-use openrouter_api::{OpenRouterClient, Ready};
+use crate::events::{EventSink, TokenUsage};
+use crate::fallback::FallbackClient;
+use crate::reasoning::ReasoningEffort;
+use crate::streaming_executor::{self, RetryPolicy};
+use anyhow::Result;
+use async_trait::async_trait;
+use openrouter_api::models::tool::Tool as ApiTool;
+use openrouter_api::types::chat::{ChatCompletionRequest, Message};
+use serde_json::{Value, json};
+use std::sync::Arc;
 use std::time::Duration;
 
-pub fn initialize_client(config: &Config) -> Result<OpenRouterClient<Ready>> {
-    let api_key = if let Some(env_var) = config.backend.config().api_key_env_var {
-        match std::env::var(env_var) {
-            Ok(val) => val,
-            Err(_) => bail!("environment variable {} not set", env_var),
+/// A backend-independent chat request.
+#[derive(Debug, Clone)]
+pub struct ChatRequest {
+    pub model: String,
+    pub messages: Vec<Message>,
+    pub tools: Vec<ApiTool>,
+}
+
+/// The answer of a language model to a `ChatRequest`.
+#[derive(Debug, Clone, Default)]
+pub struct ChatResponse {
+    /// The assistant message, or `None` if the model returned nothing.
+    pub message: Option<Message>,
+    /// The token usage reported by the API, if any.
+    pub usage: Option<TokenUsage>,
+    /// The reasoning of the model, separated from the message content.
+    pub reasoning: Option<String>,
+    /// The model that answered, if known. It differs from the requested model if a
+    /// fallback model answered.
+    pub model: Option<String>,
+}
+
+/// A language model backend.
+///
+/// Both methods return the complete response. The streaming variant additionally
+/// reports the response as it arrives, as `AgentEvent::TextDelta`,
+/// `AgentEvent::ReasoningDelta` and `AgentEvent::ToolCallDelta`.
+#[async_trait]
+pub trait LlmClient: Send + Sync {
+    async fn chat_completion(
+        &self,
+        request: ChatRequest,
+        events: &dyn EventSink,
+    ) -> Result<ChatResponse>;
+
+    async fn chat_completion_stream(
+        &self,
+        request: ChatRequest,
+        events: &dyn EventSink,
+    ) -> Result<ChatResponse>;
+}
+
+/// The `LlmClient` for all backends that speak the OpenAI `/chat/completions` API.
+/// Requests and responses have the format of the `openrouter_api` types, extended by
+/// OpenRouter's `reasoning`.
+pub struct ChatCompletionsClient {
+    http: reqwest::Client,
+    base_url: String,
+    api_key: Option<String>,
+    api: Api,
+    /// Sent as `models` for OpenRouter's routing. Empty for other backends.
+    fallback_models: Vec<String>,
+    reasoning_effort: Option<ReasoningEffort>,
+    retry_policy: RetryPolicy,
+}
+
+impl ChatCompletionsClient {
+    pub fn new(api: Api, base_url: &str, api_key: Option<String>, http: reqwest::Client) -> Self {
+        Self {
+            http,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key,
+            api,
+            fallback_models: Vec::new(),
+            reasoning_effort: None,
+            retry_policy: RetryPolicy::default(),
+        }
+    }
+
+    pub fn with_fallback_models(mut self, fallback_models: Vec<String>) -> Self {
+        self.fallback_models = fallback_models;
+        self
+    }
+
+    /// Sent as `reasoning.effort` to OpenRouter and as `reasoning_effort` to other
+    /// backends.
+    pub fn with_reasoning_effort(mut self, reasoning_effort: Option<ReasoningEffort>) -> Self {
+        self.reasoning_effort = reasoning_effort;
+        self
+    }
+
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    fn to_openrouter(&self, request: ChatRequest, stream: bool) -> ChatCompletionRequest {
+        let models = if self.fallback_models.is_empty() {
+            None
+        } else {
+            Some(
+                std::iter::once(request.model.clone())
+                    .chain(self.fallback_models.iter().cloned())
+                    .collect(),
+            )
+        };
+        ChatCompletionRequest {
+            model: request.model,
+            messages: request.messages,
+            tools: Some(request.tools).filter(|tools| !tools.is_empty()),
+            stream: Some(stream),
+            response_format: None,
+            provider: None,
+            models,
+            transforms: None,
+        }
+    }
+
+    /// The body of a `/chat/completions` call.
+    fn build_body(&self, request: &ChatRequest, stream: bool) -> Result<Value> {
+        let mut body = serde_json::to_value(self.to_openrouter(request.clone(), stream))?;
+        if let Some(effort) = self.reasoning_effort {
+            if self.api == Api::Openrouter {
+                body["reasoning"] = json!({ "effort": effort });
+            } else {
+                body["reasoning_effort"] = json!(effort);
+            }
+        }
+        Ok(body)
+    }
+
+    async fn send(&self, body: &Value) -> Result<reqwest::Response> {
+        let mut request = self
+            .http
+            .post(format!("{}/chat/completions", self.base_url))
+            .json(body);
+        if let Some(api_key) = &self.api_key {
+            request = request.bearer_auth(api_key);
+        }
+        let response = request.send().await?;
+
+        if !response.status().is_success() {
+            return Err(
+                streaming_executor::error_from_response(response, "Chat completions API").await,
+            );
         }
+        Ok(response)
+    }
+}
+
+#[async_trait]
+impl LlmClient for ChatCompletionsClient {
+    async fn chat_completion(
+        &self,
+        request: ChatRequest,
+        events: &dyn EventSink,
+    ) -> Result<ChatResponse> {
+        let body = &self.build_body(&request, false)?;
+        streaming_executor::with_retries(&self.retry_policy, events, move || async move {
+            streaming_executor::collect_response_non_streaming(self.send(body).await?).await
+        })
+        .await
+    }
+
+    async fn chat_completion_stream(
+        &self,
+        request: ChatRequest,
+        events: &dyn EventSink,
+    ) -> Result<ChatResponse> {
+        let body = &self.build_body(&request, true)?;
+        streaming_executor::with_retries(&self.retry_policy, events, move || async move {
+            streaming_executor::stream_and_collect_response(self.send(body).await?, events).await
+        })
+        .await
+    }
+}
+
+pub fn initialize_client(config: &Config) -> Result<Arc<dyn LlmClient>> {
+    let backend = config.backend_config()?;
+    let timeout = Duration::from_secs(config.timeout_seconds);
+    let retry_policy = RetryPolicy::from_config(config);
+
+    let client: Arc<dyn LlmClient> = if backend.api == Api::Anthropic {
+        let client = AnthropicClient::new(
+            &backend.base_url,
+            backend.api_key()?,
+            backend.http_client(timeout)?,
+        )
+        .with_thinking_budget(
+            config.thinking_budget_tokens.or(config
+                .reasoning_effort
+                .map(ReasoningEffort::thinking_budget_tokens)),
+        )
+        .with_retry_policy(retry_policy);
+        Arc::new(client)
     } else {
-        // TODO: only call .with_api_key if let Some(config.backend.api_key_env_var())
-        "sk-or-v1-0000000000000000000000000000000000000000000000000000000000000000".to_string()
+        let client = ChatCompletionsClient::new(
+            backend.api,
+            &backend.base_url,
+            backend.api_key()?,
+            backend.http_client(timeout)?,
+        )
+        .with_fallback_models(if backend.api == Api::Openrouter {
+            config.fallback_models.clone()
+        } else {
+            Vec::new()
+        })
+        .with_reasoning_effort(config.reasoning_effort)
+        .with_retry_policy(retry_policy);
+        Arc::new(client)
     };
-    let client = OpenRouterClient::new()
-        .with_base_url(&config.base_url)?
-        .with_timeout(Duration::from_secs(config.timeout_seconds))
-        .with_api_key(api_key)?;
-    Ok(client)
+
+    if config.fallback_models.is_empty() {
+        return Ok(client);
+    }
+    Ok(Arc::new(FallbackClient::new(
+        client,
+        config.fallback_models.clone(),
+    )))
 }
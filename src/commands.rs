@@ -0,0 +1,100 @@
+//! # REPL Commands
+//!
+//! Lines starting with `/` are commands of the interactive REPL. They are handled
+//! locally by `ui::App` and are never sent to the LLM.
+
+use anyhow::{Result, anyhow};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// Starts a new session with only the system prompt.
+    Clear,
+    /// Undoes the file changes and messages of the last turn.
+    Undo,
+    /// Switches to the given model, or shows the current one.
+    Model(Option<String>),
+    /// Saves the session and shows where it is stored.
+    Save,
+    /// Loads the session with the given id, or lists the stored sessions.
+    Load(Option<String>),
+    /// Lists the files the agent has read, with their hashes.
+    Files,
+    /// Shows the size of the history and the token usage.
+    Tokens,
+    /// Shows the merged configuration.
+    Config,
+    Help,
+}
+
+/// The commands with their arguments and descriptions, as shown by `/help`.
+pub const HELP: &[(&str, &str)] = &[
+    ("/clear", "Clear the history and start a new session"),
+    (
+        "/undo",
+        "Undo the file changes and messages of the last turn",
+    ),
+    ("/model [id]", "Switch the model, or show the current one"),
+    ("/save", "Save the session and show its id"),
+    ("/load [id]", "Load a session, or list the stored sessions"),
+    (
+        "/files",
+        "List the files the agent has read, with their hashes",
+    ),
+    (
+        "/tokens",
+        "Show the history size and token usage (alias: /cost)",
+    ),
+    ("/config", "Show the merged configuration"),
+    ("/help", "Show this list"),
+];
+
+impl Command {
+    /// Parses a line of input. Returns `None` if the line is not a command.
+    pub fn parse(input: &str) -> Option<Result<Self>> {
+        let input = input.trim().strip_prefix('/')?;
+        let (name, argument) = match input.split_once(char::is_whitespace) {
+            Some((name, argument)) => (name, Some(argument.trim().to_string())),
+            None => (input, None),
+        };
+        // An absolute path like `/etc/hosts` starts a prompt, not a command.
+        if name.contains('/') {
+            return None;
+        }
+        let argument = argument.filter(|argument| !argument.is_empty());
+        let command = match name {
+            "clear" => Ok(Self::Clear),
+            "undo" => Ok(Self::Undo),
+            "model" => Ok(Self::Model(argument)),
+            "save" => Ok(Self::Save),
+            "load" => Ok(Self::Load(argument)),
+            "files" => Ok(Self::Files),
+            "tokens" | "cost" => Ok(Self::Tokens),
+            "config" => Ok(Self::Config),
+            "help" => Ok(Self::Help),
+            _ => Err(anyhow!("Unknown command '/{name}'. Type /help for a list.")),
+        };
+        Some(command)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_commands_and_arguments() {
+        assert!(Command::parse("fix the bug").is_none());
+        assert!(Command::parse("/etc/hosts is empty, why?").is_none());
+        assert_eq!(Command::parse("/clear").unwrap().unwrap(), Command::Clear);
+        assert_eq!(
+            Command::parse(" /model  openai/gpt-4.1 ").unwrap().unwrap(),
+            Command::Model(Some("openai/gpt-4.1".to_string()))
+        );
+        assert_eq!(
+            Command::parse("/model").unwrap().unwrap(),
+            Command::Model(None)
+        );
+        assert_eq!(Command::parse("/cost").unwrap().unwrap(), Command::Tokens);
+        assert!(Command::parse("/nope").unwrap().is_err());
+    }
+}
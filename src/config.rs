@@ -1,7 +1,12 @@
-use crate::backend::Backend;
-use anyhow::Result;
+use crate::backend::{self, BackendConfig};
+use crate::context::CompactionStrategy;
+use crate::project_config::{self, TrustPrompt};
+use crate::reasoning::{ReasoningEffort, ReasoningHistory};
+use crate::usage::ModelPricing;
+use anyhow::{Context, Result, anyhow};
 use clap::Args;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
 
 const DEFAULT_SYSTEM_PROMPT: &str = "You are an AI coding assistent.
@@ -41,12 +46,13 @@ To search for code, use the ripgrep `rg -n` command.";
 
 /// Represents a layer of configuration, either from a file or from the command line.
 /// All fields are optional.
-#[derive(Args, Deserialize, Debug, Default)]
+#[derive(Args, Deserialize, Serialize, Debug, Default, Clone)]
 #[serde(default)]
 pub struct ConfigLayer {
-    /// The backend to use.
-    #[arg(long, value_enum)]
-    pub backend: Option<Backend>,
+    /// The backend to use: `openrouter`, `ollama`, `openai`, `anthropic` or a backend
+    /// defined in `[backends.<name>]`.
+    #[arg(long)]
+    pub backend: Option<String>,
 
     /// The model to use for the agent.
     #[arg(long)]
@@ -70,14 +76,17 @@ pub struct ConfigLayer {
 
     /// Command prefixes that the agent is allowed to execute.
     #[arg(long, value_delimiter = ',')]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
     pub allowed_command_prefixes: Vec<String>,
 
     /// Paths to ignore when listing or reading files.
     #[arg(long, value_delimiter = ',')]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
     pub ignored_paths: Vec<String>,
 
     /// Paths that the agent is allowed to access.
     #[arg(long, value_delimiter = ',')]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
     pub accessible_paths: Vec<String>,
 
     /// Enable or disable the terminal bell.
@@ -100,15 +109,103 @@ pub struct ConfigLayer {
     #[arg(long)]
     pub print_messages: Option<bool>,
 
-    /// The base URL for the API client.
+    /// The base URL for the API client. Overrides the base URL of the backend.
     #[arg(long)]
     pub base_url: Option<String>,
+
+    /// Token budget for extended thinking (Anthropic backend only).
+    #[arg(long)]
+    pub thinking_budget_tokens: Option<u32>,
+
+    /// The estimated number of tokens the message history may use before it is compacted.
+    #[arg(long)]
+    pub context_budget_tokens: Option<u64>,
+
+    /// How the message history is compacted when it approaches the context budget.
+    #[arg(long, value_enum)]
+    pub compaction: Option<CompactionStrategy>,
+
+    /// How much reasoning models should think before answering.
+    #[arg(long, value_enum)]
+    pub reasoning_effort: Option<ReasoningEffort>,
+
+    /// Which reasoning of the model is sent back to it with the history.
+    #[arg(long, value_enum)]
+    pub reasoning_history: Option<ReasoningHistory>,
+
+    /// Models to fail over to, in order, if the model fails or returns an empty response.
+    #[arg(long, value_delimiter = ',')]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub fallback_models: Vec<String>,
+
+    /// Enables the architect/editor mode: this model plans each change with read-only
+    /// tools, then `editor_model` implements the plan.
+    #[arg(long)]
+    pub architect_model: Option<String>,
+
+    /// The model that implements the architect's plans. Defaults to `model`.
+    #[arg(long)]
+    pub editor_model: Option<String>,
+
+    /// The model of sub-agents started with the `delegate_task` tool. Defaults to `model`.
+    #[arg(long)]
+    pub subagent_model: Option<String>,
+
+    /// Models without native tool calling. Their tools are described in the system
+    /// prompt and their tool calls are parsed from the answer text.
+    #[arg(long, value_delimiter = ',')]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub text_tool_call_models: Vec<String>,
+
+    /// How often a request is retried after a rate limit, a server error or a dropped
+    /// connection.
+    #[arg(long)]
+    pub max_retries: Option<u32>,
+
+    /// The delay before the first retry in milliseconds. It doubles with every retry.
+    #[arg(long)]
+    pub retry_initial_delay_ms: Option<u64>,
+
+    /// The maximum delay between retries in milliseconds, also for `Retry-After`.
+    #[arg(long)]
+    pub retry_max_delay_ms: Option<u64>,
+
+    /// Commit the files each turn wrote to git. `/undo` then reverts the commit.
+    #[arg(long)]
+    pub git_auto_commit: Option<bool>,
+
+    /// How many turns `/undo` can undo. Each one keeps a copy of the history.
+    #[arg(long)]
+    pub undo_depth: Option<usize>,
+
+    /// Prices of models in USD per million tokens, used when the API does not report
+    /// the cost. Only available in the configuration file.
+    #[arg(skip)]
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub pricing: BTreeMap<String, ModelPricing>,
+
+    /// Backends in addition to or replacing the built-in ones, e.g. `[backends.vllm]`.
+    /// Only available in the configuration file.
+    #[arg(skip)]
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub backends: BTreeMap<String, BackendConfig>,
+
+    /// The profile that is applied if none is selected with `--profile`.
+    /// Only available in the configuration file.
+    #[arg(skip)]
+    pub default_profile: Option<String>,
+
+    /// Named sets of settings that are applied together, e.g. `[profiles.local]`.
+    /// Only available in the configuration file.
+    #[arg(skip)]
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub profiles: BTreeMap<String, ConfigLayer>,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
 #[serde(default)]
 pub struct Config {
-    pub backend: Backend,
+    pub backend: String,
     pub model: String,
     pub system_prompt: Option<String>,
     pub timeout_seconds: u64,
@@ -122,7 +219,27 @@ pub struct Config {
     pub debug_tool_calls: bool,
     pub auto_execute: bool,
     pub print_messages: bool,
-    pub base_url: String,
+    pub base_url: Option<String>,
+    pub thinking_budget_tokens: Option<u32>,
+    pub context_budget_tokens: u64,
+    pub compaction: CompactionStrategy,
+    pub reasoning_effort: Option<ReasoningEffort>,
+    pub reasoning_history: ReasoningHistory,
+    pub fallback_models: Vec<String>,
+    pub architect_model: Option<String>,
+    pub editor_model: Option<String>,
+    pub subagent_model: Option<String>,
+    pub text_tool_call_models: Vec<String>,
+    pub max_retries: u32,
+    pub retry_initial_delay_ms: u64,
+    pub retry_max_delay_ms: u64,
+    pub git_auto_commit: bool,
+    pub undo_depth: usize,
+    pub default_profile: Option<String>,
+    pub pricing: BTreeMap<String, ModelPricing>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub backends: BTreeMap<String, BackendConfig>,
+    pub profiles: BTreeMap<String, ConfigLayer>,
 }
 impl Config {
     /// Merges a configuration layer into the current configuration.
@@ -130,9 +247,9 @@ impl Config {
     pub fn merge(&mut self, layer: &ConfigLayer) {
         if let Some(backend) = &layer.backend {
             self.backend = backend.clone();
-            // Only update base_url if it wasn't explicitly provided in the same layer.
+            // Use the backend's base_url, unless one is provided in the same layer.
             if layer.base_url.is_none() {
-                self.base_url = self.backend.config().base_url.to_string();
+                self.base_url = None;
             }
         }
 
@@ -181,16 +298,111 @@ impl Config {
             self.print_messages = print_messages;
         }
         if let Some(base_url) = &layer.base_url {
-            self.base_url = base_url.clone();
+            self.base_url = Some(base_url.clone());
+        }
+        if let Some(thinking_budget_tokens) = layer.thinking_budget_tokens {
+            self.thinking_budget_tokens = Some(thinking_budget_tokens);
+        }
+        if let Some(context_budget_tokens) = layer.context_budget_tokens {
+            self.context_budget_tokens = context_budget_tokens;
+        }
+        if let Some(compaction) = layer.compaction {
+            self.compaction = compaction;
+        }
+        if let Some(reasoning_effort) = layer.reasoning_effort {
+            self.reasoning_effort = Some(reasoning_effort);
+        }
+        if let Some(reasoning_history) = layer.reasoning_history {
+            self.reasoning_history = reasoning_history;
+        }
+        if !layer.fallback_models.is_empty() {
+            self.fallback_models = layer.fallback_models.clone();
         }
+        if let Some(architect_model) = &layer.architect_model {
+            self.architect_model = Some(architect_model.clone());
+        }
+        if let Some(editor_model) = &layer.editor_model {
+            self.editor_model = Some(editor_model.clone());
+        }
+        if let Some(subagent_model) = &layer.subagent_model {
+            self.subagent_model = Some(subagent_model.clone());
+        }
+        if !layer.text_tool_call_models.is_empty() {
+            self.text_tool_call_models = layer.text_tool_call_models.clone();
+        }
+        if let Some(max_retries) = layer.max_retries {
+            self.max_retries = max_retries;
+        }
+        if let Some(retry_initial_delay_ms) = layer.retry_initial_delay_ms {
+            self.retry_initial_delay_ms = retry_initial_delay_ms;
+        }
+        if let Some(retry_max_delay_ms) = layer.retry_max_delay_ms {
+            self.retry_max_delay_ms = retry_max_delay_ms;
+        }
+        if let Some(git_auto_commit) = layer.git_auto_commit {
+            self.git_auto_commit = git_auto_commit;
+        }
+        if let Some(undo_depth) = layer.undo_depth {
+            self.undo_depth = undo_depth;
+        }
+        self.pricing.extend(layer.pricing.clone());
+        self.backends.extend(layer.backends.clone());
+        if let Some(default_profile) = &layer.default_profile {
+            self.default_profile = Some(default_profile.clone());
+        }
+        self.profiles.extend(layer.profiles.clone());
+    }
+
+    /// Merges the profile with the given name.
+    pub fn apply_profile(&mut self, name: &str) -> Result<()> {
+        let Some(profile) = self.profiles.get(name).cloned() else {
+            let available: Vec<&str> = self.profiles.keys().map(String::as_str).collect();
+            return Err(anyhow!(
+                "Unknown profile '{name}'. Available profiles: {}",
+                if available.is_empty() {
+                    "none".to_string()
+                } else {
+                    available.join(", ")
+                }
+            ));
+        };
+        self.merge(&profile);
+        Ok(())
+    }
+
+    /// The configuration of the selected backend, with `base_url` applied.
+    pub fn backend_config(&self) -> Result<BackendConfig> {
+        let Some(backend) = self.backends.get(&self.backend) else {
+            let available: Vec<&str> = self.backends.keys().map(String::as_str).collect();
+            return Err(anyhow!(
+                "Unknown backend '{}'. Available backends: {}",
+                self.backend,
+                available.join(", ")
+            ));
+        };
+        let mut backend = backend.clone();
+        if let Some(base_url) = &self.base_url {
+            backend.base_url = base_url.clone();
+        }
+        Ok(backend)
+    }
+
+    /// Whether the tools of `model` are described in the system prompt instead of being
+    /// sent as native tools.
+    pub fn uses_text_tool_calls(&self, model: &str) -> bool {
+        self.text_tool_call_models.iter().any(|m| m == model)
+    }
+
+    /// The model that implements the architect's plans. Defaults to `model`.
+    pub fn editor_model(&self) -> &str {
+        self.editor_model.as_deref().unwrap_or(&self.model)
     }
 }
 
 impl Default for Config {
     fn default() -> Self {
-        let backend = Backend::default();
         Self {
-            backend: backend.clone(),
+            backend: "openrouter".to_string(),
             model: "openai/gpt-4.1-mini".to_string(),
             system_prompt: Some(DEFAULT_SYSTEM_PROMPT.to_string()),
             timeout_seconds: 120,
@@ -211,7 +423,26 @@ impl Default for Config {
             debug_tool_calls: false,
             auto_execute: false,
             print_messages: false,
-            base_url: backend.config().base_url.to_string(),
+            base_url: None,
+            thinking_budget_tokens: None,
+            context_budget_tokens: 100_000,
+            compaction: CompactionStrategy::default(),
+            reasoning_effort: None,
+            reasoning_history: ReasoningHistory::default(),
+            fallback_models: Vec::new(),
+            architect_model: None,
+            editor_model: None,
+            subagent_model: None,
+            text_tool_call_models: Vec::new(),
+            max_retries: 3,
+            retry_initial_delay_ms: 1000,
+            retry_max_delay_ms: 30_000,
+            git_auto_commit: false,
+            undo_depth: 20,
+            default_profile: None,
+            pricing: BTreeMap::new(),
+            backends: backend::builtin_backends(),
+            profiles: BTreeMap::new(),
         }
     }
 }
@@ -221,18 +452,27 @@ impl Default for Config {
 ///
 /// 1. `Config::default()` is used as the base.
 /// 2. The `config.toml` file is loaded and merged.
-/// 3. The `cli_layer` from command-line arguments is merged.
+/// 3. The project configuration (`.alors.toml`), if any, is merged. If it widens
+///    permissions, `trust_prompt` decides whether it may, see `project_config`.
+/// 4. The `profile` from `[profiles.<name>]`, or else the `default_profile`, is merged.
+/// 5. The `cli_layer` from command-line arguments is merged.
 ///
 /// The function will also create or update the `config.toml` file to include any
 /// newly available default settings, making them discoverable to the user.
-pub fn load(cli_layer: &ConfigLayer) -> Result<Config> {
+pub fn load(
+    cli_layer: &ConfigLayer,
+    profile: Option<&str>,
+    trust_prompt: TrustPrompt,
+) -> Result<Config> {
     let xdg_dirs = xdg::BaseDirectories::new();
     let config_path = xdg_dirs.place_config_file("alors/config.toml")?;
 
-    // Load file layer, or use a default if it doesn't exist or fails to parse.
+    // Load file layer, or use a default if it doesn't exist. A file that fails to parse
+    // is an error, so that it is not overwritten below.
     let file_layer: ConfigLayer = if config_path.exists() {
         let config_string = fs::read_to_string(&config_path)?;
-        toml::from_str(&config_string).unwrap_or_default()
+        toml::from_str(&config_string)
+            .with_context(|| format!("Failed to parse the config {}", config_path.display()))?
     } else {
         ConfigLayer::default()
     };
@@ -241,8 +481,18 @@ pub fn load(cli_layer: &ConfigLayer) -> Result<Config> {
     let mut config_for_disk = Config::default();
     config_for_disk.merge(&file_layer);
 
-    // If the on-disk representation is out of date or doesn't exist, write it.
-    let new_disk_toml = toml::to_string_pretty(&config_for_disk)?;
+    // If the on-disk representation is out of date or doesn't exist, write it. Only the
+    // backends the user defined or changed are written; the built-in ones stay in the code.
+    let builtin_backends = backend::builtin_backends();
+    let new_disk_toml = toml::to_string_pretty(&Config {
+        backends: file_layer
+            .backends
+            .iter()
+            .filter(|(name, backend)| builtin_backends.get(*name) != Some(*backend))
+            .map(|(name, backend)| (name.clone(), backend.clone()))
+            .collect(),
+        ..config_for_disk.clone()
+    })?;
     let old_disk_toml = fs::read_to_string(&config_path).unwrap_or_default();
 
     if new_disk_toml != old_disk_toml {
@@ -251,13 +501,140 @@ pub fn load(cli_layer: &ConfigLayer) -> Result<Config> {
         }
         fs::write(&config_path, new_disk_toml)?;
         if old_disk_toml.is_empty() {
-            println!("Created default config at: {}", config_path.display());
+            eprintln!("Created default config at: {}", config_path.display());
         }
     }
 
-    // Start with the on-disk config state and merge the final CLI layer.
-    let mut final_config = config_for_disk;
-    final_config.merge(cli_layer);
+    // Start with the on-disk config state and merge the project, profile and CLI layers.
+    let project_layer = match project_config::find(&std::env::current_dir()?) {
+        Some(project_path) => Some(project_config::load(
+            &project_path,
+            &config_for_disk,
+            trust_prompt,
+        )?),
+        None => None,
+    };
+    merge_layers(config_for_disk, project_layer.as_ref(), profile, cli_layer)
+}
+
+/// Merges the project layer, the `profile` or else the `default_profile`, and the CLI
+/// layer into `config`, in this order.
+fn merge_layers(
+    mut config: Config,
+    project_layer: Option<&ConfigLayer>,
+    profile: Option<&str>,
+    cli_layer: &ConfigLayer,
+) -> Result<Config> {
+    if let Some(project_layer) = project_layer {
+        config.merge(project_layer);
+    }
+    if let Some(profile) = profile
+        .map(str::to_string)
+        .or_else(|| config.default_profile.clone())
+    {
+        config.apply_profile(&profile)?;
+    }
+    config.merge(cli_layer);
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    Ok(final_config)
+    fn file_config(file: &str) -> Config {
+        let mut config = Config::default();
+        config.merge(&toml::from_str(file).unwrap());
+        config
+    }
+
+    const PROFILES: &str = r#"
+        model = "openai/gpt-4.1-mini"
+
+        [profiles.local]
+        backend = "ollama"
+        model = "qwen3-coder"
+
+        [profiles.fast]
+        model = "google/gemini-2.5-flash"
+        max_iterations = 10
+    "#;
+
+    #[test]
+    fn test_apply_profile() {
+        let mut config = file_config(PROFILES);
+        config.base_url = Some("https://example.com/v1/".to_string());
+
+        config.apply_profile("local").unwrap();
+        assert_eq!(config.backend, "ollama");
+        assert_eq!(config.model, "qwen3-coder");
+        // The backend of the profile comes with its own base_url.
+        assert_eq!(config.base_url, None);
+        assert_eq!(config.max_iterations, 50);
+    }
+
+    #[test]
+    fn test_unknown_profile_lists_the_available_ones() {
+        let error = file_config(PROFILES).apply_profile("remote").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Unknown profile 'remote'. Available profiles: fast, local"
+        );
+
+        let error = Config::default().apply_profile("remote").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Unknown profile 'remote'. Available profiles: none"
+        );
+    }
+
+    #[test]
+    fn test_default_profile_unless_another_is_selected() {
+        let file = format!("default_profile = \"fast\"\n{PROFILES}");
+        let cli_layer = ConfigLayer::default();
+
+        let config = merge_layers(file_config(&file), None, None, &cli_layer).unwrap();
+        assert_eq!(config.model, "google/gemini-2.5-flash");
+
+        let config = merge_layers(file_config(&file), None, Some("local"), &cli_layer).unwrap();
+        assert_eq!(config.model, "qwen3-coder");
+        assert_eq!(config.max_iterations, 50);
+
+        let config = merge_layers(file_config(PROFILES), None, None, &cli_layer).unwrap();
+        assert_eq!(config.model, "openai/gpt-4.1-mini");
+    }
+
+    #[test]
+    fn test_layers_are_merged_in_order() {
+        let base = file_config(
+            r#"
+            model = "base"
+            max_iterations = 1
+            timeout_seconds = 1
+            max_read_lines = 1
+
+            [profiles.review]
+            timeout_seconds = 3
+            max_read_lines = 3
+            "#,
+        );
+        let project_layer: ConfigLayer = toml::from_str(
+            r#"
+            max_iterations = 2
+            timeout_seconds = 2
+            max_read_lines = 2
+            "#,
+        )
+        .unwrap();
+        let cli_layer = ConfigLayer {
+            max_read_lines: Some(4),
+            ..Default::default()
+        };
+
+        let config = merge_layers(base, Some(&project_layer), Some("review"), &cli_layer).unwrap();
+        assert_eq!(config.model, "base");
+        assert_eq!(config.max_iterations, 2);
+        assert_eq!(config.timeout_seconds, 3);
+        assert_eq!(config.max_read_lines, 4);
+    }
 }
@@ -0,0 +1,409 @@
+//! # Context Window Management
+//!
+//! Long sessions eventually outgrow the model's context window. This module estimates
+//! the size of the message history and compacts older parts of it once the configured
+//! `context_budget_tokens` is nearly used up.
+//!
+//! Two strategies are available:
+//!
+//! - **Placeholder**: Old tool outputs are replaced by a one-line placeholder, oldest
+//!   first, until the history fits again.
+//! - **Summarize**: All complete turns before the current one are replaced by a summary
+//!   written by the model.
+//!
+//! In both cases, system messages and the latest LIF view of every file are kept, so the
+//! model always sees the current LIDs of the files it works on.
+//!
+//! Independent of the budget, LIF views of files that have changed since they were shown
+//! are collapsed to their header with a `[superseded by hash …]` note, so the model is not
+//! misled by stale LIDs.
+
+use once_cell::sync::Lazy;
+use openrouter_api::types::chat::Message;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+
+/// Compaction starts when the history uses this share of the budget (in percent).
+pub const COMPACTION_THRESHOLD_PERCENT: u64 = 80;
+/// Placeholder compaction stops as soon as the history is below this share of the budget.
+pub const COMPACTION_TARGET_PERCENT: u64 = 50;
+/// Tool outputs smaller than this are not worth replacing.
+const MIN_PLACEHOLDER_TOKENS: u64 = 50;
+/// Rough per-message overhead for role and formatting tokens.
+const MESSAGE_OVERHEAD_TOKENS: u64 = 4;
+
+pub const SUMMARY_SYSTEM_PROMPT: &str = "You summarize the earlier part of a conversation between a user and an AI coding agent, so the agent can continue the work without the full transcript.
+Keep: the user's goals and instructions, decisions made, files created or changed and why, commands run and their important results, and open problems.
+Omit file contents and line identifiers (LIDs); the current file contents are provided separately.
+Answer with the summary only.";
+
+/// How the history is shrunk when it exceeds the context budget.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CompactionStrategy {
+    /// Replace stale tool outputs with short placeholders.
+    #[default]
+    Placeholder,
+    /// Let the model summarize earlier turns.
+    Summarize,
+}
+
+/// Estimates the number of tokens of a text.
+/// This is a heuristic (about four characters per token), not a real tokenizer.
+pub fn estimate_tokens(text: &str) -> u64 {
+    (text.chars().count() as u64).div_ceil(4)
+}
+
+pub fn estimate_message_tokens(message: &Message) -> u64 {
+    let tool_call_tokens: u64 = message
+        .tool_calls
+        .iter()
+        .flatten()
+        .map(|tool_call| {
+            estimate_tokens(&tool_call.function_call.name)
+                + estimate_tokens(&tool_call.function_call.arguments)
+        })
+        .sum();
+    MESSAGE_OVERHEAD_TOKENS + estimate_tokens(&message.content) + tool_call_tokens
+}
+
+pub fn estimate_history_tokens(messages: &[Message]) -> u64 {
+    messages.iter().map(estimate_message_tokens).sum()
+}
+
+/// Returns `true` if the history uses enough of the budget to be compacted.
+pub fn needs_compaction(messages: &[Message], budget_tokens: u64) -> bool {
+    budget_tokens > 0
+        && estimate_history_tokens(messages) * 100 > budget_tokens * COMPACTION_THRESHOLD_PERCENT
+}
+
+/// A LIF rendering of a file inside a message, as produced by `display_lif_contents`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LifView {
+    /// The path as shown in the header.
+    pub path: String,
+    /// The short `lif_hash` as shown in the header.
+    pub hash: String,
+    /// The byte range of the header and all content lines within the message.
+    pub range: Range<usize>,
+}
+
+static LIF_HEADER: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^File: (.+) \| Hash: ([0-9a-f]+) \| Lines: ").unwrap());
+static LIF_LINE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^\d+\s*lid-\S+: ").unwrap());
+
+/// Finds all LIF views in a message content.
+pub fn find_lif_views(content: &str) -> Vec<LifView> {
+    let mut views: Vec<LifView> = Vec::new();
+    let mut current: Option<LifView> = None;
+    let mut offset = 0;
+
+    for line in content.split_inclusive('\n') {
+        let text = line.trim_end_matches('\n');
+        let line_end = offset + text.len();
+
+        if let Some(captures) = LIF_HEADER.captures(text) {
+            views.extend(current.take());
+            current = Some(LifView {
+                path: captures[1].to_string(),
+                hash: captures[2].to_string(),
+                range: offset..line_end,
+            });
+        } else if let Some(view) = current.as_mut() {
+            if LIF_LINE.is_match(text) || text == "..." || text == "[File is empty]" {
+                view.range.end = line_end;
+            } else {
+                views.extend(current.take());
+            }
+        }
+        offset += line.len();
+    }
+    views.extend(current);
+    views
+}
+
+/// Returns the indices of the messages that contain the latest LIF view of some file.
+pub fn latest_lif_view_messages(messages: &[Message]) -> HashSet<usize> {
+    let mut latest: HashMap<String, usize> = HashMap::new();
+    for (index, message) in messages.iter().enumerate() {
+        for view in find_lif_views(&message.content) {
+            latest.insert(view.path, index);
+        }
+    }
+    latest.into_values().collect()
+}
+
+/// Collapses every LIF view whose file has a different hash by now, keeping only its
+/// header. `current_hashes` maps the displayed path of each known file to its current
+/// short hash. This includes the views in the system prompt, e.g. of @-mentioned files.
+/// Returns the number of collapsed views.
+pub fn supersede_stale_views(
+    messages: &mut [Message],
+    current_hashes: &HashMap<String, String>,
+) -> usize {
+    let mut superseded = 0;
+    for message in messages.iter_mut() {
+        // Replace from the back, so the ranges of the earlier views stay valid.
+        for view in find_lif_views(&message.content).into_iter().rev() {
+            let Some(current_hash) = current_hashes.get(&view.path) else {
+                continue;
+            };
+            if *current_hash == view.hash {
+                continue;
+            }
+            let replacement = format!(
+                "File: {} | Hash: {} [superseded by hash {current_hash}]",
+                view.path, view.hash
+            );
+            message.content.replace_range(view.range, &replacement);
+            superseded += 1;
+        }
+    }
+    superseded
+}
+
+/// Replaces old tool outputs with placeholders, oldest first, until the history is below
+/// `target_tokens`. Outputs of the latest assistant message and messages holding the
+/// latest LIF view of a file are kept. Returns the number of replaced outputs.
+pub fn compact_with_placeholders(messages: &mut [Message], target_tokens: u64) -> usize {
+    let protected = latest_lif_view_messages(messages);
+    let last_assistant = messages
+        .iter()
+        .rposition(|m| m.role == "assistant")
+        .unwrap_or(0);
+
+    let mut total = estimate_history_tokens(messages);
+    let mut replaced = 0;
+    for (index, message) in messages.iter_mut().enumerate().take(last_assistant) {
+        if total <= target_tokens {
+            break;
+        }
+        if message.role != "tool" || protected.contains(&index) {
+            continue;
+        }
+        let tokens = estimate_tokens(&message.content);
+        if tokens < MIN_PLACEHOLDER_TOKENS {
+            continue;
+        }
+        let placeholder = format!(
+            "[Output of `{}` removed to save context ({tokens} tokens).]",
+            message.name.as_deref().unwrap_or("tool")
+        );
+        total = total - tokens + estimate_tokens(&placeholder);
+        message.content = placeholder;
+        replaced += 1;
+    }
+    replaced
+}
+
+/// The messages that summarization may replace: all complete turns before the current
+/// one, after the leading system messages. `None` if there is nothing to summarize.
+pub fn summarizable_range(messages: &[Message]) -> Option<Range<usize>> {
+    let start = messages.iter().position(|m| m.role != "system")?;
+    let end = messages.iter().rposition(|m| m.role == "user")?;
+    (start < end).then_some(start..end)
+}
+
+/// Renders messages as plain text, as input for the summarizer.
+pub fn render_transcript(messages: &[Message]) -> String {
+    let mut transcript = String::new();
+    for message in messages {
+        transcript.push_str(&format!("[{}]\n", message.role));
+        if !message.content.is_empty() {
+            transcript.push_str(&message.content);
+            transcript.push('\n');
+        }
+        for tool_call in message.tool_calls.iter().flatten() {
+            transcript.push_str(&format!(
+                "(tool call `{}` with arguments {})\n",
+                tool_call.function_call.name, tool_call.function_call.arguments
+            ));
+        }
+        transcript.push('\n');
+    }
+    transcript
+}
+
+/// Builds the message that replaces the summarized `range` of `messages`. The latest LIF
+/// views that are inside the range are attached, so their LIDs stay usable.
+pub fn summary_message(summary: &str, messages: &[Message], range: Range<usize>) -> Message {
+    // The latest view of each file, with its position in the history.
+    let mut latest: HashMap<String, (usize, usize, String)> = HashMap::new();
+    for (index, message) in messages.iter().enumerate() {
+        for view in find_lif_views(&message.content) {
+            let text = message.content[view.range.clone()].to_string();
+            latest.insert(view.path, (index, view.range.start, text));
+        }
+    }
+    let mut kept_views: Vec<(usize, usize, String)> = latest
+        .into_values()
+        .filter(|(index, _, _)| range.contains(index))
+        .collect();
+    kept_views.sort();
+
+    let mut content = format!("[Summary of the earlier conversation]\n{summary}");
+    if !kept_views.is_empty() {
+        content.push_str("\n\nCurrent contents of files from the summarized conversation:\n\n");
+        let views: Vec<String> = kept_views.into_iter().map(|(_, _, text)| text).collect();
+        content.push_str(&views.join("\n\n"));
+    }
+    Message {
+        role: "user".to_string(),
+        content,
+        name: None,
+        tool_calls: None,
+        tool_call_id: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_state::FileState;
+    use crate::scripted_client::message;
+    use std::path::PathBuf;
+
+    fn tool_result(content: &str) -> Message {
+        Message {
+            name: Some("read_files".to_string()),
+            ..message("tool", content)
+        }
+    }
+
+    fn lif_view(path: &str, content: &str) -> String {
+        FileState::new(PathBuf::from(path), content).display_lif_contents()
+    }
+
+    #[test]
+    fn test_find_lif_views() {
+        let first = lif_view("/tmp/a.txt", "one\ntwo\n");
+        let second = lif_view("/tmp/b.txt", "");
+        let content = format!("--- File: a ---\n{first}\n\n--- File: b ---\n{second}\nDone.");
+
+        let views = find_lif_views(&content);
+
+        assert_eq!(views.len(), 2);
+        assert!(views[0].path.ends_with("a.txt"));
+        assert_eq!(&content[views[0].range.clone()], first);
+        assert!(views[1].path.ends_with("b.txt"));
+        assert_eq!(&content[views[1].range.clone()], second);
+    }
+
+    #[test]
+    fn test_supersede_stale_views() {
+        let mut state = FileState::new(PathBuf::from("/tmp/a.txt"), "one\n");
+        let old_view = state.display_lif_contents();
+        let old_hash = state.get_short_hash().to_string();
+        state.lif_hash = "abcd1234ffff".to_string();
+        let unrelated_view = lif_view("/tmp/b.txt", "b\n");
+        let mut messages = vec![
+            message("system", "sys"),
+            tool_result(&format!("{old_view}\n\n{unrelated_view}")),
+        ];
+        let current_hashes = HashMap::from([(
+            state.display_path().to_string_lossy().to_string(),
+            state.get_short_hash().to_string(),
+        )]);
+
+        let superseded = supersede_stale_views(&mut messages, &current_hashes);
+
+        assert_eq!(superseded, 1);
+        assert!(messages[1].content.starts_with(&format!(
+            "File: {} | Hash: {old_hash} [superseded by hash abcd1234]",
+            state.display_path().display()
+        )));
+        assert!(messages[1].content.ends_with(&unrelated_view));
+        // Superseded views are not views anymore and are left alone from now on.
+        assert_eq!(find_lif_views(&messages[1].content).len(), 1);
+    }
+
+    #[test]
+    fn test_supersede_stale_views_in_the_system_prompt() {
+        let mut state = FileState::new(PathBuf::from("/tmp/conventions.md"), "Use tabs.\n");
+        let old_view = state.display_lif_contents();
+        let old_hash = state.get_short_hash().to_string();
+        let system_prompt = format!("Follow the conventions:\n\n{old_view}\n\nBe brief.");
+        let mut messages = vec![message("system", &system_prompt)];
+        let mut current_hashes = HashMap::from([(
+            state.display_path().to_string_lossy().to_string(),
+            old_hash.clone(),
+        )]);
+        assert_eq!(supersede_stale_views(&mut messages, &current_hashes), 0);
+
+        state.lif_hash = "abcd1234ffff".to_string();
+        current_hashes.insert(
+            state.display_path().to_string_lossy().to_string(),
+            state.get_short_hash().to_string(),
+        );
+        let superseded = supersede_stale_views(&mut messages, &current_hashes);
+
+        assert_eq!(superseded, 1);
+        assert_eq!(
+            messages[0].content,
+            format!(
+                "Follow the conventions:\n\nFile: {} | Hash: {old_hash} [superseded by hash abcd1234]\n\nBe brief.",
+                state.display_path().display()
+            )
+        );
+    }
+
+    #[test]
+    fn test_placeholders_keep_latest_views_and_current_results() {
+        let big_output = "x".repeat(1000);
+        let mut messages = vec![
+            message("system", &big_output),
+            message("user", "read the files"),
+            message("assistant", ""),
+            tool_result(&big_output),
+            tool_result(&lif_view("/tmp/a.txt", &"line\n".repeat(100))),
+            message("assistant", ""),
+            tool_result(&big_output),
+        ];
+
+        let replaced = compact_with_placeholders(&mut messages, 0);
+
+        assert_eq!(replaced, 1);
+        assert!(
+            messages[3]
+                .content
+                .starts_with("[Output of `read_files` removed")
+        );
+        assert_eq!(messages[0].content, big_output);
+        assert!(messages[4].content.starts_with("File: "));
+        assert_eq!(messages[6].content, big_output);
+    }
+
+    #[test]
+    fn test_summary_message_attaches_latest_views_from_range() {
+        let old_view = lif_view("/tmp/a.txt", "old\n");
+        let new_view = lif_view("/tmp/a.txt", "new\n");
+        let other_view = lif_view("/tmp/b.txt", "b\n");
+        let messages = vec![
+            message("system", "sys"),
+            message("user", "first"),
+            tool_result(&old_view),
+            tool_result(&other_view),
+            tool_result(&new_view),
+            message("user", "second"),
+        ];
+
+        let range = summarizable_range(&messages).unwrap();
+        assert_eq!(range, 1..5);
+        let summary = summary_message("We read files.", &messages, range);
+
+        assert!(summary.content.contains("We read files."));
+        assert!(summary.content.contains(&other_view));
+        assert!(summary.content.contains(&new_view));
+        assert!(!summary.content.contains(&old_view));
+    }
+
+    #[test]
+    fn test_needs_compaction() {
+        let messages = vec![message("user", &"x".repeat(400))];
+        assert!(!needs_compaction(&messages, 1000));
+        assert!(needs_compaction(&messages, 100));
+        assert!(!needs_compaction(&messages, 0));
+    }
+}
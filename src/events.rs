@@ -0,0 +1,159 @@
+//! # Agent Events
+//!
+//! The library does not print to the terminal. Everything that happens during a turn
+//! (streamed text, tool calls, live tool output, errors, token usage) is reported as an
+//! `AgentEvent` to an `EventSink`. The terminal UI is one sink among others; a GUI, a
+//! server or a test can subscribe with their own sink or through a channel.
+
+use crate::context::CompactionStrategy;
+use openrouter_api::{models::tool::ToolCall, types::chat::Message};
+use serde::Serialize;
+use std::path::PathBuf;
+use tokio::sync::mpsc;
+
+/// The stream a line of live tool output was written to.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+/// Token counts reported by the API for a single request.
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct TokenUsage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub total_tokens: u64,
+    /// The cost in USD, if known. Calculated by the agent from the configured `pricing`.
+    pub cost: Option<f64>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AgentEvent {
+    /// The history is about to be sent to the LLM.
+    /// Only emitted if `print_messages` is enabled.
+    RequestMessages { messages: Vec<Message> },
+    /// A piece of assistant text arrived from the stream.
+    TextDelta { text: String },
+    /// A piece of the model's reasoning arrived from the stream.
+    ReasoningDelta { text: String },
+    /// A piece of a tool call arrived from the stream.
+    ToolCallDelta {
+        index: u32,
+        id: Option<String>,
+        name: Option<String>,
+        arguments: String,
+    },
+    /// A tool call of the response was repaired before it was added to the history,
+    /// e.g. its arguments were not valid JSON or its id was missing.
+    ToolCallRepaired {
+        tool_call: ToolCall,
+        repairs: Vec<String>,
+    },
+    /// The complete assistant message was received and added to the history.
+    AssistantMessage { message: Message },
+    /// An approved tool call is about to be executed.
+    ToolStarted { tool_call: ToolCall },
+    /// A tool produced a line of live output, e.g. a running shell command.
+    ToolOutput { stream: OutputStream, line: String },
+    /// A shell command finished, e.g. with `Exit code: 0`.
+    CommandExited { status: String },
+    /// A tool call finished. `result` is the content sent back to the LLM.
+    ToolFinished {
+        tool_call: ToolCall,
+        result: String,
+        is_error: bool,
+    },
+    /// The architect finished its plan and the editor model starts implementing it.
+    EditorStarted { model: String },
+    /// Something went wrong, but the turn continues.
+    Error { message: String },
+    /// An LLM request failed with a transient error and is repeated after `delay_ms`.
+    /// Text and tool call deltas received since the last `AssistantMessage` belong to
+    /// the failed attempt and must be discarded.
+    Retrying {
+        attempt: u32,
+        max_retries: u32,
+        delay_ms: u64,
+        error: String,
+    },
+    /// The model `from` failed and the request is repeated with the fallback model `to`.
+    /// As with `Retrying`, deltas of the failed attempt must be discarded.
+    ModelFallback {
+        from: String,
+        to: String,
+        reason: String,
+    },
+    /// The response came from a model other than the requested one, e.g. a fallback
+    /// model.
+    AnsweredBy { model: String },
+    /// `git_auto_commit` committed the files the turn wrote.
+    TurnCommitted { commit: String, files: Vec<PathBuf> },
+    /// `git_auto_commit` did not commit the turn, e.g. because the working tree had
+    /// changes of the user.
+    AutoCommitSkipped { reason: String },
+    /// Token usage of a single LLM request, priced if possible.
+    Usage { usage: TokenUsage },
+    /// Token usage of a single LLM request of a sub-agent, reported by the tool that ran
+    /// it. The agent adds it to its totals.
+    SubagentUsage { usage: TokenUsage },
+    /// The history was compacted to stay within the context budget.
+    /// The token counts are estimates.
+    HistoryCompacted {
+        strategy: CompactionStrategy,
+        tokens_before: u64,
+        tokens_after: u64,
+    },
+}
+
+/// Receives the events of an agent.
+/// Implementations must not block, since events are emitted from within the turn.
+pub trait EventSink: Send + Sync {
+    fn emit(&self, event: AgentEvent);
+}
+
+/// Discards all events.
+pub struct NullSink;
+
+impl EventSink for NullSink {
+    fn emit(&self, _event: AgentEvent) {}
+}
+
+/// Forwards events into a channel, so they can be consumed by another task.
+impl EventSink for mpsc::UnboundedSender<AgentEvent> {
+    fn emit(&self, event: AgentEvent) {
+        // The receiver may have been dropped, in which case nobody is interested anymore.
+        self.send(event).ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_channel_sink_forwards_events() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        tx.emit(AgentEvent::TextDelta {
+            text: "hello".to_string(),
+        });
+        match rx.try_recv().unwrap() {
+            AgentEvent::TextDelta { text } => assert_eq!(text, "hello"),
+            other => panic!("unexpected event: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_events_serialize_with_type_tag() {
+        let event = AgentEvent::ToolOutput {
+            stream: OutputStream::Stderr,
+            line: "warning".to_string(),
+        };
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["type"], "tool_output");
+        assert_eq!(json["stream"], "stderr");
+        assert_eq!(json["line"], "warning");
+    }
+}
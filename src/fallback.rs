@@ -0,0 +1,163 @@
+//! # Model Fallback
+//!
+//! `FallbackClient` wraps another `LlmClient` and tries the `fallback_models` in order
+//! when the requested model fails. A model fails if its request returns an error after
+//! all retries (e.g. the model is down, or the history exceeds its context length) or
+//! if it returns an empty response.
+//!
+//! OpenRouter additionally routes between the models on its side (`models`), see
+//! `ChatCompletionsClient::with_fallback_models`.
+
+use crate::client::{ChatRequest, ChatResponse, LlmClient};
+use crate::events::{AgentEvent, EventSink};
+use anyhow::Result;
+use async_trait::async_trait;
+use std::sync::Arc;
+
+pub struct FallbackClient {
+    inner: Arc<dyn LlmClient>,
+    fallback_models: Vec<String>,
+}
+
+impl FallbackClient {
+    pub fn new(inner: Arc<dyn LlmClient>, fallback_models: Vec<String>) -> Self {
+        Self {
+            inner,
+            fallback_models,
+        }
+    }
+
+    /// The requested model, followed by the fallback models.
+    fn models(&self, requested: &str) -> Vec<String> {
+        let mut models = vec![requested.to_string()];
+        models.extend(
+            self.fallback_models
+                .iter()
+                .filter(|model| *model != requested)
+                .cloned(),
+        );
+        models
+    }
+
+    async fn complete(
+        &self,
+        request: ChatRequest,
+        events: &dyn EventSink,
+        stream: bool,
+    ) -> Result<ChatResponse> {
+        let models = self.models(&request.model);
+        let mut result = Ok(ChatResponse::default());
+        let mut answered_by = request.model.clone();
+        for (index, model) in models.iter().enumerate() {
+            answered_by = model.clone();
+            let attempt = ChatRequest {
+                model: model.clone(),
+                ..request.clone()
+            };
+            result = if stream {
+                self.inner.chat_completion_stream(attempt, events).await
+            } else {
+                self.inner.chat_completion(attempt, events).await
+            };
+            let reason = match &result {
+                Ok(response) if !is_empty(response) => break,
+                Ok(_) => "The model returned an empty response.".to_string(),
+                Err(e) => e.to_string(),
+            };
+            if let Some(next) = models.get(index + 1) {
+                events.emit(AgentEvent::ModelFallback {
+                    from: model.clone(),
+                    to: next.clone(),
+                    reason,
+                });
+            }
+        }
+        result.map(|mut response| {
+            response.model.get_or_insert(answered_by);
+            response
+        })
+    }
+}
+
+/// Whether the response has neither text nor tool calls.
+fn is_empty(response: &ChatResponse) -> bool {
+    response.message.as_ref().is_none_or(|message| {
+        message.content.trim().is_empty() && message.tool_calls.as_ref().is_none_or(Vec::is_empty)
+    })
+}
+
+#[async_trait]
+impl LlmClient for FallbackClient {
+    async fn chat_completion(
+        &self,
+        request: ChatRequest,
+        events: &dyn EventSink,
+    ) -> Result<ChatResponse> {
+        self.complete(request, events, false).await
+    }
+
+    async fn chat_completion_stream(
+        &self,
+        request: ChatRequest,
+        events: &dyn EventSink,
+    ) -> Result<ChatResponse> {
+        self.complete(request, events, true).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scripted_client::ScriptedClient;
+
+    #[tokio::test]
+    async fn test_empty_response_falls_back_to_next_model() {
+        let scripted = Arc::new(
+            ScriptedClient::new()
+                .with_text_response("")
+                .with_text_response("Hello!"),
+        );
+        let client = FallbackClient::new(
+            scripted.clone(),
+            vec!["primary".to_string(), "backup".to_string()],
+        );
+        let (events_tx, mut events_rx) = tokio::sync::mpsc::unbounded_channel();
+        let request = ChatRequest {
+            model: "primary".to_string(),
+            messages: Vec::new(),
+            tools: Vec::new(),
+        };
+
+        let response = client.chat_completion(request, &events_tx).await.unwrap();
+
+        assert_eq!(response.message.unwrap().content, "Hello!");
+        assert_eq!(response.model.as_deref(), Some("backup"));
+        let models: Vec<String> = scripted.requests().into_iter().map(|r| r.model).collect();
+        assert_eq!(models, ["primary", "backup"]);
+        match events_rx.try_recv().unwrap() {
+            AgentEvent::ModelFallback { from, to, .. } => {
+                assert_eq!((from, to), ("primary".to_string(), "backup".to_string()))
+            }
+            event => panic!("unexpected event: {event:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_last_error_is_returned_when_all_models_fail() {
+        // An empty script fails every request.
+        let client =
+            FallbackClient::new(Arc::new(ScriptedClient::new()), vec!["backup".to_string()]);
+        let request = ChatRequest {
+            model: "primary".to_string(),
+            messages: Vec::new(),
+            tools: Vec::new(),
+        };
+
+        let error = client
+            .chat_completion(request, &crate::events::NullSink)
+            .await
+            .unwrap_err();
+
+        assert!(error.to_string().contains("no scripted response left"));
+    }
+}
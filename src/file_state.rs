@@ -248,8 +248,7 @@ impl FileState {
     /// a consistent header and formats the selected lines with their LIDs.
     /// If `ranges` is `None` or empty, it renders the entire file.
     pub fn display_lif_contents_for_ranges(&self, ranges: Option<&[RangeSpec]>) -> String {
-        let project_root = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
-        let relative_path = self.path.strip_prefix(&project_root).unwrap_or(&self.path);
+        let relative_path = self.display_path();
         let short_hash = self.get_short_hash();
 
         if self.lines.is_empty() {
@@ -333,6 +332,15 @@ impl FileState {
         format!("{header}\n{body}")
     }
 
+    /// The path as shown in the LIF header: relative to the current directory if possible.
+    pub fn display_path(&self) -> PathBuf {
+        let project_root = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+        self.path
+            .strip_prefix(&project_root)
+            .unwrap_or(&self.path)
+            .to_path_buf()
+    }
+
     /// Extracts the content of lines within a given LID range, inclusive, using FractionalIndex.
     pub fn get_content_in_range(
         &self,
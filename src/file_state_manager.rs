@@ -13,11 +13,15 @@ use std::{
 
 use anyhow::{Result, anyhow};
 
+use crate::checkpoint::Checkpoint;
 use crate::file_state::FileState;
+use openrouter_api::types::chat::Message;
 
 #[derive(Default)]
 pub struct FileStateManager {
     pub open_files: HashMap<String, FileState>,
+    /// One checkpoint per turn, the current turn last.
+    pub checkpoints: Vec<Checkpoint>,
 }
 
 impl FileStateManager {
@@ -30,16 +34,27 @@ impl FileStateManager {
     /// it returns the cached mutable state. Otherwise, it reads the file
     /// from disk, creates a new `FileState`, caches it, and then returns it.
     pub fn open_file(&mut self, path_str: &str) -> Result<&mut FileState> {
-        let canonical_path = self.get_canonical_path(path_str)?;
+        let canonical_path = Self::get_canonical_path(path_str)?;
+        let content = fs::read_to_string(&canonical_path)?;
+        Ok(self.update_file(canonical_path, &content))
+    }
+
+    /// Like `open_file`, but with the content the caller read from disk, so that the
+    /// manager does not need to be locked while reading.
+    pub fn update_file(&mut self, canonical_path: PathBuf, content: &str) -> &mut FileState {
         let canonical_key = canonical_path.to_string_lossy().to_string();
 
-        if self.is_content_stale(&canonical_key, &canonical_path)? {
-            let content = fs::read_to_string(&canonical_path)?;
-            let file_state = FileState::new(canonical_path, &content);
+        // Compare the reconstructed content from the cache with the actual disk content.
+        let is_stale = self
+            .open_files
+            .get(&canonical_key)
+            .is_none_or(|cached_state| cached_state.get_full_content() != content);
+        if is_stale {
+            let file_state = FileState::new(canonical_path, content);
             self.open_files.insert(canonical_key.clone(), file_state);
         }
 
-        Ok(self.open_files.get_mut(&canonical_key).unwrap())
+        self.open_files.get_mut(&canonical_key).unwrap()
     }
 
     /// Retrieves the current state of a file from the manager, mutably.
@@ -55,23 +70,38 @@ impl FileStateManager {
             })
     }
 
-    /// Checks if the cached file state is stale compared to the disk.
-    /// Returns true if the file is not in the cache or if the content differs.
-    fn is_content_stale(&self, key: &str, path: &Path) -> Result<bool> {
-        match self.open_files.get(key) {
-            Some(cached_state) => {
-                let disk_content = fs::read_to_string(path)?;
-                // Compare the reconstructed content from the cache with the actual disk content.
-                Ok(cached_state.get_full_content() != disk_content)
-            }
-            None => {
-                // Not in cache, so it's "stale" in the sense that we need to load it.
-                Ok(true)
-            }
+    /// Returns the current short `lif_hash` of every open file, keyed by the path
+    /// as it is shown in LIF headers.
+    pub fn current_hashes(&self) -> HashMap<String, String> {
+        self.open_files
+            .values()
+            .map(|state| {
+                (
+                    state.display_path().to_string_lossy().to_string(),
+                    state.get_short_hash().to_string(),
+                )
+            })
+            .collect()
+    }
+
+    /// Starts the checkpoint of a new turn with the history before the turn. Only the
+    /// latest `max_checkpoints` are kept.
+    pub fn begin_checkpoint(&mut self, messages: Vec<Message>, max_checkpoints: usize) {
+        self.checkpoints.push(Checkpoint::new(messages));
+        let excess = self.checkpoints.len().saturating_sub(max_checkpoints);
+        self.checkpoints.drain(..excess);
+    }
+
+    /// Saves the file in the current checkpoint before a tool writes it.
+    pub fn snapshot_before_write(&mut self, path: &Path) -> Result<()> {
+        match self.checkpoints.last_mut() {
+            Some(checkpoint) => checkpoint.snapshot(path, &self.open_files),
+            None => Ok(()),
         }
     }
 
-    fn get_canonical_path(&self, path_str: &str) -> Result<PathBuf> {
+    /// The canonical path of a file, which is the key of its state.
+    pub fn get_canonical_path(path_str: &str) -> Result<PathBuf> {
         let path = Path::new(path_str);
         if !path.exists() {
             return Err(anyhow!("Path does not exist: {}", path.display()));
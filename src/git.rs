@@ -0,0 +1,210 @@
+//! # Git Auto-Commit
+//!
+//! With `git_auto_commit` enabled, every turn that wrote files becomes one commit. The
+//! commit contains exactly the files the turn wrote through the `FileStateManager`, and
+//! its message is made of the prompt and the final answer of the turn, followed by a
+//! `Generated-by` trailer. Undoing such a turn reverts its commit.
+//!
+//! A turn that is cancelled is committed with the files it wrote until then.
+//!
+//! Changes of the user are never committed: the commit is skipped if the working tree
+//! had uncommitted changes before the turn, or if files other than the written ones
+//! changed during the turn.
+
+use anyhow::{Result, anyhow};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// The trailer key that marks commits made by the agent.
+pub const TRAILER: &str = "Generated-by";
+
+/// The state of the working tree before a turn.
+#[derive(Debug, Clone)]
+pub struct RepoState {
+    root: PathBuf,
+    /// The changed and untracked files, as absolute paths.
+    changed: BTreeSet<PathBuf>,
+}
+
+impl RepoState {
+    /// Reads the state of the repository that contains `dir`.
+    pub fn read(dir: &Path) -> Result<Self> {
+        let root = git(dir, &["rev-parse", "--show-toplevel"])?;
+        let root = PathBuf::from(root.trim()).canonicalize()?;
+        let changed = changed_paths(&root)?;
+        Ok(Self { root, changed })
+    }
+
+    /// Commits the `written` files of a turn, unless the working tree has other changes.
+    /// Returns the hash of the commit.
+    pub fn commit_turn(
+        &self,
+        written: &[PathBuf],
+        prompt: &str,
+        answer: &str,
+        model: &str,
+    ) -> Result<String> {
+        // Files created during the turn may have been saved under a non-canonical path.
+        let written: BTreeSet<PathBuf> = written
+            .iter()
+            .map(|path| path.canonicalize().unwrap_or_else(|_| path.clone()))
+            .filter(|path| path.starts_with(&self.root))
+            .collect();
+        if written.is_empty() {
+            return Err(anyhow!(
+                "None of the written files is in the repository {}.",
+                self.root.display()
+            ));
+        }
+        let now = changed_paths(&self.root)?;
+        let unrelated: Vec<String> = self
+            .changed
+            .iter()
+            .chain(now.iter().filter(|path| !written.contains(*path)))
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .map(|path| self.relative(path))
+            .collect();
+        if !unrelated.is_empty() {
+            return Err(anyhow!(
+                "The working tree has uncommitted changes that the agent did not make: {}",
+                unrelated.join(", ")
+            ));
+        }
+
+        let paths: Vec<String> = written.iter().map(|path| self.relative(path)).collect();
+        let message = commit_message(prompt, answer, model, &paths);
+        let mut add = vec!["add", "--"];
+        add.extend(paths.iter().map(String::as_str));
+        git(&self.root, &add)?;
+        let mut commit = vec!["commit", "--quiet", "-m", &message, "--"];
+        commit.extend(paths.iter().map(String::as_str));
+        git(&self.root, &commit)?;
+        Ok(git(&self.root, &["rev-parse", "HEAD"])?.trim().to_string())
+    }
+
+    fn relative(&self, path: &Path) -> String {
+        path.strip_prefix(&self.root)
+            .unwrap_or(path)
+            .to_string_lossy()
+            .to_string()
+    }
+}
+
+/// Reverts an auto-commit in the repository that contains `dir`. If the revert fails,
+/// e.g. because of a conflict, it is aborted, so the working tree is left as it was.
+pub fn revert(dir: &Path, commit: &str) -> Result<()> {
+    if let Err(e) = git(dir, &["revert", "--no-edit", commit]) {
+        git(dir, &["revert", "--abort"]).ok();
+        return Err(e);
+    }
+    Ok(())
+}
+
+/// Builds the message of an auto-commit: the first line of the prompt as the subject,
+/// the final answer as the body, then the written files and the trailer.
+fn commit_message(prompt: &str, answer: &str, model: &str, files: &[String]) -> String {
+    const MAX_SUBJECT_CHARS: usize = 72;
+
+    let first_line = prompt
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .unwrap_or("Continue the previous task");
+    let mut subject: String = first_line.chars().take(MAX_SUBJECT_CHARS).collect();
+    if first_line.chars().count() > MAX_SUBJECT_CHARS {
+        subject.pop();
+        subject.push('…');
+    }
+
+    let mut message = subject;
+    if !answer.trim().is_empty() {
+        message.push_str("\n\n");
+        message.push_str(answer.trim());
+    }
+    message.push_str("\n\nFiles:\n");
+    for file in files {
+        message.push_str(&format!("- {file}\n"));
+    }
+    message.push_str(&format!("\n{TRAILER}: alors ({model})\n"));
+    message
+}
+
+/// Returns the changed and untracked files of the working tree as absolute paths.
+fn changed_paths(root: &Path) -> Result<BTreeSet<PathBuf>> {
+    let status = git(
+        root,
+        &["status", "--porcelain", "-z", "--untracked-files=all"],
+    )?;
+    Ok(parse_status(root, &status))
+}
+
+/// Parses the output of `git status --porcelain -z`.
+fn parse_status(root: &Path, status: &str) -> BTreeSet<PathBuf> {
+    let mut paths = BTreeSet::new();
+    let mut entries = status.split('\0').filter(|entry| !entry.is_empty());
+    while let Some(entry) = entries.next() {
+        let (code, path) = entry.split_at(entry.len().min(3));
+        paths.insert(root.join(path));
+        // Renames and copies are followed by their source path.
+        let source = if code.starts_with(['R', 'C']) {
+            entries.next()
+        } else {
+            None
+        };
+        paths.extend(source.map(|source| root.join(source)));
+    }
+    paths
+}
+
+fn git(dir: &Path, args: &[&str]) -> Result<String> {
+    let output = Command::new("git").arg("-C").arg(dir).args(args).output()?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "git {} failed: {}",
+            args.first().unwrap_or(&""),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_status_includes_renames_and_untracked_files() {
+        let root = Path::new("/repo");
+        let status = " M src/main.rs\0R  new.rs\0old.rs\0?? notes.txt\0";
+
+        let paths: Vec<PathBuf> = parse_status(root, status).into_iter().collect();
+
+        assert_eq!(
+            paths,
+            [
+                "/repo/new.rs",
+                "/repo/notes.txt",
+                "/repo/old.rs",
+                "/repo/src/main.rs"
+            ]
+            .map(PathBuf::from)
+        );
+    }
+
+    #[test]
+    fn test_commit_message_has_subject_body_files_and_trailer() {
+        let message = commit_message(
+            "\nRename the config loader\nIt is called load_config.",
+            "Renamed it.",
+            "openai/gpt-4.1",
+            &["src/config.rs".to_string()],
+        );
+
+        assert_eq!(
+            message,
+            "Rename the config loader\n\nRenamed it.\n\nFiles:\n- src/config.rs\n\nGenerated-by: alors (openai/gpt-4.1)\n"
+        );
+    }
+}
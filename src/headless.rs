@@ -0,0 +1,217 @@
+//! # Headless Mode
+//!
+//! Support for running a single agent turn without a human in the loop, e.g. in CI.
+//! Tool calls are approved with the same rules as in the interactive mode
+//! (`auto_execute` plus `is_safe_for_auto_execute`), but anything that would require a
+//! confirmation is denied, since nobody is there to answer.
+//!
+//! The outcome of the turn is summarized in a machine-readable `TurnReport`.
+//! With `JsonLinesSink`, the events of the turn can be followed live as JSON lines.
+
+use crate::agent::{Approval, ApprovalRequest, StopReason, ToolApprover};
+use crate::events::{AgentEvent, EventSink};
+use crate::reasoning;
+use crate::usage::UsageTotals;
+use anyhow::Result;
+use async_trait::async_trait;
+use openrouter_api::types::chat::Message;
+use serde::Serialize;
+use serde_json::Value;
+
+/// Approves tool calls that are safe for automatic execution and denies all others.
+pub struct NonInteractiveApprover;
+
+#[async_trait]
+impl ToolApprover for NonInteractiveApprover {
+    async fn approve(&mut self, request: ApprovalRequest<'_>) -> Result<Approval> {
+        if request.auto_approved {
+            Ok(Approval::Approved)
+        } else {
+            Ok(Approval::Denied(format!(
+                "Tool call `{}` was denied: it is not allowed to run without confirmation, and this session is non-interactive.",
+                request.tool_call.function_call.name
+            )))
+        }
+    }
+}
+
+/// Writes every event as a single line of JSON to stdout.
+pub struct JsonLinesSink;
+
+impl EventSink for JsonLinesSink {
+    fn emit(&self, event: AgentEvent) {
+        match serde_json::to_string(&event) {
+            Ok(line) => println!("{line}"),
+            Err(e) => eprintln!("Failed to serialize event: {e}"),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TurnStatus {
+    Done,
+    MaxIterations,
+    Cancelled,
+    Error,
+}
+
+impl TurnStatus {
+    /// The process exit code for this status.
+    pub fn exit_code(self) -> i32 {
+        match self {
+            TurnStatus::Done => 0,
+            TurnStatus::Error => 1,
+            TurnStatus::MaxIterations => 2,
+            TurnStatus::Cancelled => 3,
+        }
+    }
+}
+
+/// A tool call made during the turn, together with its result.
+#[derive(Serialize, Debug)]
+pub struct ToolCallReport {
+    pub id: String,
+    pub name: String,
+    /// The parsed arguments, or the raw string if they are not valid JSON.
+    pub arguments: Value,
+    pub result: Option<String>,
+}
+
+/// A machine-readable summary of a single agent turn.
+#[derive(Serialize, Debug)]
+pub struct TurnReport {
+    pub status: TurnStatus,
+    pub exit_code: i32,
+    /// The content of the last assistant message of the turn.
+    pub final_message: Option<String>,
+    pub tool_calls: Vec<ToolCallReport>,
+    pub error: Option<String>,
+    /// Token usage and cost of the turn. Empty until set by the caller.
+    pub usage: UsageTotals,
+}
+
+impl TurnReport {
+    /// Builds the report from the messages added during the turn and its result.
+    pub fn from_turn(turn_messages: &[Message], result: &Result<StopReason>) -> Self {
+        let (status, error) = match result {
+            Ok(StopReason::Done) => (TurnStatus::Done, None),
+            Ok(StopReason::MaxIterations(max_iterations)) => (
+                TurnStatus::MaxIterations,
+                Some(format!(
+                    "Stopped after {max_iterations} LLM calls (max_iterations)."
+                )),
+            ),
+            Ok(StopReason::Cancelled) => (TurnStatus::Cancelled, None),
+            Err(e) => (TurnStatus::Error, Some(e.to_string())),
+        };
+
+        // Stored reasoning is not part of the answer.
+        let final_message = turn_messages
+            .iter()
+            .rev()
+            .filter(|m| m.role == "assistant")
+            .map(|m| reasoning::strip_reasoning(&m.content))
+            .find(|content| !content.trim().is_empty());
+
+        let tool_calls = turn_messages
+            .iter()
+            .filter_map(|m| m.tool_calls.as_ref())
+            .flatten()
+            .map(|tool_call| {
+                let arguments = serde_json::from_str(&tool_call.function_call.arguments)
+                    .unwrap_or_else(|_| Value::String(tool_call.function_call.arguments.clone()));
+                let result = turn_messages
+                    .iter()
+                    .find(|m| {
+                        m.role == "tool" && m.tool_call_id.as_deref() == Some(tool_call.id.as_str())
+                    })
+                    .map(|m| m.content.clone());
+                ToolCallReport {
+                    id: tool_call.id.clone(),
+                    name: tool_call.function_call.name.clone(),
+                    arguments,
+                    result,
+                }
+            })
+            .collect();
+
+        Self {
+            status,
+            exit_code: status.exit_code(),
+            final_message,
+            tool_calls,
+            error,
+            usage: UsageTotals::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scripted_client::message;
+    use anyhow::anyhow;
+    use openrouter_api::models::tool::{FunctionCall, ToolCall};
+
+    #[test]
+    fn test_report_collects_tool_calls_and_final_message() {
+        let mut assistant = message("assistant", "");
+        assistant.tool_calls = Some(vec![ToolCall {
+            id: "call_1".to_string(),
+            kind: "function".to_string(),
+            function_call: FunctionCall {
+                name: "list_files".to_string(),
+                arguments: r#"{"path": "."}"#.to_string(),
+            },
+        }]);
+        let mut tool_result = message("tool", "Files in `.`:\nmain.rs");
+        tool_result.tool_call_id = Some("call_1".to_string());
+        let messages = vec![
+            message("user", "what files are there?"),
+            assistant,
+            tool_result,
+            message("assistant", "There is one file."),
+        ];
+
+        let report = TurnReport::from_turn(&messages, &Ok(StopReason::Done));
+
+        assert_eq!(report.status, TurnStatus::Done);
+        assert_eq!(report.exit_code, 0);
+        assert_eq!(report.final_message.as_deref(), Some("There is one file."));
+        assert_eq!(report.tool_calls.len(), 1);
+        assert_eq!(report.tool_calls[0].arguments["path"], ".");
+        assert_eq!(
+            report.tool_calls[0].result.as_deref(),
+            Some("Files in `.`:\nmain.rs")
+        );
+    }
+
+    #[test]
+    fn test_final_message_without_reasoning() {
+        let messages = vec![
+            message("user", "hi"),
+            message(
+                "assistant",
+                &reasoning::with_reasoning("Hello!", "The user greets me."),
+            ),
+            message(
+                "assistant",
+                &reasoning::with_reasoning("", "Nothing to add."),
+            ),
+        ];
+
+        let report = TurnReport::from_turn(&messages, &Ok(StopReason::Done));
+
+        assert_eq!(report.final_message.as_deref(), Some("Hello!"));
+    }
+
+    #[test]
+    fn test_report_for_error() {
+        let report = TurnReport::from_turn(&[], &Err(anyhow!("connection refused")));
+        assert_eq!(report.status, TurnStatus::Error);
+        assert_eq!(report.exit_code, 1);
+        assert_eq!(report.error.as_deref(), Some("connection refused"));
+        assert!(report.final_message.is_none());
+    }
+}
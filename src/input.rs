@@ -0,0 +1,248 @@
+//! # Line Input
+//!
+//! Reads the user's input with line editing on a dedicated thread, so the async `App`
+//! can wait for it next to Ctrl+C.
+//!
+//! - A line ending with `\` continues on the next line. Pasted text may contain
+//!   newlines, thanks to bracketed paste.
+//! - Prompts are kept in a history under the XDG state directory
+//!   (`~/.local/state/alors/history`), navigable with the arrow keys.
+//! - `Ctrl-X Ctrl-E` opens the current input in `$VISUAL` or `$EDITOR` and sends what
+//!   was written there.
+//!
+//! In raw mode, Ctrl+C does not raise a signal while the user is typing. It is reported
+//! as `Input::Interrupted` instead.
+
+use anyhow::{Result, anyhow};
+use rustyline::error::ReadlineError;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::{ValidationContext, ValidationResult, Validator};
+use rustyline::{
+    Cmd, ConditionalEventHandler, Editor, Event, EventContext, EventHandler, KeyEvent, RepeatCount,
+};
+use rustyline::{Completer, Helper, Highlighter, Hinter};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::{fs, process, thread};
+use tokio::sync::mpsc;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Input {
+    Line(String),
+    /// Ctrl+C was pressed while typing.
+    Interrupted,
+    /// Ctrl+D was pressed, or stdin was closed.
+    Eof,
+}
+
+struct Request {
+    prompt: String,
+    /// Whether the line is a prompt for the agent, which supports multiline input and the
+    /// editor and is added to the history. Otherwise, it is an answer like `y`.
+    is_prompt: bool,
+}
+
+/// Reads lines on a background thread, one per request.
+pub struct InputReader {
+    requests: std::sync::mpsc::Sender<Request>,
+    lines: mpsc::UnboundedReceiver<Input>,
+    /// Whether a line was requested, but not received yet.
+    pending: bool,
+}
+
+impl InputReader {
+    pub fn spawn() -> Self {
+        let (requests_tx, requests_rx) = std::sync::mpsc::channel::<Request>();
+        let (lines_tx, lines_rx) = mpsc::unbounded_channel();
+        thread::spawn(move || {
+            let mut reader = LineReader::new();
+            for request in requests_rx {
+                let input = reader.read(&request);
+                let eof = input == Input::Eof;
+                if lines_tx.send(input).is_err() || eof {
+                    break;
+                }
+            }
+        });
+        Self {
+            requests: requests_tx,
+            lines: lines_rx,
+            pending: false,
+        }
+    }
+
+    /// Reads a prompt for the agent.
+    pub async fn read_prompt(&mut self, prompt: &str) -> Input {
+        self.read(prompt, true).await
+    }
+
+    /// Reads a single-line answer, e.g. to a confirmation.
+    pub async fn read_answer(&mut self, prompt: &str) -> Input {
+        self.read(prompt, false).await
+    }
+
+    /// Cancel-safe: if the returned future is dropped, the requested line is returned by
+    /// the next call.
+    async fn read(&mut self, prompt: &str, is_prompt: bool) -> Input {
+        if !self.pending {
+            let request = Request {
+                prompt: prompt.to_string(),
+                is_prompt,
+            };
+            if self.requests.send(request).is_err() {
+                return Input::Eof;
+            }
+            self.pending = true;
+        }
+        let input = self.lines.recv().await.unwrap_or(Input::Eof);
+        self.pending = false;
+        input
+    }
+}
+
+/// Continues the input on the next line if it ends with `\`, unless the editor was
+/// requested.
+#[derive(Helper, Completer, Hinter, Highlighter)]
+struct InputHelper {
+    editor_requested: Arc<AtomicBool>,
+}
+
+impl Validator for InputHelper {
+    fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
+        if ctx.input().ends_with('\\') && !self.editor_requested.load(Ordering::SeqCst) {
+            Ok(ValidationResult::Incomplete)
+        } else {
+            Ok(ValidationResult::Valid(None))
+        }
+    }
+}
+
+/// Accepts the line and marks it to be continued in the editor.
+struct OpenEditor {
+    editor_requested: Arc<AtomicBool>,
+}
+
+impl ConditionalEventHandler for OpenEditor {
+    fn handle(&self, _: &Event, _: RepeatCount, _: bool, _: &EventContext) -> Option<Cmd> {
+        self.editor_requested.store(true, Ordering::SeqCst);
+        Some(Cmd::AcceptLine)
+    }
+}
+
+struct LineReader {
+    /// `None` if line editing is not available; then stdin is read line by line.
+    editor: Option<Editor<InputHelper, DefaultHistory>>,
+    editor_requested: Arc<AtomicBool>,
+    history_path: Option<PathBuf>,
+}
+
+impl LineReader {
+    fn new() -> Self {
+        let editor_requested = Arc::new(AtomicBool::new(false));
+        let history_path = xdg::BaseDirectories::new()
+            .place_state_file("alors/history")
+            .ok();
+        let editor = Editor::new().ok().map(|mut editor| {
+            editor.set_helper(Some(InputHelper {
+                editor_requested: editor_requested.clone(),
+            }));
+            editor.bind_sequence(
+                Event::KeySeq(vec![KeyEvent::ctrl('X'), KeyEvent::ctrl('E')]),
+                EventHandler::Conditional(Box::new(OpenEditor {
+                    editor_requested: editor_requested.clone(),
+                })),
+            );
+            if let Some(path) = &history_path {
+                // The history does not exist before the first prompt.
+                editor.load_history(path).ok();
+            }
+            editor
+        });
+        Self {
+            editor,
+            editor_requested,
+            history_path,
+        }
+    }
+
+    fn read(&mut self, request: &Request) -> Input {
+        let Some(editor) = &mut self.editor else {
+            return read_stdin_line(&request.prompt);
+        };
+        self.editor_requested.store(false, Ordering::SeqCst);
+        let line = match editor.readline(&request.prompt) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => return Input::Interrupted,
+            Err(_) => return Input::Eof,
+        };
+        if !request.is_prompt {
+            return Input::Line(line.trim().to_string());
+        }
+
+        let mut text = line.replace("\\\n", "\n");
+        if self.editor_requested.swap(false, Ordering::SeqCst) {
+            match edit_in_editor(&text) {
+                Ok(edited) => text = edited,
+                Err(e) => {
+                    // The input stays in the history, so it is not lost.
+                    eprintln!("{e}");
+                    self.add_history(&text);
+                    return Input::Line(String::new());
+                }
+            }
+        }
+        let text = text.trim().to_string();
+        self.add_history(&text);
+        Input::Line(text)
+    }
+
+    fn add_history(&mut self, text: &str) {
+        let Some(editor) = &mut self.editor else {
+            return;
+        };
+        if text.trim().is_empty() {
+            return;
+        }
+        editor.add_history_entry(text).ok();
+        if let Some(path) = &self.history_path {
+            editor.save_history(path).ok();
+        }
+    }
+}
+
+fn read_stdin_line(prompt: &str) -> Input {
+    print!("{prompt}");
+    io::stdout().flush().ok();
+    let mut buffer = String::new();
+    match io::stdin().read_line(&mut buffer) {
+        Ok(0) | Err(_) => Input::Eof,
+        Ok(_) => Input::Line(buffer.trim().to_string()),
+    }
+}
+
+/// Opens `text` in the user's editor and returns the saved text.
+fn edit_in_editor(text: &str) -> Result<String> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    // `$EDITOR` may contain arguments, e.g. `code --wait`.
+    let mut words = editor.split_whitespace();
+    let program = words.next().ok_or_else(|| anyhow!("$EDITOR is empty"))?;
+    let file = tempfile::Builder::new()
+        .prefix("alors-prompt-")
+        .suffix(".md")
+        .tempfile()?;
+    fs::write(file.path(), text)?;
+
+    let status = process::Command::new(program)
+        .args(words)
+        .arg(file.path())
+        .status()
+        .map_err(|e| anyhow!("Failed to start the editor '{editor}': {e}"))?;
+    if !status.success() {
+        return Err(anyhow!("The editor '{editor}' exited with {status}"));
+    }
+    Ok(fs::read_to_string(file.path())?)
+}
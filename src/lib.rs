@@ -1,18 +1,33 @@
 pub mod agent;
+pub mod anthropic;
 pub mod backend;
+pub mod checkpoint;
 pub mod client;
 pub mod config;
+pub mod context;
 pub mod diff;
 pub mod enricher;
+pub mod events;
+pub mod fallback;
 pub mod file_state;
 pub mod file_state_manager;
+pub mod git;
+pub mod headless;
 pub mod patch;
 pub mod path_expander;
 pub mod permissions;
+pub mod project_config;
 pub mod prompt_builder;
+pub mod reasoning;
+pub mod scripted_client;
+pub mod session;
+pub mod sse;
 pub mod streaming_executor;
+pub mod text_tool_calls;
+pub mod tool_call_repair;
 pub mod tool_collection;
 pub mod tools;
+pub mod usage;
 
 pub use config::Config;
 pub use tool_collection::ToolCollection;
@@ -1,22 +1,96 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use clap::Parser;
 use console::style;
 use openrouter_api::types::chat::Message;
-use std::sync::Arc;
+use std::{
+    fs,
+    io::{self, IsTerminal, Read, Write},
+    os::{fd::AsFd, unix::fs::FileTypeExt},
+    path::Path,
+    process,
+    sync::Arc,
+};
 
-use alors::{agent::Agent, tool_collection::ToolCollection};
+use alors::{
+    agent::Agent,
+    headless::{JsonLinesSink, NonInteractiveApprover, TurnReport},
+    session::{self, Session},
+    tool_collection::ToolCollection,
+};
+use cli::OutputFormat;
 
 mod cli;
+mod commands;
+mod input;
 mod ui;
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli = cli::Cli::parse();
-    let config = alors::config::load(&cli.overrides)?;
+
+    if cli.list_sessions {
+        print_sessions()?;
+        return Ok(());
+    }
+
+    // Piped input is attached to the prompt. Since stdin is consumed then, the run is
+    // non-interactive. A prompt given with --print or --prompt-file is complete, so stdin
+    // is only read then if asked for with `-`: CI runners may leave it open without ever
+    // writing to it, and reading it would block forever.
+    let stdin_requested = cli.prompt.as_deref() == Some("-");
+    let prompt_given = cli.prompt_file.is_some() || (cli.print && cli.prompt.is_some());
+    let piped_input = if stdin_requested || (!prompt_given && stdin_has_input()?) {
+        read_stdin()?
+    } else {
+        None
+    };
+    let non_interactive = cli.print || stdin_requested || piped_input.is_some();
+    let prompt = match &cli.prompt_file {
+        Some(path) => fs::read_to_string(path)
+            .with_context(|| format!("Failed to read the prompt file {}", path.display()))?,
+        None => cli
+            .prompt
+            .clone()
+            .filter(|_| !stdin_requested)
+            .unwrap_or_default(),
+    };
+
+    let resume_id =
+        if let Some(id) = &cli.resume {
+            Some(id.clone())
+        } else if cli.continue_session {
+            let cwd = std::env::current_dir()?;
+            Some(session::latest_session_id(&cwd)?.ok_or_else(|| {
+                anyhow::anyhow!("No previous session found for {}", cwd.display())
+            })?)
+        } else {
+            None
+        };
+    let resumed = resume_id.map(|id| Session::load(&id)).transpose()?;
+
+    // A resumed session keeps the config it was started with. A selected profile and the
+    // CLI flags still apply on top.
+    let config = match &resumed {
+        Some(loaded) => {
+            let mut config = loaded.config.clone();
+            if let Some(profile) = &cli.profile {
+                config.apply_profile(profile)?;
+            }
+            config.merge(&cli.overrides);
+            config
+        }
+        None => alors::config::load(
+            &cli.overrides,
+            cli.profile.as_deref(),
+            &mut |path, widened| confirm_project_trust(path, widened, non_interactive),
+        )?,
+    };
 
     let client = alors::client::initialize_client(&config)?;
-    // Always print backend
-    println!("Backend: {:?}", config.backend);
+    // Always print backend, except when the output is meant for other programs.
+    if !non_interactive {
+        println!("Backend: {}", config.backend);
+    }
 
     let mut tool_collection = ToolCollection::new();
     // Register tools
@@ -25,6 +99,14 @@ async fn main() -> Result<()> {
     tool_collection.register(Box::new(alors::tools::FileReaderTool));
     tool_collection.register(Box::new(alors::tools::ListFilesTool));
     tool_collection.register(Box::new(alors::tools::ShellTool));
+    // Sub-agents only explore.
+    let mut subagent_tools = ToolCollection::new();
+    subagent_tools.register(Box::new(alors::tools::FileReaderTool));
+    subagent_tools.register(Box::new(alors::tools::ListFilesTool));
+    tool_collection.register(Box::new(alors::tools::DelegateTool::new(
+        client.clone(),
+        Arc::new(subagent_tools),
+    )));
     // Collect tool names
     let schemas = tool_collection.get_all_schemas();
     let tool_names: Vec<String> = schemas
@@ -34,7 +116,7 @@ async fn main() -> Result<()> {
         })
         .collect();
     // If no initial user message, print tools and model
-    if cli.prompt.clone().unwrap_or_default().is_empty() {
+    if !non_interactive && prompt.is_empty() {
         println!("tools: {}", tool_names.join(", "));
         println!("model: {}", config.model);
     }
@@ -42,14 +124,36 @@ async fn main() -> Result<()> {
 
     let mut agent = Agent::new(config.clone(), Some(client), tool_collection);
 
-    // Only process system prompt if one is configured
-    if let Some(system_prompt) = &agent.config.system_prompt {
+    if let Some(loaded) = resumed {
+        // Status messages go to stderr, so they never mix with `--print` output.
+        eprintln!(
+            "{}",
+            style(format!(
+                "Resumed session {} ({} messages)",
+                loaded.session.id,
+                loaded.messages.len()
+            ))
+            .dim()
+        );
+        agent.messages = loaded.messages;
+        agent.session = Some(loaded.session);
+    } else {
+        agent.session = Some(Session::create(&agent.config)?);
+    }
+
+    // Only process system prompt if one is configured and the history doesn't have one yet.
+    if let Some(system_prompt) = agent
+        .config
+        .system_prompt
+        .clone()
+        .filter(|_| agent.messages.is_empty())
+    {
         let prompt_data = {
             let mut fsm = agent.file_state_manager.lock().unwrap();
-            alors::prompt_builder::process_prompt(system_prompt, &agent.config, &mut fsm)?
+            alors::prompt_builder::process_prompt(&system_prompt, &agent.config, &mut fsm)?
         };
 
-        if agent.config.show_system_prompt {
+        if agent.config.show_system_prompt && !non_interactive {
             println!("[{}]", style("system").blue());
             println!("{system_prompt}"); // Print the original, un-expanded prompt
 
@@ -73,10 +177,157 @@ async fn main() -> Result<()> {
             tool_call_id: None,
         };
         agent.messages.push(system_message);
+        agent.save_session()?;
     }
 
+    if non_interactive {
+        let exit_code = run_headless(
+            &mut agent,
+            &prompt,
+            piped_input.as_deref(),
+            cli.output_format,
+        )
+        .await?;
+        process::exit(exit_code);
+    }
+
+    agent.events = Arc::new(ui::TerminalRenderer::new(agent.config.debug_tool_calls));
     let mut app = ui::App::new(agent);
-    app.run(&cli.prompt.unwrap_or_default()).await?;
+    app.run(&prompt).await?;
+
+    Ok(())
+}
+
+/// Asks whether the project config at `path` may widen the permissions. Without a
+/// terminal to ask on, it may not.
+fn confirm_project_trust(path: &Path, widened: &[String], non_interactive: bool) -> Result<bool> {
+    eprintln!(
+        "{}",
+        style(format!(
+            "The project config {} widens the permissions:",
+            path.display()
+        ))
+        .yellow()
+    );
+    for permission in widened {
+        eprintln!("  - {permission}");
+    }
+    let trusted = if non_interactive {
+        false
+    } else {
+        eprint!("Trust this config? [y/N] ");
+        io::stderr().flush()?;
+        let mut answer = String::new();
+        io::stdin().read_line(&mut answer)?;
+        answer.trim().eq_ignore_ascii_case("y")
+    };
+    if !trusted {
+        eprintln!(
+            "{}",
+            style("These settings are ignored. The rest of the project config applies.").yellow()
+        );
+    }
+    Ok(trusted)
+}
+
+/// Whether stdin is a pipe or a non-empty file, as in `git diff | alors "review this"`.
+/// Terminals, `/dev/null` and other devices are not read.
+fn stdin_has_input() -> Result<bool> {
+    if io::stdin().is_terminal() {
+        return Ok(false);
+    }
+    let stdin = fs::File::from(io::stdin().as_fd().try_clone_to_owned()?);
+    let metadata = stdin.metadata()?;
+    let file_type = metadata.file_type();
+    Ok(file_type.is_fifo() || (file_type.is_file() && metadata.len() > 0))
+}
 
+/// Reads all of stdin. Returns `None` if it only contains whitespace.
+fn read_stdin() -> Result<Option<String>> {
+    let mut input = String::new();
+    io::stdin().read_to_string(&mut input)?;
+    Ok(Some(input).filter(|input| !input.trim().is_empty()))
+}
+
+/// Runs a single turn without user interaction, writes the result to stdout
+/// in the requested format and returns the process exit code.
+/// `piped_input` is attached to the prompt.
+async fn run_headless(
+    agent: &mut Agent,
+    prompt: &str,
+    piped_input: Option<&str>,
+    output_format: OutputFormat,
+) -> Result<i32> {
+    if prompt.trim().is_empty() && piped_input.is_none() {
+        anyhow::bail!("A prompt is required in non-interactive mode.");
+    }
+
+    let mut prompt_data = agent.prepare_prompt(prompt)?;
+    if let Some(piped_input) = piped_input {
+        prompt_data.attach("stdin", piped_input);
+    }
+    for warning in &prompt_data.warnings {
+        eprintln!("{}", style(warning).yellow());
+    }
+
+    // Only stream-json reports what happens during the turn; the other formats
+    // just need the final result.
+    if output_format == OutputFormat::StreamJson {
+        agent.streaming = true;
+        agent.events = Arc::new(JsonLinesSink);
+    } else {
+        agent.streaming = false;
+    }
+    let turn_start = agent.messages.len();
+    let result = agent
+        .run_turn(prompt_data.final_prompt, &mut NonInteractiveApprover)
+        .await;
+    if let Err(e) = agent.save_session() {
+        eprintln!("{}", style(format!("Failed to save session: {e}")).yellow());
+    }
+
+    let mut report = TurnReport::from_turn(&agent.messages[turn_start..], &result);
+    report.usage = agent.turn_usage;
+
+    match output_format {
+        OutputFormat::Text => {
+            if let Some(final_message) = &report.final_message {
+                println!("{final_message}");
+            }
+            if let Some(error) = &report.error {
+                eprintln!("{}", style(error).red());
+            }
+        }
+        OutputFormat::Json => {
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        }
+        OutputFormat::StreamJson => {
+            let mut result_line = serde_json::to_value(&report)?;
+            result_line["type"] = "result".into();
+            println!("{result_line}");
+        }
+    }
+
+    Ok(report.exit_code)
+}
+
+fn print_sessions() -> Result<()> {
+    let summaries = session::list_sessions()?;
+    if summaries.is_empty() {
+        println!("No sessions found.");
+        return Ok(());
+    }
+    for summary in summaries {
+        println!(
+            "{}  {}  {} messages  {}",
+            style(&summary.id).bold(),
+            summary.model,
+            summary.message_count,
+            style(&summary.cwd).dim()
+        );
+        if let Some(prompt) = summary.first_prompt {
+            println!("    {prompt}");
+        }
+    }
     Ok(())
 }
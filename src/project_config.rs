@@ -0,0 +1,292 @@
+//! # Project Configuration
+//!
+//! A repository can bring its own configuration in `.alors.toml` or `.alors/config.toml`.
+//! The file is found by walking up from the working directory and is merged as a
+//! `ConfigLayer` between the user's `config.toml` and the CLI flags.
+//!
+//! A cloned repository must not be able to grant itself permissions. If the project
+//! configuration widens them (more command prefixes or accessible paths, fewer ignored
+//! paths, `auto_execute`, a different API endpoint or backend, files outside the project
+//! mentioned with `@` in the system prompt), the user is asked to
+//! trust it first. Trust is remembered per file and content; when the file changes, the
+//! user is asked again. Without trust, the widening settings are dropped and the rest of
+//! the file still applies.
+
+use crate::config::{Config, ConfigLayer};
+use crate::enricher;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// The file names of a project configuration, in order of precedence.
+const FILE_NAMES: &[&str] = &[".alors.toml", ".alors/config.toml"];
+
+/// Asks the user whether to trust a project configuration. Gets the path of the file
+/// and the permissions it widens.
+pub type TrustPrompt<'a> = &'a mut dyn FnMut(&Path, &[String]) -> Result<bool>;
+
+/// The project configurations the user trusts, stored in the XDG data directory.
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct TrustStore {
+    /// The SHA-1 hash of the trusted content, by path.
+    trusted: BTreeMap<String, String>,
+}
+
+/// Returns the nearest project configuration file in `dir` or its ancestors.
+pub fn find(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .flat_map(|ancestor| FILE_NAMES.iter().map(move |name| ancestor.join(name)))
+        .find(|path| path.is_file())
+}
+
+/// Returns the directory of the project that the configuration file at `path` belongs to.
+fn project_root(path: &Path) -> &Path {
+    let dir = path.parent().unwrap_or(Path::new(""));
+    if dir.ends_with(".alors") {
+        dir.parent().unwrap_or(dir)
+    } else {
+        dir
+    }
+}
+
+/// Loads the project configuration at `path`. If it widens the permissions of `base`
+/// and is not trusted yet, `trust_prompt` decides whether it may.
+pub fn load(path: &Path, base: &Config, trust_prompt: TrustPrompt) -> Result<ConfigLayer> {
+    let content = fs::read_to_string(path)?;
+    let mut layer: ConfigLayer = toml::from_str(&content)
+        .with_context(|| format!("Failed to parse the project config {}", path.display()))?;
+
+    let root = project_root(path);
+    let widened = widened_permissions(base, &layer, root);
+    if widened.is_empty() {
+        return Ok(layer);
+    }
+    let store_path = xdg::BaseDirectories::new().place_data_file("alors/trusted_projects.toml")?;
+    let mut store: TrustStore = fs::read_to_string(&store_path)
+        .ok()
+        .and_then(|store| toml::from_str(&store).ok())
+        .unwrap_or_default();
+    let key = path.canonicalize()?.to_string_lossy().to_string();
+    let hash = format!("{:x}", Sha1::digest(content.as_bytes()));
+    if store.trusted.get(&key) == Some(&hash) {
+        return Ok(layer);
+    }
+
+    if trust_prompt(path, &widened)? {
+        store.trusted.insert(key, hash);
+        fs::write(&store_path, toml::to_string_pretty(&store)?)?;
+    } else {
+        drop_permission_changes(&mut layer, root);
+    }
+    Ok(layer)
+}
+
+/// Describes every way in which `layer` widens the permissions of `base`. `root` is the
+/// directory of the project.
+pub fn widened_permissions(base: &Config, layer: &ConfigLayer, root: &Path) -> Vec<String> {
+    let mut widened = Vec::new();
+    let added = |new: &[String], old: &[String]| -> Vec<String> {
+        new.iter()
+            .filter(|item| !old.contains(*item))
+            .cloned()
+            .collect()
+    };
+
+    let commands = added(
+        &layer.allowed_command_prefixes,
+        &base.allowed_command_prefixes,
+    );
+    if !commands.is_empty() {
+        widened.push(format!("allows the commands: {}", commands.join(", ")));
+    }
+    let paths = added(&layer.accessible_paths, &base.accessible_paths);
+    if !paths.is_empty() {
+        widened.push(format!("allows access to: {}", paths.join(", ")));
+    }
+    if !layer.ignored_paths.is_empty() {
+        let unignored = added(&base.ignored_paths, &layer.ignored_paths);
+        if !unignored.is_empty() {
+            widened.push(format!("stops ignoring: {}", unignored.join(", ")));
+        }
+    }
+    if layer.auto_execute == Some(true) && !base.auto_execute {
+        widened.push("executes safe tool calls without asking".to_string());
+    }
+    if layer.git_auto_commit == Some(true) && !base.git_auto_commit {
+        widened.push("commits every turn to git".to_string());
+    }
+    // The base URL that requests go to without the project configuration.
+    let base_url = base.backend_config().ok().map(|backend| backend.base_url);
+    if let Some(base_url) = layer
+        .base_url
+        .as_ref()
+        .filter(|url| Some(*url) != base_url.as_ref())
+    {
+        widened.push(format!("sends requests and the API key to {base_url}"));
+    } else if layer
+        .backend
+        .as_ref()
+        .is_some_and(|backend| *backend != base.backend)
+    {
+        widened.push("sends requests and the API key to another backend".to_string());
+    }
+    for (name, backend) in &layer.backends {
+        if base.backends.get(name) == Some(backend) {
+            continue;
+        }
+        widened.push(format!(
+            "defines the backend {name}, which sends requests to {}",
+            backend.base_url
+        ));
+        if let Some(command) = &backend.api_key_command {
+            widened.push(format!("runs `{command}` to read the API key"));
+        }
+    }
+    let outside = mentions_outside(layer, root);
+    if !outside.is_empty() {
+        widened.push(format!(
+            "attaches files outside the project to the system prompt: {}",
+            outside.join(", ")
+        ));
+    }
+    for (name, profile) in &layer.profiles {
+        widened.extend(
+            widened_permissions(base, profile, root)
+                .into_iter()
+                .map(|permission| format!("profile {name} {permission}")),
+        );
+    }
+    widened
+}
+
+/// Returns the paths mentioned with `@` in the system prompt of `layer` that may lie
+/// outside `root`. Relative paths are resolved against the working directory, which is
+/// inside the project, so only those that climb up with `..` may leave it.
+fn mentions_outside(layer: &ConfigLayer, root: &Path) -> Vec<String> {
+    let Some(system_prompt) = &layer.system_prompt else {
+        return Vec::new();
+    };
+    enricher::extract_enrichments(system_prompt)
+        .mentioned_files
+        .into_iter()
+        .filter(|mention| {
+            let path = Path::new(mention);
+            (path.is_absolute() && !path.starts_with(root))
+                || path.components().any(|c| c == Component::ParentDir)
+        })
+        .collect()
+}
+
+/// Removes the settings that can widen permissions.
+fn drop_permission_changes(layer: &mut ConfigLayer, root: &Path) {
+    layer.allowed_command_prefixes.clear();
+    layer.accessible_paths.clear();
+    layer.ignored_paths.clear();
+    layer.auto_execute = None;
+    layer.git_auto_commit = None;
+    layer.base_url = None;
+    layer.backend = None;
+    layer.backends.clear();
+    if !mentions_outside(layer, root).is_empty() {
+        layer.system_prompt = None;
+    }
+    for profile in layer.profiles.values_mut() {
+        drop_permission_changes(profile, root);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_find_walks_up_from_the_working_directory() {
+        let root = tempdir().unwrap();
+        let nested = root.path().join("crates/core/src");
+        fs::create_dir_all(&nested).unwrap();
+        assert_eq!(find(&nested), None);
+
+        fs::create_dir(root.path().join(".alors")).unwrap();
+        fs::write(root.path().join(".alors/config.toml"), "").unwrap();
+        assert_eq!(find(&nested), Some(root.path().join(".alors/config.toml")));
+
+        fs::write(root.path().join("crates/.alors.toml"), "").unwrap();
+        assert_eq!(find(&nested), Some(root.path().join("crates/.alors.toml")));
+        assert_eq!(
+            project_root(&root.path().join("crates/.alors.toml")),
+            root.path().join("crates")
+        );
+        assert_eq!(
+            project_root(&root.path().join(".alors/config.toml")),
+            root.path()
+        );
+    }
+
+    #[test]
+    fn test_widened_permissions() {
+        let base = Config::default();
+        let narrowing: ConfigLayer = toml::from_str(
+            r#"
+            allowed_command_prefixes = ["ls", "rg"]
+            ignored_paths = [".git", "target"]
+            system_prompt = "Answer in German."
+            "#,
+        )
+        .unwrap();
+        let root = Path::new("/home/me/project");
+        assert!(widened_permissions(&base, &narrowing, root).is_empty());
+
+        let widening: ConfigLayer = toml::from_str(
+            r#"
+            allowed_command_prefixes = ["ls", "cargo test"]
+            ignored_paths = ["target"]
+            auto_execute = true
+            base_url = "https://example.com/v1"
+            system_prompt = "Follow @CONVENTIONS.md and @/home/me/project/docs, not @/etc/passwd or @../secrets.txt."
+
+            [backends.gateway]
+            base_url = "https://llm.example.com/v1/"
+            api_key_command = "pass show gateway"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            widened_permissions(&base, &widening, root),
+            [
+                "allows the commands: cargo test",
+                "stops ignoring: .git",
+                "executes safe tool calls without asking",
+                "sends requests and the API key to https://example.com/v1",
+                "defines the backend gateway, which sends requests to https://llm.example.com/v1/",
+                "runs `pass show gateway` to read the API key",
+                "attaches files outside the project to the system prompt: /etc/passwd, ../secrets.txt",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_base_url_is_compared_with_the_url_of_the_backend() {
+        let base = Config::default();
+        let root = Path::new("/home/me/project");
+        let same: ConfigLayer =
+            toml::from_str(r#"base_url = "https://openrouter.ai/api/v1/""#).unwrap();
+        assert!(widened_permissions(&base, &same, root).is_empty());
+
+        let other: ConfigLayer =
+            toml::from_str(r#"base_url = "https://llm.example.com/v1/""#).unwrap();
+        assert_eq!(
+            widened_permissions(&base, &other, root),
+            ["sends requests and the API key to https://llm.example.com/v1/"]
+        );
+
+        let overridden = Config {
+            base_url: Some("https://llm.example.com/v1/".to_string()),
+            ..Config::default()
+        };
+        assert!(widened_permissions(&overridden, &other, root).is_empty());
+    }
+}
@@ -113,6 +113,23 @@ pub fn process_prompt(
     })
 }
 
+/// Formats text that is not a file, e.g. piped stdin, as an attachment block to append
+/// to a prompt.
+pub fn format_attachment(name: &str, content: &str) -> String {
+    // The fence must be longer than any backtick run in the content.
+    let mut longest_run = 0;
+    let mut run = 0;
+    for c in content.chars() {
+        run = if c == '`' { run + 1 } else { 0 };
+        longest_run = longest_run.max(run);
+    }
+    let fence = "`".repeat(longest_run.max(2) + 1);
+    format!(
+        "\n\nAttached {name}:\n{fence}\n{}\n{fence}\n",
+        content.trim_end()
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -179,4 +196,16 @@ mod tests {
         assert!(result.final_prompt.contains("Hello, world!"));
         assert!(result.final_prompt.contains("nonexistent.txt"));
     }
+
+    #[test]
+    fn test_format_attachment_fences_content_with_backticks() {
+        assert_eq!(
+            format_attachment("stdin", "a diff\n"),
+            "\n\nAttached stdin:\n```\na diff\n```\n"
+        );
+        assert_eq!(
+            format_attachment("stdin", "```rust\nfn main() {}\n```"),
+            "\n\nAttached stdin:\n````\n```rust\nfn main() {}\n```\n````\n"
+        );
+    }
 }
@@ -0,0 +1,210 @@
+//! # Reasoning
+//!
+//! Reasoning models think before they answer. Depending on the API, the reasoning
+//! arrives as thinking blocks (Anthropic), in a `reasoning` field (OpenRouter) or inline
+//! in the content, wrapped in `<think>` tags (e.g. Qwen on Ollama).
+//!
+//! Both are reported as `AgentEvent::ReasoningDelta`, separate from the answer.
+//! Whether the reasoning is sent back to the model in later requests is decided by
+//! `ReasoningHistory`. Since `Message` has no field for it, kept reasoning is stored in
+//! the content, wrapped in `<think>` tags.
+
+use serde::{Deserialize, Serialize};
+
+const THINK_START: &str = "<think>";
+const THINK_END: &str = "</think>";
+
+/// How much the model should think before answering, for models that support it.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ReasoningEffort {
+    Low,
+    Medium,
+    High,
+}
+
+impl ReasoningEffort {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ReasoningEffort::Low => "low",
+            ReasoningEffort::Medium => "medium",
+            ReasoningEffort::High => "high",
+        }
+    }
+
+    /// The thinking budget for APIs that expect a number of tokens instead.
+    pub fn thinking_budget_tokens(self) -> u32 {
+        match self {
+            ReasoningEffort::Low => 2048,
+            ReasoningEffort::Medium => 8192,
+            ReasoningEffort::High => 24576,
+        }
+    }
+}
+
+/// Which reasoning is sent back to the model with the history.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ReasoningHistory {
+    /// Reasoning is only shown, never sent back.
+    #[default]
+    Drop,
+    /// Only the reasoning of the latest assistant message is sent back.
+    Last,
+    /// All reasoning is sent back.
+    All,
+}
+
+/// A piece of streamed content, classified by `ThinkTagSplitter`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment {
+    Reasoning(String),
+    Text(String),
+}
+
+/// Separates inline `<think>` blocks from the answer in streamed content. Tags may be
+/// split across chunks.
+#[derive(Debug, Default)]
+pub struct ThinkTagSplitter {
+    in_think: bool,
+    /// The end of the last chunk, if it could be the start of a tag.
+    pending: String,
+}
+
+impl ThinkTagSplitter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends a chunk of content and returns the segments that are complete.
+    pub fn push(&mut self, chunk: &str) -> Vec<Segment> {
+        let mut buffer = std::mem::take(&mut self.pending);
+        buffer.push_str(chunk);
+
+        let mut segments = Vec::new();
+        loop {
+            let tag = if self.in_think {
+                THINK_END
+            } else {
+                THINK_START
+            };
+            if let Some(position) = buffer.find(tag) {
+                self.push_segment(&mut segments, &buffer[..position]);
+                self.in_think = !self.in_think;
+                buffer.drain(..position + tag.len());
+                continue;
+            }
+
+            // Hold back a suffix that may be the beginning of the tag.
+            let keep = (1..tag.len())
+                .rev()
+                .find(|&length| buffer.ends_with(&tag[..length]))
+                .unwrap_or(0);
+            let complete = buffer.len() - keep;
+            self.push_segment(&mut segments, &buffer[..complete]);
+            self.pending = buffer[complete..].to_string();
+            return segments;
+        }
+    }
+
+    /// Returns the content that was held back, at the end of the stream.
+    pub fn finish(&mut self) -> Vec<Segment> {
+        let pending = std::mem::take(&mut self.pending);
+        let mut segments = Vec::new();
+        self.push_segment(&mut segments, &pending);
+        segments
+    }
+
+    fn push_segment(&self, segments: &mut Vec<Segment>, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        segments.push(if self.in_think {
+            Segment::Reasoning(text.to_string())
+        } else {
+            Segment::Text(text.to_string())
+        });
+    }
+}
+
+/// Splits complete content into its reasoning, if any, and the answer.
+pub fn split_think_tags(content: &str) -> (Option<String>, String) {
+    let mut splitter = ThinkTagSplitter::new();
+    let mut segments = splitter.push(content);
+    segments.extend(splitter.finish());
+
+    let mut reasoning = String::new();
+    let mut text = String::new();
+    for segment in segments {
+        match segment {
+            Segment::Reasoning(part) => reasoning.push_str(&part),
+            Segment::Text(part) => text.push_str(&part),
+        }
+    }
+    if reasoning.trim().is_empty() {
+        (None, text)
+    } else {
+        (
+            Some(reasoning.trim().to_string()),
+            text.trim_start().to_string(),
+        )
+    }
+}
+
+/// Stores the reasoning in front of the answer, for the history.
+pub fn with_reasoning(content: &str, reasoning: &str) -> String {
+    format!("{THINK_START}\n{reasoning}\n{THINK_END}\n\n{content}")
+}
+
+/// Removes `<think>` blocks from the content.
+pub fn strip_reasoning(content: &str) -> String {
+    split_think_tags(content).1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_splitter_handles_tags_split_across_chunks() {
+        let mut splitter = ThinkTagSplitter::new();
+        let mut segments = Vec::new();
+        for chunk in [
+            "<th",
+            "ink>Let me",
+            " think.</thi",
+            "nk>\n\nThe answer",
+            " is <b>42</b>.",
+        ] {
+            segments.extend(splitter.push(chunk));
+        }
+        segments.extend(splitter.finish());
+
+        let reasoning: String = segments
+            .iter()
+            .filter_map(|segment| match segment {
+                Segment::Reasoning(text) => Some(text.as_str()),
+                Segment::Text(_) => None,
+            })
+            .collect();
+        let text: String = segments
+            .iter()
+            .filter_map(|segment| match segment {
+                Segment::Text(text) => Some(text.as_str()),
+                Segment::Reasoning(_) => None,
+            })
+            .collect();
+        assert_eq!(reasoning, "Let me think.");
+        assert_eq!(text, "\n\nThe answer is <b>42</b>.");
+    }
+
+    #[test]
+    fn test_stored_reasoning_can_be_stripped() {
+        let content = with_reasoning("The answer.", "Hmm.");
+        assert_eq!(
+            split_think_tags(&content),
+            (Some("Hmm.".to_string()), "The answer.".to_string())
+        );
+        assert_eq!(strip_reasoning("No reasoning."), "No reasoning.");
+    }
+}
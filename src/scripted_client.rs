@@ -0,0 +1,192 @@
+//! # Scripted Client
+//!
+//! An in-process `LlmClient` that replays canned assistant messages in order, so
+//! complete agent loops can be tested offline and deterministically. Every request it
+//! receives is recorded for later assertions.
+
+use crate::client::{ChatRequest, ChatResponse, LlmClient};
+use crate::events::{AgentEvent, EventSink, TokenUsage};
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use openrouter_api::models::tool::{FunctionCall, ToolCall};
+use openrouter_api::types::chat::Message;
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+#[derive(Default)]
+pub struct ScriptedClient {
+    responses: Mutex<VecDeque<ChatResponse>>,
+    requests: Mutex<Vec<ChatRequest>>,
+}
+
+impl ScriptedClient {
+    /// Creates a client with an empty script. Any request fails until responses are added.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends an assistant message with the given text to the script.
+    pub fn with_text_response(self, content: &str) -> Self {
+        self.with_response(Message {
+            role: "assistant".to_string(),
+            content: content.to_string(),
+            name: None,
+            tool_calls: None,
+            tool_call_id: None,
+        })
+    }
+
+    /// Appends an assistant message requesting the given tool calls to the script.
+    pub fn with_tool_calls(self, tool_calls: Vec<ToolCall>) -> Self {
+        self.with_response(Message {
+            role: "assistant".to_string(),
+            content: String::new(),
+            name: None,
+            tool_calls: Some(tool_calls),
+            tool_call_id: None,
+        })
+    }
+
+    /// Appends an arbitrary message to the script.
+    pub fn with_response(self, message: Message) -> Self {
+        self.responses.lock().unwrap().push_back(ChatResponse {
+            message: Some(message),
+            usage: None,
+            reasoning: None,
+            model: None,
+        });
+        self
+    }
+
+    /// Attaches reasoning to the last scripted response.
+    pub fn with_reasoning(self, reasoning: &str) -> Self {
+        if let Some(response) = self.responses.lock().unwrap().back_mut() {
+            response.reasoning = Some(reasoning.to_string());
+        }
+        self
+    }
+
+    /// Reports the given token usage with the last scripted response.
+    pub fn with_usage(self, usage: TokenUsage) -> Self {
+        if let Some(response) = self.responses.lock().unwrap().back_mut() {
+            response.usage = Some(usage);
+        }
+        self
+    }
+
+    /// Returns all requests received so far, in order.
+    pub fn requests(&self) -> Vec<ChatRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// Returns the number of scripted responses that were not requested yet.
+    pub fn remaining_responses(&self) -> usize {
+        self.responses.lock().unwrap().len()
+    }
+
+    fn next_response(&self, request: ChatRequest) -> Result<ChatResponse> {
+        self.requests.lock().unwrap().push(request);
+        self.responses
+            .lock()
+            .unwrap()
+            .pop_front()
+            .ok_or_else(|| anyhow!("ScriptedClient: no scripted response left"))
+    }
+}
+
+/// Builds a tool call with JSON arguments, for use in scripts.
+pub fn tool_call(id: &str, name: &str, arguments: Value) -> ToolCall {
+    ToolCall {
+        id: id.to_string(),
+        kind: "function".to_string(),
+        function_call: FunctionCall {
+            name: name.to_string(),
+            arguments: arguments.to_string(),
+        },
+    }
+}
+
+/// Builds a message without tool calls, for unit tests.
+#[cfg(test)]
+pub(crate) fn message(role: &str, content: &str) -> Message {
+    Message {
+        role: role.to_string(),
+        content: content.to_string(),
+        name: None,
+        tool_calls: None,
+        tool_call_id: None,
+    }
+}
+
+#[async_trait]
+impl LlmClient for ScriptedClient {
+    async fn chat_completion(
+        &self,
+        request: ChatRequest,
+        _events: &dyn EventSink,
+    ) -> Result<ChatResponse> {
+        self.next_response(request)
+    }
+
+    async fn chat_completion_stream(
+        &self,
+        request: ChatRequest,
+        events: &dyn EventSink,
+    ) -> Result<ChatResponse> {
+        let response = self.next_response(request)?;
+
+        // Replay the message as a single chunk, like a very fast stream.
+        if let Some(reasoning) = &response.reasoning {
+            events.emit(AgentEvent::ReasoningDelta {
+                text: reasoning.clone(),
+            });
+        }
+        if let Some(message) = &response.message {
+            if !message.content.is_empty() {
+                events.emit(AgentEvent::TextDelta {
+                    text: message.content.clone(),
+                });
+            }
+            for (index, tool_call) in message.tool_calls.iter().flatten().enumerate() {
+                events.emit(AgentEvent::ToolCallDelta {
+                    index: index as u32,
+                    id: Some(tool_call.id.clone()),
+                    name: Some(tool_call.function_call.name.clone()),
+                    arguments: tool_call.function_call.arguments.clone(),
+                });
+            }
+        }
+
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::NullSink;
+
+    fn request() -> ChatRequest {
+        ChatRequest {
+            model: "scripted".to_string(),
+            messages: Vec::new(),
+            tools: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_replays_responses_in_order() {
+        let client = ScriptedClient::new()
+            .with_text_response("first")
+            .with_text_response("second");
+
+        let first = client.chat_completion(request(), &NullSink).await.unwrap();
+        let second = client.chat_completion(request(), &NullSink).await.unwrap();
+
+        assert_eq!(first.message.unwrap().content, "first");
+        assert_eq!(second.message.unwrap().content, "second");
+        assert_eq!(client.requests().len(), 2);
+        assert!(client.chat_completion(request(), &NullSink).await.is_err());
+    }
+}
@@ -0,0 +1,366 @@
+//! # Session Persistence
+//!
+//! Every conversation is stored as a JSONL file under the XDG data directory
+//! (`~/.local/share/alors/sessions/<id>.jsonl`). The first line is a header containing
+//! the session id, the working directory and the `Config` the session was started with.
+//! Every following line is exactly one `Message` of the conversation history, including
+//! the LIF file dumps the model relies on.
+//!
+//! New messages are appended after each turn, so an interrupted session loses at most
+//! the turn that was in flight. If the history was rewritten (e.g. shortened), the file
+//! is rewritten as a whole.
+
+use crate::config::Config;
+use anyhow::{Result, anyhow};
+use openrouter_api::types::chat::Message;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// A single line in a session file.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum SessionRecord {
+    Header {
+        id: String,
+        created_at: u64,
+        cwd: String,
+        config: Config,
+    },
+    Message {
+        message: Message,
+    },
+}
+
+/// A handle to a session file on disk.
+#[derive(Debug)]
+pub struct Session {
+    pub id: String,
+    pub path: PathBuf,
+    pub created_at: u64,
+    pub cwd: String,
+    config: Config,
+    /// The number of messages that are already written to disk.
+    persisted_messages: usize,
+}
+
+/// A session loaded from disk, ready to rebuild an `Agent` from.
+#[derive(Debug)]
+pub struct LoadedSession {
+    pub session: Session,
+    pub config: Config,
+    pub messages: Vec<Message>,
+}
+
+/// A short description of a stored session, used for listing.
+#[derive(Debug)]
+pub struct SessionSummary {
+    pub id: String,
+    pub created_at: u64,
+    pub cwd: String,
+    pub model: String,
+    pub message_count: usize,
+    /// The first line of the first user message, if any.
+    pub first_prompt: Option<String>,
+}
+
+/// Returns the directory where sessions are stored, creating it if necessary.
+pub fn sessions_dir() -> Result<PathBuf> {
+    let xdg_dirs = xdg::BaseDirectories::new();
+    Ok(xdg_dirs.create_data_directory("alors/sessions")?)
+}
+
+impl Session {
+    /// Creates a new, empty session in the default sessions directory.
+    pub fn create(config: &Config) -> Result<Self> {
+        Self::create_in(&sessions_dir()?, config)
+    }
+
+    /// Creates a new, empty session in `dir` and writes its header.
+    pub fn create_in(dir: &Path, config: &Config) -> Result<Self> {
+        let created_at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        // The timestamp prefix keeps ids sortable by creation time.
+        let id = format!(
+            "{created_at}-{}",
+            crate::file_state::generate_random_suffix()
+        );
+        let cwd = std::env::current_dir()
+            .map(|p| p.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        let mut session = Self {
+            path: dir.join(format!("{id}.jsonl")),
+            id,
+            created_at,
+            cwd,
+            config: config.clone(),
+            persisted_messages: 0,
+        };
+        session.rewrite(&[])?;
+        Ok(session)
+    }
+
+    /// Loads the session with the given id from the default sessions directory.
+    pub fn load(id: &str) -> Result<LoadedSession> {
+        if !is_valid_id(id) {
+            return Err(anyhow!(
+                "Invalid session id '{id}'. Use --list-sessions to see the stored sessions."
+            ));
+        }
+        let path = sessions_dir()?.join(format!("{id}.jsonl"));
+        if !path.exists() {
+            return Err(anyhow!("Session '{id}' not found at {}", path.display()));
+        }
+        Self::load_from(&path)
+    }
+
+    /// Loads a session file, returning the session handle, its config and its history.
+    pub fn load_from(path: &Path) -> Result<LoadedSession> {
+        let file = fs::File::open(path)?;
+        let mut lines = BufReader::new(file).lines();
+
+        let header_line = lines
+            .next()
+            .ok_or_else(|| anyhow!("Session file '{}' is empty", path.display()))??;
+        let (id, created_at, cwd, config) = match serde_json::from_str(&header_line)? {
+            SessionRecord::Header {
+                id,
+                created_at,
+                cwd,
+                config,
+            } => (id, created_at, cwd, config),
+            SessionRecord::Message { .. } => {
+                return Err(anyhow!(
+                    "Session file '{}' does not start with a header",
+                    path.display()
+                ));
+            }
+        };
+
+        let mut messages = Vec::new();
+        for (line_number, line) in lines.enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str(&line) {
+                Ok(SessionRecord::Message { message }) => messages.push(message),
+                Ok(SessionRecord::Header { .. }) => {
+                    return Err(anyhow!(
+                        "Unexpected header on line {} of '{}'",
+                        line_number + 2,
+                        path.display()
+                    ));
+                }
+                Err(e) => {
+                    return Err(anyhow!(
+                        "Failed to parse line {} of '{}': {e}",
+                        line_number + 2,
+                        path.display()
+                    ));
+                }
+            }
+        }
+
+        let session = Self {
+            id,
+            path: path.to_path_buf(),
+            created_at,
+            cwd,
+            config: config.clone(),
+            persisted_messages: messages.len(),
+        };
+        Ok(LoadedSession {
+            session,
+            config,
+            messages,
+        })
+    }
+
+    /// Persists the message history.
+    /// New messages are appended. If the history is shorter than what was
+    /// already written, the whole file is rewritten.
+    pub fn save(&mut self, messages: &[Message]) -> Result<()> {
+        if messages.len() < self.persisted_messages {
+            return self.rewrite(messages);
+        }
+        if messages.len() == self.persisted_messages {
+            return Ok(());
+        }
+
+        let mut file = OpenOptions::new().append(true).open(&self.path)?;
+        for message in &messages[self.persisted_messages..] {
+            let record = SessionRecord::Message {
+                message: message.clone(),
+            };
+            writeln!(file, "{}", serde_json::to_string(&record)?)?;
+        }
+        self.persisted_messages = messages.len();
+        Ok(())
+    }
+
+    /// Rewrites the whole session file with the header and the given messages.
+    pub fn rewrite(&mut self, messages: &[Message]) -> Result<()> {
+        let mut content = String::new();
+        let header = SessionRecord::Header {
+            id: self.id.clone(),
+            created_at: self.created_at,
+            cwd: self.cwd.clone(),
+            config: self.config.clone(),
+        };
+        content.push_str(&serde_json::to_string(&header)?);
+        content.push('\n');
+        for message in messages {
+            let record = SessionRecord::Message {
+                message: message.clone(),
+            };
+            content.push_str(&serde_json::to_string(&record)?);
+            content.push('\n');
+        }
+        fs::write(&self.path, content)?;
+        self.persisted_messages = messages.len();
+        Ok(())
+    }
+}
+
+/// Lists all sessions in the default sessions directory, newest first.
+pub fn list_sessions() -> Result<Vec<SessionSummary>> {
+    list_sessions_in(&sessions_dir()?)
+}
+
+/// Lists all sessions in `dir`, newest first. Unreadable files are skipped.
+pub fn list_sessions_in(dir: &Path) -> Result<Vec<SessionSummary>> {
+    let mut summaries = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("jsonl") {
+            continue;
+        }
+        let Ok(loaded) = Session::load_from(&path) else {
+            continue;
+        };
+        let first_prompt = loaded
+            .messages
+            .iter()
+            .find(|m| m.role == "user")
+            .and_then(|m| m.content.lines().next())
+            .map(|line| line.to_string());
+        summaries.push(SessionSummary {
+            id: loaded.session.id,
+            created_at: loaded.session.created_at,
+            cwd: loaded.session.cwd,
+            model: loaded.config.model,
+            message_count: loaded.messages.len(),
+            first_prompt,
+        });
+    }
+    summaries.sort_by(|a, b| b.id.cmp(&a.id));
+    Ok(summaries)
+}
+
+/// Whether `id` has the format of generated ids, `<timestamp>-<suffix>`. Anything else,
+/// like `../x`, could point outside the sessions directory.
+fn is_valid_id(id: &str) -> bool {
+    let Some((timestamp, suffix)) = id.split_once('-') else {
+        return false;
+    };
+    !timestamp.is_empty()
+        && timestamp.chars().all(|c| c.is_ascii_digit())
+        && !suffix.is_empty()
+        && suffix
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit())
+}
+
+/// Returns the id of the most recent session started in `cwd`, if there is one.
+pub fn latest_session_id(cwd: &Path) -> Result<Option<String>> {
+    let cwd = cwd.to_string_lossy();
+    Ok(list_sessions()?
+        .into_iter()
+        .find(|summary| summary.cwd == cwd)
+        .map(|summary| summary.id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scripted_client::message;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_session_roundtrip() {
+        let dir = tempdir().unwrap();
+        let config = Config {
+            model: "test/model".to_string(),
+            ..Default::default()
+        };
+        let mut session = Session::create_in(dir.path(), &config).unwrap();
+
+        let mut messages = vec![message("system", "sys"), message("user", "hello")];
+        session.save(&messages).unwrap();
+        messages.push(message("assistant", "hi there\nsecond line"));
+        session.save(&messages).unwrap();
+
+        let loaded = Session::load_from(&session.path).unwrap();
+        assert_eq!(loaded.session.id, session.id);
+        assert_eq!(loaded.config.model, "test/model");
+        assert_eq!(loaded.messages.len(), 3);
+        assert_eq!(loaded.messages[2].role, "assistant");
+        assert_eq!(loaded.messages[2].content, "hi there\nsecond line");
+    }
+
+    #[test]
+    fn test_session_rewrites_shortened_history() {
+        let dir = tempdir().unwrap();
+        let mut session = Session::create_in(dir.path(), &Config::default()).unwrap();
+
+        let messages = vec![message("user", "one"), message("assistant", "two")];
+        session.save(&messages).unwrap();
+        session.save(&messages[..1]).unwrap();
+
+        let loaded = Session::load_from(&session.path).unwrap();
+        assert_eq!(loaded.messages.len(), 1);
+        assert_eq!(loaded.messages[0].content, "one");
+    }
+
+    #[test]
+    fn test_session_ids_must_have_the_generated_format() {
+        let dir = tempdir().unwrap();
+        let session = Session::create_in(dir.path(), &Config::default()).unwrap();
+        assert!(is_valid_id(&session.id));
+
+        for id in [
+            "../../x",
+            "1700000000-ab/../c",
+            "1700000000-ab.c",
+            "latest",
+            "",
+        ] {
+            assert!(!is_valid_id(id), "{id}");
+            assert!(Session::load(id).is_err());
+        }
+    }
+
+    #[test]
+    fn test_list_sessions_in() {
+        let dir = tempdir().unwrap();
+        let mut session = Session::create_in(dir.path(), &Config::default()).unwrap();
+        session
+            .save(&[
+                message("user", "fix the bug\nplease"),
+                message("assistant", "ok"),
+            ])
+            .unwrap();
+        fs::write(dir.path().join("not-a-session.txt"), "ignored").unwrap();
+
+        let summaries = list_sessions_in(dir.path()).unwrap();
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(summaries[0].id, session.id);
+        assert_eq!(summaries[0].message_count, 2);
+        assert_eq!(summaries[0].first_prompt.as_deref(), Some("fix the bug"));
+    }
+}
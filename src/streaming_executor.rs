@@ -1,119 +1,427 @@
-use anyhow::Result;
-use console::style;
+//! # Streaming Executor
+//!
+//! Collects the responses of chat completion requests into a `ChatResponse`. Streamed
+//! responses are reported to the `EventSink` as they arrive.
+//!
+//! The `usage` block of a response is captured in both modes. When streaming, it
+//! arrives with the last chunk, if the API sends it.
+//!
+//! Reasoning arrives in OpenRouter's `reasoning` field or inline in `<think>` tags. Both
+//! are separated from the answer and reported as `AgentEvent::ReasoningDelta`.
+//!
+//! Rate limits, server errors and dropped connections are transient. `with_retries`
+//! repeats a whole request after such an error, with exponential backoff and jitter, or
+//! as long as the server asks for with `Retry-After`.
+
+use crate::client::ChatResponse;
+use crate::config::Config;
+use crate::events::{AgentEvent, EventSink, TokenUsage};
+use crate::reasoning::{self, Segment, ThinkTagSplitter};
+use crate::sse::SseParser;
+use anyhow::{Result, anyhow};
 use futures::StreamExt;
-use openrouter_api::{
-    OpenRouterClient, Ready,
-    models::tool::{FunctionCall, ToolCall},
-};
-use openrouter_api::{models::tool::ToolCallChunk, types::chat::*};
-use std::collections::HashMap;
-use std::io::{Write, stdout};
-
-pub async fn collect_response_non_streaming(
-    client: &OpenRouterClient<Ready>,
-    mut request: ChatCompletionRequest,
-) -> Result<Option<Message>> {
-    request.stream = Some(false);
-
-    let response = client.chat()?.chat_completion(request).await?;
-
-    if let Some(choice) = response.choices.first() {
-        Ok(Some(choice.message.clone()))
-    } else {
-        Ok(None)
+use openrouter_api::models::tool::{FunctionCall, ToolCall};
+use openrouter_api::types::chat::Message;
+use rand::Rng;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::future::Future;
+use std::time::Duration;
+
+/// A tool call whose chunks are still arriving.
+#[derive(Default)]
+struct PartialToolCall {
+    id: Option<String>,
+    name: String,
+    arguments: String,
+}
+
+/// A `/chat/completions` response. Only the fields the agent uses are parsed.
+#[derive(Deserialize)]
+struct CompletionResponse {
+    /// The model that answered, which differs from the requested one under OpenRouter's
+    /// `models` routing.
+    model: Option<String>,
+    #[serde(default)]
+    choices: Vec<ResponseChoice>,
+    usage: Option<ApiUsage>,
+}
+
+#[derive(Deserialize)]
+struct ResponseChoice {
+    message: ResponseMessage,
+}
+
+#[derive(Deserialize)]
+struct ResponseMessage {
+    content: Option<String>,
+    tool_calls: Option<Vec<ToolCall>>,
+    /// OpenRouter's reasoning of thinking models.
+    reasoning: Option<String>,
+}
+
+/// A chunk of a streamed `/chat/completions` response.
+#[derive(Deserialize)]
+struct CompletionChunk {
+    model: Option<String>,
+    #[serde(default)]
+    choices: Vec<ChunkChoice>,
+    usage: Option<ApiUsage>,
+}
+
+#[derive(Deserialize)]
+struct ChunkChoice {
+    #[serde(default)]
+    delta: Delta,
+}
+
+#[derive(Deserialize, Default)]
+struct Delta {
+    content: Option<String>,
+    reasoning: Option<String>,
+    tool_calls: Option<Vec<ToolCallDelta>>,
+}
+
+#[derive(Deserialize)]
+struct ToolCallDelta {
+    index: u32,
+    id: Option<String>,
+    function: Option<FunctionDelta>,
+}
+
+#[derive(Deserialize)]
+struct FunctionDelta {
+    name: Option<String>,
+    arguments: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ApiUsage {
+    prompt_tokens: u64,
+    completion_tokens: u64,
+    total_tokens: u64,
+}
+
+impl From<ApiUsage> for TokenUsage {
+    /// The cost is calculated by the agent.
+    fn from(usage: ApiUsage) -> Self {
+        TokenUsage {
+            prompt_tokens: usage.prompt_tokens,
+            completion_tokens: usage.completion_tokens,
+            total_tokens: usage.total_tokens,
+            cost: None,
+        }
     }
 }
 
-/// Streams a chat completion request to the console and collects the full response.
+pub async fn collect_response_non_streaming(response: reqwest::Response) -> Result<ChatResponse> {
+    let response: CompletionResponse = serde_json::from_str(&response.text().await?)
+        .map_err(|e| anyhow!("Failed to parse the response: {e}"))?;
+
+    let mut reasoning = None;
+    let message = response.choices.into_iter().next().map(|choice| {
+        let (inline_reasoning, content) =
+            reasoning::split_think_tags(&choice.message.content.unwrap_or_default());
+        reasoning = choice
+            .message
+            .reasoning
+            .filter(|reasoning| !reasoning.trim().is_empty())
+            .or(inline_reasoning);
+        assistant_message(content, choice.message.tool_calls.unwrap_or_default())
+    });
+
+    Ok(ChatResponse {
+        message,
+        usage: response.usage.map(TokenUsage::from),
+        reasoning,
+        model: response.model,
+    })
+}
+
+/// Reads a streamed chat completion, reports the deltas to `events` and collects the
+/// full response.
 pub async fn stream_and_collect_response(
-    client: &OpenRouterClient<Ready>,
-    mut request: ChatCompletionRequest,
-) -> Result<Option<Message>> {
-    request.stream = Some(true);
-    let mut stream = client.chat()?.chat_completion_stream(request);
+    response: reqwest::Response,
+    events: &dyn EventSink,
+) -> Result<ChatResponse> {
+    let mut stream = response.bytes_stream();
+    let mut parser = SseParser::new();
 
     let mut content = String::new();
-    let mut tool_call_chunks: HashMap<u32, ToolCallChunk> = HashMap::new();
-    let mut header_printed = false;
-
-    while let Some(chunk) = stream.next().await {
-        let chunk = chunk?;
-        let choice = chunk.choices.first();
-
-        if let Some(c) = choice.and_then(|c| c.delta.content.as_deref()) {
-            if !header_printed {
-                print!("\n[{}]\n", style("assistant").blue());
-                stdout().flush()?;
-                header_printed = true;
+    let mut reasoning = String::new();
+    let mut think_tags = ThinkTagSplitter::new();
+    let mut tool_call_chunks: BTreeMap<u32, PartialToolCall> = BTreeMap::new();
+    let mut usage = None;
+    let mut model = None;
+
+    while let Some(bytes) = stream.next().await {
+        for event in parser.push(&bytes?) {
+            if event.data == "[DONE]" {
+                continue;
             }
-            print!("{c}");
-            stdout().flush()?;
-            content.push_str(c);
-        }
+            let chunk: CompletionChunk = serde_json::from_str(&event.data)
+                .map_err(|e| anyhow!("Failed to parse stream chunk: {e}"))?;
+            if let Some(chunk_usage) = chunk.usage {
+                usage = Some(TokenUsage::from(chunk_usage));
+            }
+            if chunk.model.is_some() {
+                model = chunk.model;
+            }
+            let Some(delta) = chunk.choices.into_iter().next().map(|c| c.delta) else {
+                continue;
+            };
 
-        if let Some(delta_tool_calls) = choice.and_then(|c| c.delta.tool_calls.as_deref()) {
-            for tool_call_chunk in delta_tool_calls {
-                let entry = tool_call_chunks
-                    .entry(tool_call_chunk.index)
-                    .or_insert_with(|| ToolCallChunk {
+            if let Some(text) = delta.reasoning.filter(|text| !text.is_empty()) {
+                collect_segment(
+                    Segment::Reasoning(text),
+                    &mut content,
+                    &mut reasoning,
+                    events,
+                );
+            }
+
+            if let Some(c) = &delta.content {
+                for segment in think_tags.push(c) {
+                    collect_segment(segment, &mut content, &mut reasoning, events);
+                }
+            }
+
+            if let Some(delta_tool_calls) = delta.tool_calls {
+                for tool_call_chunk in delta_tool_calls {
+                    let name = tool_call_chunk
+                        .function
+                        .as_ref()
+                        .and_then(|f| f.name.clone());
+                    let arguments = tool_call_chunk
+                        .function
+                        .as_ref()
+                        .and_then(|f| f.arguments.clone())
+                        .unwrap_or_default();
+                    events.emit(AgentEvent::ToolCallDelta {
                         index: tool_call_chunk.index,
-                        id: None,
-                        kind: None,
-                        function: None,
+                        id: tool_call_chunk.id.clone(),
+                        name: name.clone(),
+                        arguments: arguments.clone(),
                     });
 
-                if let Some(id) = &tool_call_chunk.id {
-                    entry.id = Some(id.clone());
-                }
-                if let Some(kind) = &tool_call_chunk.kind {
-                    entry.kind = Some(kind.clone());
-                }
-                if let Some(function_chunk) = &tool_call_chunk.function {
-                    let function_entry = entry.function.get_or_insert(
-                        openrouter_api::models::tool::FunctionCallChunk {
-                            name: None,
-                            arguments: None,
-                        },
-                    );
-
-                    if let Some(name) = &function_chunk.name {
-                        function_entry.name = Some(name.clone());
+                    let entry = tool_call_chunks.entry(tool_call_chunk.index).or_default();
+                    if let Some(id) = &tool_call_chunk.id {
+                        entry.id = Some(id.clone());
                     }
-                    if let Some(arguments) = &function_chunk.arguments {
-                        let current_args = function_entry.arguments.get_or_insert_with(String::new);
-                        current_args.push_str(arguments);
+                    if let Some(name) = name {
+                        entry.name = name;
                     }
+                    entry.arguments.push_str(&arguments);
                 }
             }
         }
     }
 
-    if header_printed {
-        println!();
+    for segment in think_tags.finish() {
+        collect_segment(segment, &mut content, &mut reasoning, events);
+    }
+    let reasoning = Some(reasoning.trim().to_string()).filter(|r| !r.is_empty());
+    if reasoning.is_some() {
+        // The answer usually starts with blank lines after a `</think>` tag.
+        content = content.trim_start().to_string();
     }
 
-    let mut tool_calls = tool_call_chunks
+    // Ordered by stream index. Missing ids are synthesized by the agent.
+    let tool_calls = tool_call_chunks
         .into_values()
-        .map(|chunk| {
-            let function_chunk = chunk.function.unwrap_or_default();
-            ToolCall {
-                id: chunk.id.unwrap_or_default(),
-                kind: chunk.kind.unwrap_or("function".to_string()),
-                function_call: FunctionCall {
-                    name: function_chunk.name.unwrap_or_default(),
-                    arguments: function_chunk.arguments.unwrap_or_default(),
-                },
-            }
+        .map(|chunk| ToolCall {
+            id: chunk.id.unwrap_or_default(),
+            kind: "function".to_string(),
+            function_call: FunctionCall {
+                name: chunk.name,
+                arguments: chunk.arguments,
+            },
         })
         .collect::<Vec<ToolCall>>();
 
-    tool_calls.sort_by_key(|tc| tc.id.clone());
+    let message = if content.is_empty() && tool_calls.is_empty() {
+        None
+    } else {
+        Some(assistant_message(content, tool_calls))
+    };
+    Ok(ChatResponse {
+        message,
+        usage,
+        reasoning,
+        model,
+    })
+}
+
+/// Reports a segment of streamed content and appends it to the answer or the reasoning.
+fn collect_segment(
+    segment: Segment,
+    content: &mut String,
+    reasoning: &mut String,
+    events: &dyn EventSink,
+) {
+    match segment {
+        Segment::Text(text) => {
+            content.push_str(&text);
+            events.emit(AgentEvent::TextDelta { text });
+        }
+        Segment::Reasoning(text) => {
+            reasoning.push_str(&text);
+            events.emit(AgentEvent::ReasoningDelta { text });
+        }
+    }
+}
+
+/// An error that is worth retrying: a rate limit, a server error or a dropped connection.
+#[derive(Debug)]
+pub struct TransientError {
+    pub message: String,
+    /// How long the server asked to wait, from the `Retry-After` header.
+    pub retry_after: Option<Duration>,
+}
+
+impl TransientError {
+    pub fn new(message: String) -> Self {
+        Self {
+            message,
+            retry_after: None,
+        }
+    }
+}
+
+impl fmt::Display for TransientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for TransientError {}
+
+/// Converts an unsuccessful HTTP response into an error, which is a `TransientError`
+/// for rate limits and server errors.
+pub async fn error_from_response(response: reqwest::Response, api_name: &str) -> anyhow::Error {
+    let status = response.status();
+    let retry_after = response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| parse_retry_after(value, chrono::Utc::now()));
+    let body = response.text().await.unwrap_or_default();
+    let message = format!("{api_name} error ({status}): {body}");
+
+    if is_transient_status(status) {
+        TransientError {
+            message,
+            retry_after,
+        }
+        .into()
+    } else {
+        anyhow!(message)
+    }
+}
+
+/// Parses a `Retry-After` value, either a number of seconds or an HTTP date like
+/// `Wed, 21 Oct 2015 07:28:00 GMT`. A date in the past means no delay.
+fn parse_retry_after(value: &str, now: chrono::DateTime<chrono::Utc>) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    Some((date.to_utc() - now).to_std().unwrap_or(Duration::ZERO))
+}
+
+fn is_transient_status(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// How often and how long to wait before a failed request is repeated.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// The number of retries after the first attempt.
+    pub max_retries: u32,
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            max_retries: config.max_retries,
+            initial_delay: Duration::from_millis(config.retry_initial_delay_ms),
+            max_delay: Duration::from_millis(config.retry_max_delay_ms),
+        }
+    }
+
+    /// The delay before the given retry (starting at 0): exponential backoff, capped at
+    /// `max_delay`, with a random jitter of up to half of it.
+    fn delay(&self, retry: u32) -> Duration {
+        let backoff = self
+            .initial_delay
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(self.max_delay);
+        let jitter = rand::thread_rng().gen_range(0.5..=1.0);
+        backoff.mul_f64(jitter)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::from_config(&Config::default())
+    }
+}
+
+/// Runs `attempt` until it succeeds, fails with an error that is not transient, or the
+/// retries of `policy` are used up. Every retry is announced as `AgentEvent::Retrying`.
+pub async fn with_retries<T, F, Fut>(
+    policy: &RetryPolicy,
+    events: &dyn EventSink,
+    mut attempt: F,
+) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let mut retry = 0;
+    loop {
+        let error = match attempt().await {
+            Ok(value) => return Ok(value),
+            Err(error) => error,
+        };
+        if retry >= policy.max_retries || !is_transient(&error) {
+            return Err(error);
+        }
 
-    if content.is_empty() && tool_calls.is_empty() {
-        return Ok(None);
+        // A `Retry-After` beyond `max_delay` is not waited out in full.
+        let delay = error
+            .downcast_ref::<TransientError>()
+            .and_then(|transient| transient.retry_after)
+            .map(|retry_after| retry_after.min(policy.max_delay))
+            .unwrap_or_else(|| policy.delay(retry));
+        retry += 1;
+        events.emit(AgentEvent::Retrying {
+            attempt: retry,
+            max_retries: policy.max_retries,
+            delay_ms: delay.as_millis() as u64,
+            error: error.to_string(),
+        });
+        tokio::time::sleep(delay).await;
     }
+}
+
+/// Whether the error is a `TransientError` or a network error.
+fn is_transient(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        cause.is::<TransientError>()
+            || cause
+                .downcast_ref::<reqwest::Error>()
+                .is_some_and(|e| e.is_timeout() || e.is_connect() || e.is_request() || e.is_body())
+    })
+}
 
-    Ok(Some(Message {
+fn assistant_message(content: String, tool_calls: Vec<ToolCall>) -> Message {
+    Message {
         role: "assistant".to_string(),
         content,
         tool_calls: if tool_calls.is_empty() {
@@ -123,5 +431,81 @@ pub async fn stream_and_collect_response(
         },
         name: None,
         tool_call_id: None,
-    }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_delay_grows_exponentially_with_jitter_and_is_capped() {
+        let policy = RetryPolicy {
+            max_retries: 10,
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(1000),
+        };
+
+        for _ in 0..20 {
+            let first = policy.delay(0);
+            assert!(first >= Duration::from_millis(50) && first <= Duration::from_millis(100));
+            let third = policy.delay(2);
+            assert!(third >= Duration::from_millis(200) && third <= Duration::from_millis(400));
+            assert!(policy.delay(8) <= Duration::from_millis(1000));
+        }
+    }
+
+    #[test]
+    fn test_retry_after_in_seconds_or_as_http_date() {
+        let now = chrono::DateTime::parse_from_rfc3339("2015-10-21T07:28:00Z")
+            .unwrap()
+            .to_utc();
+
+        assert_eq!(
+            parse_retry_after(" 120 ", now),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:30 GMT", now),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:00:00 GMT", now),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(parse_retry_after("soon", now), None);
+    }
+
+    #[tokio::test]
+    async fn test_retry_after_is_capped_at_max_delay() {
+        let policy = RetryPolicy {
+            max_retries: 1,
+            initial_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(10),
+        };
+        let (events_tx, mut events_rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut attempts = 0;
+
+        let result = with_retries(&policy, &events_tx, || {
+            attempts += 1;
+            let first = attempts == 1;
+            async move {
+                if first {
+                    return Err(TransientError {
+                        message: "rate limited".to_string(),
+                        retry_after: Some(Duration::from_secs(3600)),
+                    }
+                    .into());
+                }
+                Ok(())
+            }
+        })
+        .await;
+
+        assert!(result.is_ok());
+        match events_rx.try_recv() {
+            Ok(AgentEvent::Retrying { delay_ms, .. }) => assert_eq!(delay_ms, 10),
+            _ => panic!("expected a Retrying event"),
+        }
+    }
 }
@@ -0,0 +1,388 @@
+//! # Text-Based Tool Calls
+//!
+//! Many local models ignore the `tools` field of a request and write their tool calls
+//! as plain text instead. For the models listed in `text_tool_call_models`, the tools
+//! are described in the system prompt, and tool calls are parsed from the answer.
+//!
+//! The history keeps native tool calls and results, so the rest of the agent does not
+//! notice the difference. Only the request is converted: tool calls are written back as
+//! `<tool_call>` blocks and tool results are sent as user messages.
+//!
+//! Recognized tool calls, with `{"name": ..., "arguments": {...}}` as JSON:
+//! - JSON in `<tool_call>` blocks (Hermes, Qwen),
+//! - XML in `<tool_call>` blocks: `<function=name><parameter=key>value</parameter></function>`,
+//! - JSON in fenced code blocks or as the whole answer, if it names a known tool.
+
+use openrouter_api::models::tool::{FunctionCall, Tool as ApiTool, ToolCall};
+use openrouter_api::types::chat::Message;
+use serde_json::{Map, Value};
+
+const TOOL_CALL_START: &str = "<tool_call>";
+const TOOL_CALL_END: &str = "</tool_call>";
+
+/// Describes the tools and the expected tool call format, for the system prompt.
+pub fn tools_prompt(tools: &[ApiTool]) -> String {
+    let mut functions: Vec<_> = tools
+        .iter()
+        .map(|tool| match tool {
+            ApiTool::Function { function } => function,
+        })
+        .collect();
+    // The order of the schemas is random, but the prompt should be cacheable.
+    functions.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut prompt = format!(
+        "<tools>
+You can call the following tools. To call a tool, write a block like this, with one block per call:
+
+{TOOL_CALL_START}
+{{\"name\": \"tool_name\", \"arguments\": {{\"parameter\": \"value\"}}}}
+{TOOL_CALL_END}
+
+The arguments must be valid JSON that matches the parameters of the tool. After your tool calls, stop and wait: the results are returned in <tool_response> blocks.
+"
+    );
+    for function in functions {
+        prompt.push_str(&format!(
+            "\n## {}\n{}\nParameters: {}\n",
+            function.name,
+            function.description.as_deref().unwrap_or_default(),
+            function.parameters
+        ));
+    }
+    prompt.push_str("</tools>");
+    prompt
+}
+
+/// Converts the history for a model without native tool calling: the tools are described
+/// in the system prompt, tool calls become text and tool results become user messages.
+pub fn to_text_messages(messages: &[Message], tools: &[ApiTool]) -> Vec<Message> {
+    let tools_prompt = tools_prompt(tools);
+    let mut converted: Vec<Message> = Vec::with_capacity(messages.len() + 1);
+    if messages.first().is_none_or(|m| m.role != "system") {
+        converted.push(text_message("system", tools_prompt.clone()));
+    }
+
+    for (index, message) in messages.iter().enumerate() {
+        match message.role.as_str() {
+            "system" if index == 0 => converted.push(text_message(
+                "system",
+                format!("{}\n\n{tools_prompt}", message.content),
+            )),
+            "assistant" => {
+                let mut content = message.content.clone();
+                for tool_call in message.tool_calls.iter().flatten() {
+                    if !content.is_empty() {
+                        content.push_str("\n\n");
+                    }
+                    content.push_str(&format_tool_call(tool_call));
+                }
+                converted.push(text_message("assistant", content));
+            }
+            "tool" => {
+                let response = format!(
+                    "<tool_response name=\"{}\">\n{}\n</tool_response>",
+                    message.name.as_deref().unwrap_or("tool"),
+                    message.content
+                );
+                // The results of one assistant message are sent together.
+                let previous = converted.last_mut().filter(|last| {
+                    last.role == "user" && last.content.ends_with("</tool_response>")
+                });
+                match previous {
+                    Some(previous) => {
+                        previous.content.push_str("\n\n");
+                        previous.content.push_str(&response);
+                    }
+                    None => converted.push(text_message("user", response)),
+                }
+            }
+            _ => converted.push(message.clone()),
+        }
+    }
+    converted
+}
+
+/// Extracts the tool calls from an answer. Returns the remaining text and the calls,
+/// with ids made of `id_prefix` and their position.
+pub fn parse_tool_calls(
+    content: &str,
+    tools: &[ApiTool],
+    id_prefix: &str,
+) -> (String, Vec<ToolCall>) {
+    let known_names: Vec<&str> = tools
+        .iter()
+        .map(|tool| match tool {
+            ApiTool::Function { function } => function.name.as_str(),
+        })
+        .collect();
+
+    let (mut text, mut calls) = extract_tagged_calls(content);
+    if calls.is_empty() {
+        (text, calls) = extract_fenced_calls(content, &known_names);
+    }
+    if calls.is_empty() {
+        let bare_calls = parse_json_calls(content.trim()).filter(|calls| {
+            calls
+                .iter()
+                .all(|(name, _)| known_names.contains(&name.as_str()))
+        });
+        if let Some(bare_calls) = bare_calls {
+            (text, calls) = (String::new(), bare_calls);
+        }
+    }
+
+    let tool_calls = calls
+        .into_iter()
+        .enumerate()
+        .map(|(index, (name, arguments))| ToolCall {
+            id: format!("{id_prefix}_{index}"),
+            kind: "function".to_string(),
+            function_call: FunctionCall { name, arguments },
+        })
+        .collect();
+    (text.trim().to_string(), tool_calls)
+}
+
+/// Finds `<tool_call>` blocks. An unclosed block at the end is accepted, since models
+/// often stop right before the closing tag.
+fn extract_tagged_calls(content: &str) -> (String, Vec<(String, String)>) {
+    let mut text = String::new();
+    let mut calls = Vec::new();
+    let mut rest = content;
+    while let Some(start) = rest.find(TOOL_CALL_START) {
+        text.push_str(&rest[..start]);
+        let after_start = &rest[start + TOOL_CALL_START.len()..];
+        let (block, after_block) = match after_start.find(TOOL_CALL_END) {
+            Some(end) => (
+                &after_start[..end],
+                &after_start[end + TOOL_CALL_END.len()..],
+            ),
+            None => (after_start, ""),
+        };
+        let block = strip_code_fence(block.trim());
+        match parse_json_calls(block).or_else(|| parse_xml_call(block).map(|call| vec![call])) {
+            Some(parsed) => calls.extend(parsed),
+            // Keep what could not be understood, so the user can see it.
+            None => text.push_str(&rest[start..rest.len() - after_block.len()]),
+        }
+        rest = after_block;
+    }
+    text.push_str(rest);
+    (text, calls)
+}
+
+/// Finds fenced code blocks that contain tool calls of known tools.
+fn extract_fenced_calls(content: &str, known_names: &[&str]) -> (String, Vec<(String, String)>) {
+    let mut text = String::new();
+    let mut calls = Vec::new();
+    let mut rest = content;
+    while let Some(start) = rest.find("```") {
+        let after_fence = &rest[start + 3..];
+        let Some(end) = after_fence.find("```") else {
+            break;
+        };
+        let block = &rest[start..start + 3 + end + 3];
+        let parsed = parse_json_calls(strip_code_fence(block)).filter(|parsed| {
+            parsed
+                .iter()
+                .all(|(name, _)| known_names.contains(&name.as_str()))
+        });
+        text.push_str(&rest[..start]);
+        match parsed {
+            Some(parsed) => calls.extend(parsed),
+            None => text.push_str(block),
+        }
+        rest = &rest[start + block.len()..];
+    }
+    text.push_str(rest);
+    (text, calls)
+}
+
+/// Removes a surrounding Markdown code fence, with or without a language tag.
+fn strip_code_fence(block: &str) -> &str {
+    let Some(inner) = block
+        .strip_prefix("```")
+        .and_then(|inner| inner.strip_suffix("```"))
+    else {
+        return block;
+    };
+    // Skip the language tag on the first line.
+    match inner.split_once('\n') {
+        Some((tag, body)) if !tag.trim_start().starts_with(['{', '[']) => body.trim(),
+        _ => inner.trim(),
+    }
+}
+
+/// Parses a JSON tool call or an array of them.
+fn parse_json_calls(text: &str) -> Option<Vec<(String, String)>> {
+    match serde_json::from_str::<Value>(text).ok()? {
+        Value::Array(values) if !values.is_empty() => {
+            values.iter().map(json_call).collect::<Option<Vec<_>>>()
+        }
+        value => json_call(&value).map(|call| vec![call]),
+    }
+}
+
+/// Reads `{"name", "arguments"}`, also accepting `parameters` and the OpenAI shape
+/// `{"function": {"name", "arguments"}}`.
+fn json_call(value: &Value) -> Option<(String, String)> {
+    let value = value
+        .get("function")
+        .filter(|f| f.is_object())
+        .unwrap_or(value);
+    let name = value.get("name")?.as_str()?.to_string();
+    let arguments = match value.get("arguments").or_else(|| value.get("parameters")) {
+        Some(Value::String(arguments)) => arguments.clone(),
+        Some(arguments) => arguments.to_string(),
+        None => "{}".to_string(),
+    };
+    Some((name, arguments))
+}
+
+/// Parses `<function=name><parameter=key>value</parameter>...</function>`. Parameter
+/// values that are valid JSON are taken as JSON, anything else as a string.
+fn parse_xml_call(block: &str) -> Option<(String, String)> {
+    let after_function = &block[block.find("<function=")? + "<function=".len()..];
+    let name_end = after_function.find('>')?;
+    let name = after_function[..name_end].trim().to_string();
+
+    let mut arguments = Map::new();
+    let mut rest = &after_function[name_end + 1..];
+    while let Some(start) = rest.find("<parameter=") {
+        let after_parameter = &rest[start + "<parameter=".len()..];
+        let key_end = after_parameter.find('>')?;
+        let key = after_parameter[..key_end].trim().to_string();
+        let after_key = &after_parameter[key_end + 1..];
+        let value_end = after_key.find("</parameter>").unwrap_or(after_key.len());
+        let raw_value = after_key[..value_end].trim_matches('\n');
+        let value = serde_json::from_str(raw_value.trim())
+            .unwrap_or_else(|_| Value::String(raw_value.to_string()));
+        arguments.insert(key, value);
+        rest = &after_key[value_end..];
+    }
+    Some((name, Value::Object(arguments).to_string()))
+}
+
+fn format_tool_call(tool_call: &ToolCall) -> String {
+    let arguments = serde_json::from_str::<Value>(&tool_call.function_call.arguments)
+        .unwrap_or_else(|_| Value::String(tool_call.function_call.arguments.clone()));
+    let call = serde_json::json!({
+        "name": tool_call.function_call.name,
+        "arguments": arguments,
+    });
+    format!("{TOOL_CALL_START}\n{call}\n{TOOL_CALL_END}")
+}
+
+fn text_message(role: &str, content: String) -> Message {
+    Message {
+        role: role.to_string(),
+        content,
+        name: None,
+        tool_calls: None,
+        tool_call_id: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openrouter_api::models::tool::FunctionDescription;
+    use serde_json::json;
+
+    fn tools() -> Vec<ApiTool> {
+        ["list_files", "read_files"]
+            .into_iter()
+            .map(|name| ApiTool::Function {
+                function: FunctionDescription {
+                    name: name.to_string(),
+                    description: Some(format!("The {name} tool.")),
+                    strict: None,
+                    parameters: json!({ "type": "object" }),
+                },
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_parses_json_and_xml_tool_call_blocks() {
+        let content = r#"Let me look.
+<tool_call>
+{"name": "list_files", "arguments": {"path": "src"}}
+</tool_call>
+<tool_call>
+<function=read_files>
+<parameter=file_paths>
+["src/main.rs"]
+</parameter>
+</function>
+</tool_call>"#;
+
+        let (text, tool_calls) = parse_tool_calls(content, &tools(), "call_3");
+
+        assert_eq!(text, "Let me look.");
+        assert_eq!(tool_calls.len(), 2);
+        assert_eq!(tool_calls[0].id, "call_3_0");
+        assert_eq!(tool_calls[0].function_call.name, "list_files");
+        assert_eq!(tool_calls[0].function_call.arguments, r#"{"path":"src"}"#);
+        assert_eq!(tool_calls[1].function_call.name, "read_files");
+        assert_eq!(
+            tool_calls[1].function_call.arguments,
+            r#"{"file_paths":["src/main.rs"]}"#
+        );
+    }
+
+    #[test]
+    fn test_fenced_json_is_only_a_tool_call_for_known_tools() {
+        let content = "```json\n{\"name\": \"list_files\", \"arguments\": {\"path\": \".\"}}\n```";
+        let (text, tool_calls) = parse_tool_calls(content, &tools(), "call");
+        assert_eq!(text, "");
+        assert_eq!(tool_calls[0].function_call.name, "list_files");
+
+        let content = "Example:\n```json\n{\"name\": \"Alice\"}\n```";
+        let (text, tool_calls) = parse_tool_calls(content, &tools(), "call");
+        assert_eq!(text, content);
+        assert!(tool_calls.is_empty());
+    }
+
+    #[test]
+    fn test_history_is_converted_to_text() {
+        let tool_call = ToolCall {
+            id: "call_1".to_string(),
+            kind: "function".to_string(),
+            function_call: FunctionCall {
+                name: "list_files".to_string(),
+                arguments: r#"{"path":"."}"#.to_string(),
+            },
+        };
+        let messages = vec![
+            text_message("system", "Be brief.".to_string()),
+            text_message("user", "What is here?".to_string()),
+            Message {
+                tool_calls: Some(vec![tool_call]),
+                ..text_message("assistant", String::new())
+            },
+            Message {
+                name: Some("list_files".to_string()),
+                tool_call_id: Some("call_1".to_string()),
+                ..text_message("tool", "README.md".to_string())
+            },
+        ];
+
+        let converted = to_text_messages(&messages, &tools());
+
+        let roles: Vec<&str> = converted.iter().map(|m| m.role.as_str()).collect();
+        assert_eq!(roles, ["system", "user", "assistant", "user"]);
+        assert!(converted[0].content.starts_with("Be brief.\n\n<tools>"));
+        assert!(converted[0].content.contains("## read_files"));
+        assert_eq!(
+            converted[2].content,
+            "<tool_call>\n{\"name\":\"list_files\",\"arguments\":{\"path\":\".\"}}\n</tool_call>"
+        );
+        assert!(converted[2].tool_calls.is_none());
+        assert_eq!(
+            converted[3].content,
+            "<tool_response name=\"list_files\">\nREADME.md\n</tool_response>"
+        );
+    }
+}
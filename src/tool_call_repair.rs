@@ -0,0 +1,297 @@
+//! # Tool Call Normalization
+//!
+//! Weak models and some providers produce tool calls that are almost, but not quite,
+//! valid: arguments with trailing commas, single quotes or code fences, missing or
+//! duplicate ids. Before the tool calls of a response are added to the
+//! history and dispatched, they are normalized:
+//!
+//! - Arguments are parsed leniently and rewritten as valid JSON.
+//! - Missing and duplicate ids are replaced by synthesized ones.
+//!
+//! Every repair is reported, so it can be logged. Arguments that cannot be repaired are
+//! left as they are; the tool then reports the parse error to the model.
+//!
+//! Truncated arguments, usually cut off by the output token limit, are never completed:
+//! a file written from them would silently lose its end. The model is asked to repeat
+//! the call instead.
+
+use openrouter_api::models::tool::ToolCall;
+use serde_json::Value;
+use std::collections::HashSet;
+
+/// A tool call that was changed during normalization.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RepairedToolCall {
+    /// The tool call after normalization.
+    pub tool_call: ToolCall,
+    /// What was repaired, e.g. "removed trailing commas".
+    pub repairs: Vec<String>,
+}
+
+/// Repairs the arguments and ids of the tool calls in place. Synthesized ids are made of
+/// `id_prefix` and the position of the call. Returns the calls that were changed.
+pub fn normalize_tool_calls(tool_calls: &mut [ToolCall], id_prefix: &str) -> Vec<RepairedToolCall> {
+    let mut seen_ids = HashSet::new();
+    let mut repaired = Vec::new();
+    for (index, tool_call) in tool_calls.iter_mut().enumerate() {
+        let mut repairs = Vec::new();
+
+        if tool_call.id.trim().is_empty() {
+            tool_call.id = format!("{id_prefix}_{index}");
+            repairs.push("added a missing id".to_string());
+        } else if seen_ids.contains(&tool_call.id) {
+            tool_call.id = format!("{id_prefix}_{index}");
+            repairs.push("replaced a duplicate id".to_string());
+        }
+        seen_ids.insert(tool_call.id.clone());
+
+        if tool_call.kind.is_empty() {
+            tool_call.kind = "function".to_string();
+        }
+
+        if let Some((arguments, argument_repairs)) = repair_json(&tool_call.function_call.arguments)
+        {
+            if !argument_repairs.is_empty() {
+                tool_call.function_call.arguments = arguments.to_string();
+                repairs.extend(argument_repairs.into_iter().map(str::to_string));
+            }
+        }
+
+        if !repairs.is_empty() {
+            repaired.push(RepairedToolCall {
+                tool_call: tool_call.clone(),
+                repairs,
+            });
+        }
+    }
+    repaired
+}
+
+/// Parses JSON arguments leniently. Returns the value and the repairs that were needed
+/// (none for valid JSON), or `None` if the arguments could not be repaired.
+pub fn repair_json(raw: &str) -> Option<(Value, Vec<&'static str>)> {
+    let mut repairs = Vec::new();
+    let mut text = raw.trim();
+
+    if text.is_empty() {
+        return Some((
+            Value::Object(Default::default()),
+            vec!["replaced empty arguments with {}"],
+        ));
+    }
+    if let Ok(value) = serde_json::from_str::<Value>(raw) {
+        return match value {
+            // Some models encode the arguments twice.
+            Value::String(inner) => match serde_json::from_str::<Value>(&inner) {
+                Ok(object @ Value::Object(_)) => {
+                    Some((object, vec!["decoded arguments that were encoded twice"]))
+                }
+                _ => Some((Value::String(inner), repairs)),
+            },
+            value => Some((value, repairs)),
+        };
+    }
+
+    if let Some(inner) = strip_code_fence(text) {
+        text = inner;
+        repairs.push("removed a code fence");
+        if let Ok(value) = serde_json::from_str::<Value>(text) {
+            return Some((value, repairs));
+        }
+    }
+
+    let (fixed, truncated) = fix_syntax(text, &mut repairs);
+    if truncated {
+        return None;
+    }
+    let mut values = serde_json::Deserializer::from_str(&fixed).into_iter::<Value>();
+    let value = values.next()?.ok()?;
+    if !fixed[values.byte_offset()..].trim().is_empty() {
+        repairs.push("ignored text after the arguments");
+    }
+    if repairs.is_empty() {
+        return None;
+    }
+    Some((value, repairs))
+}
+
+fn strip_code_fence(text: &str) -> Option<&str> {
+    let inner = text.strip_prefix("```")?;
+    let inner = inner.strip_suffix("```").unwrap_or(inner);
+    // Skip a language tag like `json`.
+    let inner = match inner.split_once('\n') {
+        Some((tag, body)) if !tag.trim_start().starts_with(['{', '[']) => body,
+        _ => inner,
+    };
+    Some(inner.trim())
+}
+
+/// Whether the arguments end inside a string, object or array.
+pub fn is_truncated(raw: &str) -> bool {
+    let text = raw.trim();
+    let text = strip_code_fence(text).unwrap_or(text);
+    fix_syntax(text, &mut Vec::new()).1
+}
+
+/// Fixes single-quoted strings, unescaped control characters and trailing commas,
+/// recording each kind of repair once. Also returns whether the text ends inside a
+/// string, object or array.
+fn fix_syntax(text: &str, repairs: &mut Vec<&'static str>) -> (String, bool) {
+    let mut record = |repair: &'static str| {
+        if !repairs.contains(&repair) {
+            repairs.push(repair);
+        }
+    };
+
+    let mut fixed = String::with_capacity(text.len() + 8);
+    let mut closers = Vec::new();
+    // The delimiter of the string the scanner is in, if any.
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+
+    for c in text.chars() {
+        if let Some(delimiter) = quote {
+            if escaped {
+                escaped = false;
+                // `\'` is not a valid escape in JSON.
+                if c != '\'' {
+                    fixed.push('\\');
+                }
+                fixed.push(c);
+                continue;
+            }
+            match c {
+                '\\' => escaped = true,
+                c if c == delimiter => {
+                    fixed.push('"');
+                    quote = None;
+                }
+                '"' => fixed.push_str("\\\""),
+                '\n' | '\r' | '\t' => {
+                    record("escaped control characters in strings");
+                    fixed.push_str(match c {
+                        '\n' => "\\n",
+                        '\r' => "\\r",
+                        _ => "\\t",
+                    });
+                }
+                c => fixed.push(c),
+            }
+            continue;
+        }
+
+        match c {
+            '"' => {
+                quote = Some('"');
+                fixed.push('"');
+            }
+            '\'' => {
+                record("replaced single quotes");
+                quote = Some('\'');
+                fixed.push('"');
+            }
+            '{' | '[' => {
+                closers.push(if c == '{' { '}' } else { ']' });
+                fixed.push(c);
+            }
+            '}' | ']' => {
+                if remove_trailing_comma(&mut fixed) {
+                    record("removed trailing commas");
+                }
+                closers.pop();
+                fixed.push(c);
+            }
+            c => fixed.push(c),
+        }
+    }
+
+    let truncated = quote.is_some() || !closers.is_empty();
+    (fixed, truncated)
+}
+
+/// Removes a comma (and the whitespace after it) at the end of `text`.
+fn remove_trailing_comma(text: &mut String) -> bool {
+    let trimmed_len = text.trim_end().len();
+    if !text[..trimmed_len].ends_with(',') {
+        return false;
+    }
+    text.truncate(trimmed_len - 1);
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openrouter_api::models::tool::FunctionCall;
+    use serde_json::json;
+
+    fn repaired(raw: &str) -> Value {
+        repair_json(raw).expect("repairable").0
+    }
+
+    #[test]
+    fn test_repairs_common_json_mistakes() {
+        assert_eq!(
+            repair_json(r#"{"a": 1}"#),
+            Some((json!({ "a": 1 }), vec![]))
+        );
+        assert_eq!(repaired(""), json!({}));
+        assert_eq!(repaired(r#"{"a": [1, 2,],}"#), json!({ "a": [1, 2] }));
+        assert_eq!(
+            repaired("{'path': 'it\\'s \"here\"'}"),
+            json!({ "path": "it's \"here\"" })
+        );
+        assert_eq!(
+            repaired("```json\n{\"path\": \".\"}\n```"),
+            json!({ "path": "." })
+        );
+        assert_eq!(repaired(r#""{\"path\": \".\"}""#), json!({ "path": "." }));
+        assert_eq!(
+            repaired("{\"content\": \"line 1\nline 2\"}"),
+            json!({ "content": "line 1\nline 2" })
+        );
+        assert_eq!(repaired(r#"{"a": 1}}"#), json!({ "a": 1 }));
+        assert_eq!(repair_json("not json at all"), None);
+    }
+
+    #[test]
+    fn test_truncated_arguments_are_not_completed() {
+        let truncated = r#"{"files": [{"path": "a.txt", "content": "hel"#;
+
+        assert_eq!(repair_json(truncated), None);
+        assert!(is_truncated(truncated));
+        assert_eq!(repair_json(r#"{"a": 1, "b":"#), None);
+        assert!(is_truncated(r#"{"a": 1, "b":"#));
+        assert!(!is_truncated(r#"{"a": 1,}"#));
+        assert!(!is_truncated("not json at all"));
+    }
+
+    #[test]
+    fn test_normalize_synthesizes_ids_and_logs_repairs() {
+        let call = |id: &str, arguments: &str| ToolCall {
+            id: id.to_string(),
+            kind: "function".to_string(),
+            function_call: FunctionCall {
+                name: "list_files".to_string(),
+                arguments: arguments.to_string(),
+            },
+        };
+        let mut tool_calls = vec![
+            call("call_a", r#"{"path": "."}"#),
+            call("", r#"{"path": "src",}"#),
+            call("call_a", r#"{"path": "tests"}"#),
+        ];
+
+        let repaired = normalize_tool_calls(&mut tool_calls, "call_7");
+
+        let ids: Vec<&str> = tool_calls.iter().map(|tc| tc.id.as_str()).collect();
+        assert_eq!(ids, ["call_a", "call_7_1", "call_7_2"]);
+        assert_eq!(tool_calls[1].function_call.arguments, r#"{"path":"src"}"#);
+        assert_eq!(repaired.len(), 2);
+        assert_eq!(
+            repaired[0].repairs,
+            ["added a missing id", "removed trailing commas"]
+        );
+        assert_eq!(repaired[1].repairs, ["replaced a duplicate id"]);
+    }
+}
@@ -4,9 +4,14 @@
 //! It maintains a registry of all available tools and dispatches calls to the appropriate
 //! implementation based on the tool name.
 
-use crate::{config::Config, file_state_manager::FileStateManager, tools::Tool};
+use crate::{
+    config::Config,
+    events::{AgentEvent, EventSink},
+    file_state_manager::FileStateManager,
+    tool_call_repair,
+    tools::Tool,
+};
 use anyhow::{Result, anyhow};
-use console::style;
 use openrouter_api::{
     models::tool::{Tool as ApiTool, ToolCall},
     types::chat::Message,
@@ -46,6 +51,17 @@ impl ToolCollection {
             .collect()
     }
 
+    /// Gathers the schemas of the read-only tools, for models that must not make changes.
+    pub fn get_read_only_schemas(&self) -> Vec<ApiTool> {
+        self.tools
+            .values()
+            .filter(|tool| tool.is_read_only())
+            .map(|tool| ApiTool::Function {
+                function: tool.schema(),
+            })
+            .collect()
+    }
+
     /// Generates a preview for a tool call.
     pub async fn preview_tool_call(
         &self,
@@ -54,13 +70,7 @@ impl ToolCollection {
         fsm: Arc<Mutex<FileStateManager>>,
     ) -> Result<String> {
         let function_name = &tool_call.function_call.name;
-        let arguments = &tool_call.function_call.arguments;
-        let args_value: Value = serde_json::from_str(arguments)?;
-
-        if config.debug_tool_calls {
-            let pretty_args = serde_json::to_string_pretty(&args_value)?;
-            println!("{}", style(pretty_args).dim());
-        }
+        let args_value = parse_arguments(tool_call)?;
 
         let tool = self
             .tools
@@ -73,8 +83,7 @@ impl ToolCollection {
     /// Checks if a tool call is safe for automatic execution.
     pub fn is_safe_for_auto_execute(&self, tool_call: &ToolCall, config: &Config) -> Result<bool> {
         let function_name = &tool_call.function_call.name;
-        let arguments = &tool_call.function_call.arguments;
-        let args_value: Value = serde_json::from_str(arguments)?;
+        let args_value = parse_arguments(tool_call)?;
 
         let tool = self
             .tools
@@ -84,19 +93,33 @@ impl ToolCollection {
         tool.is_safe_for_auto_execute(&args_value, config)
     }
 
+    /// Checks if a tool call only reads, so it may run concurrently with other
+    /// read-only calls. Calls to unknown tools are not read-only.
+    pub fn is_read_only(&self, tool_call: &ToolCall) -> bool {
+        self.tools
+            .get(&tool_call.function_call.name)
+            .is_some_and(|tool| tool.is_read_only())
+    }
+
     /// Executes a tool call and returns the result as a `Message`.
     /// This function is designed to always succeed from the caller's perspective,
     /// returning a `Message`. Any failures in tool lookup, argument parsing,
     /// or execution are captured and returned within the `content` of the
     /// `tool` role message.
+    ///
+    /// The start and the result of the execution are reported to `events`.
     pub async fn execute_tool_call(
         &self,
         tool_call: &ToolCall,
         config: &Config,
         fsm: Arc<Mutex<FileStateManager>>,
+        events: &dyn EventSink,
     ) -> Message {
         let function_name = &tool_call.function_call.name;
-        let arguments = &tool_call.function_call.arguments;
+
+        events.emit(AgentEvent::ToolStarted {
+            tool_call: tool_call.clone(),
+        });
 
         let result = async {
             let tool = self
@@ -104,37 +127,24 @@ impl ToolCollection {
                 .get(function_name)
                 .ok_or_else(|| anyhow!("Unknown tool: {function_name}"))?;
 
-            let args_value: Value = serde_json::from_str(arguments)
-                .map_err(|e| anyhow!("Failed to parse JSON arguments: {e}"))?;
+            let args_value = parse_arguments(tool_call)?;
 
-            tool.execute(&args_value, config, fsm).await
+            tool.execute(&args_value, config, fsm, events).await
         }
         .await;
 
+        let is_error = result.is_err();
         let message_content = match result {
-            Ok(output) => {
-                if config.debug_tool_calls {
-                    println!(
-                        "{}",
-                        style(format!("Tool output:\n{}", output.clone())).dim()
-                    );
-                }
-
-                strip_str(&output)
-            }
-            Err(e) => {
-                let error_message = e.to_string();
-                eprintln!(
-                    "{}",
-                    style(format!(
-                        "Error executing tool `{function_name}`: {error_message}"
-                    ))
-                    .red()
-                );
-                error_message
-            }
+            Ok(output) => strip_str(&output),
+            Err(e) => e.to_string(),
         };
 
+        events.emit(AgentEvent::ToolFinished {
+            tool_call: tool_call.clone(),
+            result: message_content.clone(),
+            is_error,
+        });
+
         Message {
             role: "tool".to_string(),
             content: message_content,
@@ -145,6 +155,28 @@ impl ToolCollection {
     }
 }
 
+/// Parses the arguments of a tool call. They were already repaired where possible, so
+/// the error tells the model how to send them instead.
+fn parse_arguments(tool_call: &ToolCall) -> Result<Value> {
+    let arguments = &tool_call.function_call.arguments;
+    serde_json::from_str(arguments).map_err(|e| {
+        if tool_call_repair::is_truncated(arguments) {
+            return anyhow!(
+                "The arguments of `{}` are truncated, probably by the output token limit, so \
+                 the call was not executed. Repeat the call with complete arguments. Split \
+                 large contents into several calls.",
+                tool_call.function_call.name
+            );
+        }
+        anyhow!(
+            "Failed to parse JSON arguments: {e}. The arguments must be a single JSON object \
+             that matches the parameters of `{}`, with double-quoted strings and without \
+             code fences or comments. Repeat the call with valid arguments.",
+            tool_call.function_call.name
+        )
+    })
+}
+
 impl Default for ToolCollection {
     fn default() -> Self {
         Self::new()
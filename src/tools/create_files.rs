@@ -2,6 +2,7 @@
 //!
 //! This module provides the `create_file` tool, allowing the agent to create new files.
 
+use crate::events::EventSink;
 use crate::file_state_manager::FileStateManager;
 use crate::permissions;
 use crate::tools::Tool;
@@ -163,6 +164,7 @@ If the file content comes from another file, instead of creating a file with the
         args: &Value,
         config: &Config,
         fsm: Arc<Mutex<FileStateManager>>,
+        _events: &dyn EventSink,
     ) -> Result<String> {
         let args: CreateFileArgs = serde_json::from_value(args.clone())?;
         plan_create_files(&args, &config.accessible_paths)?;
@@ -197,6 +199,7 @@ pub fn execute_create_files(
             // All validation (existence, permissions) is now done in the planner.
             let path_to_create = Path::new(&spec.file_path);
 
+            file_state_manager.snapshot_before_write(path_to_create)?;
             if let Some(parent) = path_to_create.parent() {
                 // This can still fail, but it's an execution-time error.
                 fs::create_dir_all(parent)?;
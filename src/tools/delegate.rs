@@ -0,0 +1,230 @@
+//! # Delegate Tool
+//!
+//! Hands a self-contained task, typically exploration, to a sub-agent: a child `Agent`
+//! with its own message history, a restricted `ToolCollection` and optionally a
+//! smaller model (`subagent_model`). Only the final answer of the sub-agent comes back
+//! as the tool result, so its exploration stays out of the parent's context.
+//!
+//! The sub-agent shares the parent's `FileStateManager`, so the LIDs it reports stay
+//! valid for the parent. Its token usage counts towards the parent's totals.
+
+use crate::agent::{Agent, Approval, ApprovalRequest, StopReason, ToolApprover};
+use crate::client::LlmClient;
+use crate::config::Config;
+use crate::events::{AgentEvent, EventSink, OutputStream};
+use crate::file_state_manager::FileStateManager;
+use crate::tool_collection::ToolCollection;
+use crate::tools::Tool;
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use openrouter_api::models::tool::FunctionDescription;
+use openrouter_api::types::chat::Message;
+use serde::Deserialize;
+use serde_json::Value;
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+
+const SUBAGENT_SYSTEM_PROMPT: &str = "You are a sub-agent of an AI coding assistant. You work on a single task on its behalf, using your tools to explore the codebase. The assistant only sees your final answer, not your tool calls.
+
+When you are done, answer with a concise, self-contained summary of your findings: the relevant files, symbols and line ranges with their LIDs, and anything else the assistant needs to continue. Do not ask questions; make reasonable assumptions and state them.";
+
+#[derive(Deserialize, Debug)]
+struct DelegateArgs {
+    task: String,
+}
+
+pub struct DelegateTool {
+    client: Arc<dyn LlmClient>,
+    tool_collection: Arc<ToolCollection>,
+}
+
+impl DelegateTool {
+    /// Creates the tool. Sub-agents can only use the tools in `tool_collection` that are
+    /// read-only.
+    pub fn new(client: Arc<dyn LlmClient>, tool_collection: Arc<ToolCollection>) -> Self {
+        Self {
+            client,
+            tool_collection,
+        }
+    }
+}
+
+#[async_trait]
+impl Tool for DelegateTool {
+    fn name(&self) -> &'static str {
+        "delegate_task"
+    }
+
+    fn schema(&self) -> FunctionDescription {
+        FunctionDescription {
+            name: "delegate_task".to_string(),
+            description: Some(
+                "Delegates a self-contained exploration task to a sub-agent that can list and read files. Returns only its final summary, which keeps your context small. Use it for broad searches, e.g. 'Find where the config file is loaded and which fields it has'. Independent tasks can be delegated in parallel."
+                    .to_string(),
+            ),
+            strict: Some(true),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "task": {
+                        "type": "string",
+                        "description": "The task, with all context the sub-agent needs. It does not see your conversation."
+                    }
+                },
+                "additionalProperties": false,
+                "required": ["task"]
+            }),
+        }
+    }
+
+    fn preview(
+        &self,
+        args: &Value,
+        config: &Config,
+        _fsm: Arc<Mutex<FileStateManager>>,
+    ) -> Result<String> {
+        let args: DelegateArgs = serde_json::from_value(args.clone())?;
+        if args.task.trim().is_empty() {
+            return Err(anyhow!("The task must not be empty."));
+        }
+        Ok(format!(
+            "Sub-agent ({}): {}",
+            subagent_config(config).model,
+            args.task
+        ))
+    }
+
+    async fn execute(
+        &self,
+        args: &Value,
+        config: &Config,
+        fsm: Arc<Mutex<FileStateManager>>,
+        events: &dyn EventSink,
+    ) -> Result<String> {
+        let args: DelegateArgs = serde_json::from_value(args.clone())?;
+
+        let mut agent = Agent::new(
+            subagent_config(config),
+            Some(self.client.clone()),
+            self.tool_collection.clone(),
+        );
+        agent.file_state_manager = fsm;
+        agent.take_checkpoints = false;
+        agent.streaming = false;
+        agent.messages.push(Message {
+            role: "system".to_string(),
+            content: SUBAGENT_SYSTEM_PROMPT.to_string(),
+            name: None,
+            tool_calls: None,
+            tool_call_id: None,
+        });
+        let (events_tx, mut events_rx) = mpsc::unbounded_channel();
+        agent.events = Arc::new(events_tx);
+
+        let mut approver = ReadOnlyApprover {
+            tool_collection: self.tool_collection.clone(),
+        };
+        // The sub-agent's progress is shown as live output of this tool.
+        let stop_reason = {
+            let turn = agent.run_turn(args.task, &mut approver);
+            tokio::pin!(turn);
+            loop {
+                tokio::select! {
+                    result = &mut turn => break result?,
+                    Some(event) = events_rx.recv() => report_progress(event, events),
+                }
+            }
+        };
+        while let Ok(event) = events_rx.try_recv() {
+            report_progress(event, events);
+        }
+        if agent.turn_usage.requests > 0 {
+            events.emit(AgentEvent::ToolOutput {
+                stream: OutputStream::Stdout,
+                line: format!("[sub-agent] {}", agent.turn_usage),
+            });
+        }
+
+        let answer = agent
+            .messages
+            .iter()
+            .rev()
+            .find(|m| m.role == "assistant" && !m.content.trim().is_empty())
+            .map(|m| m.content.clone())
+            .unwrap_or_default();
+        match stop_reason {
+            StopReason::Done if !answer.is_empty() => Ok(answer),
+            StopReason::Done => Err(anyhow!("The sub-agent finished without an answer.")),
+            StopReason::MaxIterations(max_iterations) => Ok(format!(
+                "The sub-agent was stopped after {max_iterations} LLM calls. Its last answer:\n{answer}"
+            )),
+            StopReason::Cancelled => Err(anyhow!("The sub-agent was cancelled.")),
+        }
+    }
+
+    fn is_read_only(&self) -> bool {
+        true
+    }
+}
+
+/// The configuration of a sub-agent: the parent's, with `subagent_model` if set. A
+/// sub-agent only reads, so it answers directly instead of planning for an editor.
+fn subagent_config(config: &Config) -> Config {
+    let mut config = config.clone();
+    if let Some(model) = config.subagent_model.clone() {
+        config.model = model;
+    }
+    config.architect_model = None;
+    config.editor_model = None;
+    config
+}
+
+/// Approves the read-only tool calls of a sub-agent and denies everything else.
+struct ReadOnlyApprover {
+    tool_collection: Arc<ToolCollection>,
+}
+
+#[async_trait]
+impl ToolApprover for ReadOnlyApprover {
+    async fn approve(&mut self, request: ApprovalRequest<'_>) -> Result<Approval> {
+        if self.tool_collection.is_read_only(request.tool_call) {
+            Ok(Approval::Approved)
+        } else {
+            Ok(Approval::Denied(
+                "Sub-agents can only use read-only tools.".to_string(),
+            ))
+        }
+    }
+}
+
+/// Reports the tool calls and errors of the sub-agent as output lines of this tool, and
+/// its usage as `AgentEvent::SubagentUsage`, so the parent adds it to its totals.
+fn report_progress(event: AgentEvent, events: &dyn EventSink) {
+    let (stream, line) = match event {
+        AgentEvent::ToolStarted { tool_call } => (
+            OutputStream::Stdout,
+            format!(
+                "[sub-agent] {} {}",
+                tool_call.function_call.name, tool_call.function_call.arguments
+            ),
+        ),
+        AgentEvent::ToolFinished {
+            tool_call,
+            result,
+            is_error: true,
+        } => (
+            OutputStream::Stderr,
+            format!(
+                "[sub-agent] {} failed: {result}",
+                tool_call.function_call.name
+            ),
+        ),
+        AgentEvent::Error { message } => (OutputStream::Stderr, format!("[sub-agent] {message}")),
+        AgentEvent::Usage { usage } => {
+            events.emit(AgentEvent::SubagentUsage { usage });
+            return;
+        }
+        _ => return,
+    };
+    events.emit(AgentEvent::ToolOutput { stream, line });
+}
@@ -17,6 +17,7 @@
 //!     primitives, which are then passed to the `FileState` module for execution.
 
 use crate::config::Config;
+use crate::events::EventSink;
 use crate::file_state::FileState;
 use crate::file_state_manager::FileStateManager;
 use crate::patch::{InsertOp, PatchOperation, ReplaceOp};
@@ -276,13 +277,13 @@ impl Tool for FileEditorTool {
     /// On success, this method returns a concise, machine-readable summary of
     /// the changes, including new file hashes. This output is for the LLM.
     ///
-    /// Any output intended for the user during execution (e.g., live command output)
-    /// should be printed directly to stdout within this method.
+    /// Nothing is printed; output for the user is reported as `AgentEvent`s.
     async fn execute(
         &self,
         args: &Value,
         config: &Config,
         fsm: Arc<Mutex<FileStateManager>>,
+        _events: &dyn EventSink,
     ) -> Result<String> {
         let args: TopLevelRequest = serde_json::from_value(args.clone())?;
         let mut manager = fsm.lock().unwrap();
@@ -729,6 +730,7 @@ pub fn execute_file_operations(
     for (path, operations) in plan.planned_ops {
         let file_path_str = path.to_string_lossy();
         let result: Result<String> = (|| {
+            file_state_manager.snapshot_before_write(&path)?;
             let file_state = file_state_manager.get_file_state_mut(&file_path_str)?;
             let initial_hash = file_state.get_short_hash().to_string();
 
@@ -736,6 +738,8 @@ pub fn execute_file_operations(
             let diff = file_state.apply_and_write_patch(&operations)?;
             let new_short_hash = file_state.get_short_hash();
 
+            // Earlier views of this file are superseded before the next request, so only
+            // the LIDs in the diff are current.
             Ok(format!(
                 "Patch from hash {initial_hash} applied successfully. New lif_hash: {new_short_hash}. Changes:\n{diff}"
             ))
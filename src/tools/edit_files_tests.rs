@@ -2,7 +2,8 @@
 
 use super::{Anchor, FileEditorTool};
 use crate::{
-    config::Config, file_state::FileState, file_state_manager::FileStateManager, tools::Tool,
+    config::Config, events::NullSink, file_state::FileState, file_state_manager::FileStateManager,
+    tools::Tool,
 };
 use std::{
     fs,
@@ -67,7 +68,7 @@ async fn test_execute_replace_successfully() {
         "moves": []
     });
 
-    let result = tool.execute(&args, &config, fsm).await.unwrap();
+    let result = tool.execute(&args, &config, fsm, &NullSink).await.unwrap();
 
     assert!(result.contains("Patch from hash"));
     let final_content = fs::read_to_string(&file_path).unwrap();
@@ -96,7 +97,7 @@ async fn test_execute_delete_successfully() {
         "moves": []
     });
 
-    tool.execute(&args, &config, fsm).await.unwrap();
+    tool.execute(&args, &config, fsm, &NullSink).await.unwrap();
     let final_content = fs::read_to_string(&file_path).unwrap();
     assert_eq!(final_content, "line 1\nline 3");
 }
@@ -122,7 +123,7 @@ async fn test_execute_insert_before_anchor() {
         "moves": []
     });
 
-    tool.execute(&args, &config, fsm).await.unwrap();
+    tool.execute(&args, &config, fsm, &NullSink).await.unwrap();
     let final_content = fs::read_to_string(&file_path).unwrap();
     assert_eq!(final_content, "line 1\nline 2\nline 3");
 }
@@ -166,7 +167,7 @@ async fn test_execute_move_successfully() {
         "inserts": []
     });
 
-    tool.execute(&args, &config, fsm).await.unwrap();
+    tool.execute(&args, &config, fsm, &NullSink).await.unwrap();
 
     let source_content = fs::read_to_string(tmp_dir.path().join("test.txt")).unwrap();
     let dest_content = fs::read_to_string(dest_path).unwrap();
@@ -192,7 +193,7 @@ async fn test_succeed_replace_with_no_anchors() {
         "moves": []
     });
 
-    let result = tool.execute(&args, &config, fsm).await;
+    let result = tool.execute(&args, &config, fsm, &NullSink).await;
     assert!(result.is_ok());
 
     let final_content = fs::read_to_string(&file_path).unwrap();
@@ -221,7 +222,7 @@ async fn test_execute_fails_with_invalid_suffix() {
         "moves": []
     });
 
-    let result = tool.execute(&args, &config, fsm).await;
+    let result = tool.execute(&args, &config, fsm, &NullSink).await;
     assert!(result.is_err());
     let error_string = result.unwrap_err().to_string();
     assert!(error_string.contains("Invalid FractionalIndex format in LID: '0'"));
@@ -248,7 +249,7 @@ async fn test_insert_multiline_string() {
         "moves": []
     });
 
-    tool.execute(&args, &config, fsm).await.unwrap();
+    tool.execute(&args, &config, fsm, &NullSink).await.unwrap();
     let final_content = fs::read_to_string(&file_path).unwrap();
     assert_eq!(final_content, "line 1\nline 1.5\nline 2\nline 3");
 }
@@ -274,7 +275,7 @@ async fn test_replace_with_multiline_string() {
         "moves": []
     });
 
-    tool.execute(&args, &config, fsm).await.unwrap();
+    tool.execute(&args, &config, fsm, &NullSink).await.unwrap();
     let final_content = fs::read_to_string(&file_path).unwrap();
     assert_eq!(final_content, "one\nTWO\nAND A HALF\nthree");
 }
@@ -300,7 +301,7 @@ async fn test_insert_with_trailing_newline() {
         "moves": []
     });
 
-    tool.execute(&args, &config, fsm).await.unwrap();
+    tool.execute(&args, &config, fsm, &NullSink).await.unwrap();
     let final_content = fs::read_to_string(&file_path).unwrap();
     assert_eq!(final_content, "line 1\nline 2\nline 3");
 }
@@ -327,7 +328,7 @@ async fn test_insert_empty_string_is_noop() {
         "moves": []
     });
 
-    tool.execute(&args, &config, fsm).await.unwrap();
+    tool.execute(&args, &config, fsm, &NullSink).await.unwrap();
     let final_content = fs::read_to_string(&file_path).unwrap();
     assert_eq!(final_content, original_content);
 }
@@ -4,6 +4,7 @@
 //! to run arbitrary shell commands.
 
 use crate::config::Config;
+use crate::events::{AgentEvent, EventSink, OutputStream};
 use crate::permissions;
 use crate::tools::Tool;
 use anyhow::{Result, anyhow};
@@ -96,9 +97,10 @@ The output and the exit code will be returned. Always do a short analysis of the
         args: &Value,
         _config: &Config,
         _fsm: Arc<Mutex<FileStateManager>>,
+        events: &dyn EventSink,
     ) -> Result<String> {
         let args: ShellCommandArgs = serde_json::from_value(args.clone())?;
-        execute_shell_command(&args.command, args.workdir.as_deref()).await
+        execute_shell_command(&args.command, args.workdir.as_deref(), events).await
     }
 
     fn is_safe_for_auto_execute(&self, args: &Value, config: &Config) -> Result<bool> {
@@ -123,7 +125,13 @@ The output and the exit code will be returned. Always do a short analysis of the
     }
 }
 
-pub async fn execute_shell_command(command: &str, workdir: Option<&str>) -> Result<String> {
+/// Runs `command` with `sh -c`, reporting every output line to `events` as it arrives.
+/// Returns the combined output followed by the exit code.
+pub async fn execute_shell_command(
+    command: &str,
+    workdir: Option<&str>,
+    events: &dyn EventSink,
+) -> Result<String> {
     let mut cmd = Command::new("sh");
     cmd.arg("-c").arg(command);
 
@@ -155,9 +163,12 @@ pub async fn execute_shell_command(command: &str, workdir: Option<&str>) -> Resu
             line = stderr_reader.next_line(), if !stderr_done => {
                 match line {
                     Ok(Some(line)) => {
-                        eprintln!("{line}");
                         output.push_str(&line);
                         output.push('\n');
+                        events.emit(AgentEvent::ToolOutput {
+                            stream: OutputStream::Stderr,
+                            line,
+                        });
                     }
                     Ok(None) => stderr_done = true,
                     Err(e) => return Err(anyhow!("Error reading stderr: {e}")),
@@ -166,9 +177,12 @@ pub async fn execute_shell_command(command: &str, workdir: Option<&str>) -> Resu
             line = stdout_reader.next_line(), if !stdout_done => {
                 match line {
                     Ok(Some(line)) => {
-                        println!("{line}");
                         output.push_str(&line);
                         output.push('\n');
+                        events.emit(AgentEvent::ToolOutput {
+                            stream: OutputStream::Stdout,
+                            line,
+                        });
                     }
                     Ok(None) => stdout_done = true,
                     Err(e) => return Err(anyhow!("Error reading stdout: {e}")),
@@ -187,8 +201,9 @@ pub async fn execute_shell_command(command: &str, workdir: Option<&str>) -> Resu
         "Process terminated by signal".to_string()
     };
 
-    let styled_exit_message = style(&exit_message).bold().to_string();
-    println!("\n{styled_exit_message}");
+    events.emit(AgentEvent::CommandExited {
+        status: exit_message.clone(),
+    });
 
     output.push('\n');
     output.push_str(&exit_message);
@@ -1,4 +1,4 @@
-use crate::{config::Config, path_expander, permissions, tools::Tool};
+use crate::{config::Config, events::EventSink, path_expander, permissions, tools::Tool};
 use anyhow::{Result, anyhow};
 use async_trait::async_trait;
 use openrouter_api::models::tool::FunctionDescription;
@@ -76,6 +76,7 @@ impl Tool for ListFilesTool {
         args: &Value,
         config: &Config,
         _fsm: Arc<Mutex<FileStateManager>>,
+        _events: &dyn EventSink,
     ) -> Result<String> {
         let args: ListFilesArgs = serde_json::from_value(args.clone())?;
         // The plan has already validated the path is a directory and accessible.
@@ -83,6 +84,10 @@ impl Tool for ListFilesTool {
         execute_list_files(&args, config)
     }
 
+    fn is_read_only(&self) -> bool {
+        true
+    }
+
     fn is_safe_for_auto_execute(&self, args: &Value, config: &Config) -> Result<bool> {
         let args: ListFilesArgs = serde_json::from_value(args.clone())?;
         if permissions::is_path_accessible(Path::new(&args.path), &config.accessible_paths).is_err()
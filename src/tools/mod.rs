@@ -4,6 +4,7 @@
 //! It provides a standardized interface for discovering, previewing, and executing tools.
 
 use crate::config::Config;
+use crate::events::EventSink;
 use crate::file_state_manager::FileStateManager;
 use anyhow::Result;
 use async_trait::async_trait;
@@ -12,11 +13,13 @@ use serde_json::Value;
 use std::sync::{Arc, Mutex};
 
 pub mod create_files;
+pub mod delegate;
 pub mod edit_files;
 pub mod execute_shell_command;
 pub mod list_files;
 pub mod read_files;
 pub use self::create_files::FileCreatorTool;
+pub use self::delegate::DelegateTool;
 pub use self::edit_files::FileEditorTool;
 pub use self::execute_shell_command::ShellTool;
 pub use self::list_files::ListFilesTool;
@@ -52,15 +55,23 @@ pub trait Tool: Send + Sync {
     /// LIDs, command output). This output should be stripped of any ANSI color codes
     /// or human-centric formatting.
     ///
-    /// Any output intended for the user during execution (e.g., live command output,
-    /// progress indicators) should be printed directly to stdout/stderr within this method.
+    /// Any output intended for the user during execution (e.g., live command output)
+    /// must not be printed, but reported to `events` as `AgentEvent::ToolOutput`.
     async fn execute(
         &self,
         args: &Value,
         config: &Config,
         fsm: Arc<Mutex<FileStateManager>>,
+        events: &dyn EventSink,
     ) -> Result<String>;
 
+    /// Whether the tool only reads and has no side effects. Read-only calls of the same
+    /// assistant message are executed concurrently. The default implementation
+    /// returns `false`.
+    fn is_read_only(&self) -> bool {
+        false
+    }
+
     /// Checks if the tool call is safe to execute without user confirmation.
     /// The default implementation returns `true`.
     fn is_safe_for_auto_execute(&self, _args: &Value, _config: &Config) -> Result<bool> {
@@ -1,13 +1,14 @@
 use crate::{
-    config::Config, file_state::RangeSpec, file_state_manager::FileStateManager, permissions,
-    tools::Tool,
+    config::Config, events::EventSink, file_state::RangeSpec, file_state_manager::FileStateManager,
+    permissions, tools::Tool,
 };
 use anyhow::{Result, anyhow};
 use async_trait::async_trait;
 use openrouter_api::models::tool::FunctionDescription;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::path::Path;
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 
 /// Validates the arguments for a file read operation.
@@ -159,11 +160,19 @@ File: jokes.txt | Hash: 931d3b24 | Lines: 1-1/1
         args: &Value,
         config: &Config,
         fsm: Arc<Mutex<FileStateManager>>,
+        _events: &dyn EventSink,
     ) -> Result<String> {
         let args: FileReadArgs = serde_json::from_value(args.clone())?;
         plan_read_operations(&args, config)?;
+        // Concurrent read-only calls only wait for each other while the file states
+        // are updated, not while the files are read.
+        let contents = read_from_disk(&args);
         let mut manager = fsm.lock().unwrap();
-        execute_read_file(&args, &mut manager)
+        Ok(display_read_files(&args, contents, &mut manager))
+    }
+
+    fn is_read_only(&self) -> bool {
+        true
     }
 
     fn is_safe_for_auto_execute(&self, args: &Value, config: &Config) -> Result<bool> {
@@ -210,15 +219,37 @@ pub fn execute_read_file(
     args: &FileReadArgs,
     file_state_manager: &mut FileStateManager,
 ) -> Result<String> {
+    let contents = read_from_disk(args);
+    Ok(display_read_files(args, contents, file_state_manager))
+}
+
+/// Reads the requested files, returning their canonical paths and contents.
+fn read_from_disk(args: &FileReadArgs) -> Vec<Result<(PathBuf, String)>> {
+    args.files
+        .iter()
+        .map(|request| {
+            // Permissions and existence are checked by the planner before this.
+            let canonical_path = FileStateManager::get_canonical_path(&request.file_path)?;
+            let content = fs::read_to_string(&canonical_path)?;
+            Ok((canonical_path, content))
+        })
+        .collect()
+}
+
+/// Updates the file states with the contents read from disk and displays them.
+fn display_read_files(
+    args: &FileReadArgs,
+    contents: Vec<Result<(PathBuf, String)>>,
+    file_state_manager: &mut FileStateManager,
+) -> String {
     let mut outputs = Vec::new();
     let multiple_files = args.files.len() > 1;
 
-    for request in &args.files {
+    for (request, content) in args.files.iter().zip(contents) {
         let file_path_str = &request.file_path;
 
-        let file_content_result: Result<String> = (|| {
-            // Permissions and existence are checked by the planner before this.
-            let file_state = file_state_manager.open_file(file_path_str)?;
+        let file_content_result: Result<String> = content.map(|(canonical_path, content)| {
+            let file_state = file_state_manager.update_file(canonical_path, &content);
 
             let merged_ranges = request
                 .ranges
@@ -226,8 +257,8 @@ pub fn execute_read_file(
                 .map(|r| merge_ranges(r.clone()))
                 .filter(|r| !r.is_empty());
 
-            Ok(file_state.display_lif_contents_for_ranges(merged_ranges.as_deref()))
-        })();
+            file_state.display_lif_contents_for_ranges(merged_ranges.as_deref())
+        });
 
         let output = match file_content_result {
             Ok(content) => content,
@@ -241,7 +272,7 @@ pub fn execute_read_file(
         }
     }
 
-    Ok(outputs.join("\n\n"))
+    outputs.join("\n\n")
 }
 
 fn create_preview(args: &FileReadArgs) -> String {
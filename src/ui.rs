@@ -1,32 +1,28 @@
-use alors::{
-    agent::{Agent, AgentOutput},
-    tool_collection::ToolCollection,
-};
+use crate::commands::{Command, HELP};
+use crate::input::{Input, InputReader};
+use alors::agent::{Agent, Approval, ApprovalRequest, StopReason, ToolApprover};
+use alors::events::{AgentEvent, EventSink, OutputStream};
+use alors::file_state_manager::FileStateManager;
+use alors::session::Session;
 use anyhow::Result;
-use console::style;
-use openrouter_api::{models::tool::ToolCall, types::chat::Message};
+use async_trait::async_trait;
+use console::{Term, measure_text_width, style};
 use std::{
     io::{self, Write},
     process,
-    sync::Arc,
+    sync::Mutex,
 };
-use tokio::sync::mpsc;
-
-enum ToolInteraction {
-    ToolsExecuted,
-    Cancelled,
-}
 
 pub struct App {
     agent: Agent,
-    stdin_receiver: mpsc::Receiver<Option<String>>,
+    input: InputReader,
 }
 
 impl App {
     pub fn new(agent: Agent) -> Self {
         Self {
             agent,
-            stdin_receiver: spawn_stdin_channel(),
+            input: InputReader::spawn(),
         }
     }
 
@@ -47,115 +43,90 @@ impl App {
                 current_prompt = prompt_data.final_prompt;
             }
 
-            // Inner loop for the agent's turn. It continues as long as there's a prompt
-            // to process or if the last message was a tool result.
-            while !current_prompt.is_empty()
+            // Run the agent's turn if there's a prompt to process or if the last
+            // message was a tool result.
+            if !current_prompt.is_empty()
                 || self.agent.messages.last().is_some_and(|m| m.role == "tool")
             {
-                let agent_output = self.agent.step_streaming(current_prompt)?;
-                current_prompt = String::new(); // Consume the prompt
+                let prompt = std::mem::take(&mut current_prompt);
+                let mut approver = TerminalApprover {
+                    input: &mut self.input,
+                    debug_tool_calls: self.agent.config.debug_tool_calls,
+                };
 
-                let final_agent_output = if let AgentOutput::PendingLLM(mut handle) = agent_output {
-                    tokio::select! {
-                        biased;
-                        _ = tokio::signal::ctrl_c() => {
-                            if ctrl_c_pressed {
-                                println!("\nShutting down...");
-                                process::exit(0);
-                            } else {
-                                println!("\nLLM generation cancelled. Press Ctrl+C again to exit.");
-                                ctrl_c_pressed = true;
-                                handle.abort(); // CRITICAL: Abort the detached task
-                                None
-                            }
-                        }
-                        result = &mut handle => {
-                            match result {
-                                Ok(Ok(Some(msg))) => {
-                                    self.agent.messages.push(msg.clone());
-                                    Some(Ok(if let Some(calls) = msg.tool_calls {
-                                        AgentOutput::ToolCalls(calls)
-                                    } else {
-                                        AgentOutput::Message(msg)
-                                    }))
-                                },
-                                Ok(Ok(None)) => Some(Ok(AgentOutput::Done)),
-                                Ok(Err(e)) => Some(Err(e)),
-                                Err(e) => Some(Err(e.into())),
-                            }
-                        }
-                    }
-                } else {
-                    Some(Ok(agent_output))
+                let turn_result = tokio::select! {
+                    biased;
+                    _ = tokio::signal::ctrl_c() => None,
+                    result = self.agent.run_turn(prompt, &mut approver) => Some(result),
                 };
 
-                if let Some(result) = final_agent_output {
-                    match result {
-                        Ok(output) => match output {
-                            AgentOutput::Message(_msg) => {
-                                // The content was already printed by the streaming executor.
-                            }
-                            AgentOutput::ToolCalls(calls) => {
-                                let tool_collection = Arc::clone(&self.agent.tool_collection);
-                                let interaction = self
-                                    .process_tool_calls_interactively(
-                                        calls,
-                                        tool_collection,
-                                        &mut ctrl_c_pressed,
-                                    )
-                                    .await?;
-                                match interaction {
-                                    ToolInteraction::ToolsExecuted => {
-                                        // After executing tools, we want to run the agent again
-                                        // to process the tool results, so we set an empty prompt.
-                                    }
-                                    ToolInteraction::Cancelled => {
-                                        // User cancelled, so break the agent's turn.
-                                        break;
-                                    }
-                                }
-                            }
-                            AgentOutput::Done => {
-                                // Agent's turn is over.
-                                break;
-                            }
-                            AgentOutput::PendingLLM(_) => unreachable!(), // Already handled
-                        },
-                        Err(e) => {
-                            eprintln!("{}", style(format!("[Error] Agent failed: {e}")).red());
-                            // Agent's turn is over, break to user prompt.
-                            break;
+                match turn_result {
+                    None => {
+                        // The turn was aborted.
+                        self.agent.abort_turn();
+                        if ctrl_c_pressed {
+                            println!("\nShutting down...");
+                            self.save_session();
+                            process::exit(0);
                         }
+                        println!("\nTurn cancelled. Press Ctrl+C again to exit.");
+                        ctrl_c_pressed = true;
+                    }
+                    Some(Ok(StopReason::Done)) | Some(Ok(StopReason::Cancelled)) => {}
+                    Some(Ok(StopReason::MaxIterations(max_iterations))) => {
+                        println!(
+                            "{}",
+                            style(format!(
+                                "Stopped after {max_iterations} LLM calls (max_iterations). Send a message to continue."
+                            ))
+                            .yellow()
+                        );
                     }
-                } else {
-                    // This happens on Ctrl+C
-                    break;
+                    Some(Err(e)) => {
+                        eprintln!("{}", style(format!("[Error] Agent failed: {e}")).red());
+                    }
+                }
+                if self.agent.turn_usage.requests > 0 {
+                    println!(
+                        "{}",
+                        style(format!(
+                            "Turn: {}. Session: {}",
+                            self.agent.turn_usage,
+                            self.agent.session_usage.cost_summary()
+                        ))
+                        .dim()
+                    );
                 }
             }
 
+            self.save_session();
+
             // After the agent's turn is complete, always wait for new user input.
-            print!("\x07{} ", style("user>").cyan().bold());
+            print!("\x07");
             io::stdout().flush()?;
+            let prompt = format!("{} ", style("user>").cyan().bold());
 
             tokio::select! {
-                        biased;
-                        _ = tokio::signal::ctrl_c() => {
-                            if ctrl_c_pressed {
-                        println!("\nShutting down...");
-                        process::exit(0);
-                            } else {
-                                println!("\nPress Ctrl+C again to exit.");
-                                ctrl_c_pressed = true;
+                biased;
+                _ = tokio::signal::ctrl_c() => handle_ctrl_c(&mut ctrl_c_pressed),
+                input = self.input.read_prompt(&prompt) => {
+                    match input {
+                        Input::Line(input) => {
+                            match Command::parse(&input) {
+                                Some(Ok(command)) => {
+                                    if let Err(e) = self.run_command(command) {
+                                        eprintln!("{}", style(format!("[Error] {e}")).red());
+                                    }
+                                }
+                                Some(Err(e)) => eprintln!("{}", style(e).yellow()),
+                                None => current_prompt = input,
                             }
+                            ctrl_c_pressed = false;
                         }
-                        line_opt = self.stdin_receiver.recv() => {
-                    match line_opt.flatten() {
-                                Some(input) => {
-                            current_prompt = input;
-                                    ctrl_c_pressed = false;
-                                }
-                                None => {
-                                    // Ctrl+D was pressed
+                        // While the user types, Ctrl+C does not raise a signal.
+                        Input::Interrupted => handle_ctrl_c(&mut ctrl_c_pressed),
+                        Input::Eof => {
+                            // Ctrl+D was pressed
                             println!("\nShutting down...");
                             process::exit(0);
                         }
@@ -165,142 +136,389 @@ impl App {
         }
     }
 
-    async fn process_tool_calls_interactively(
-        &mut self,
-        tool_calls: Vec<ToolCall>,
-        tool_collection: Arc<ToolCollection>,
-        ctrl_c_pressed: &mut bool,
-    ) -> Result<ToolInteraction> {
-        let mut any_tool_run = false;
+    /// Runs a REPL command. Nothing is sent to the LLM.
+    fn run_command(&mut self, command: Command) -> Result<()> {
+        match command {
+            Command::Clear => {
+                self.agent.messages.retain(|m| m.role == "system");
+                *self.agent.file_state_manager.lock().unwrap() = FileStateManager::new();
+                self.save_session();
+                self.agent.session = Some(Session::create(&self.agent.config)?);
+                self.save_session();
+                println!("{}", style("History cleared. Started a new session.").dim());
+            }
+            Command::Undo => match self.agent.undo_turn()? {
+                Some(restored) => {
+                    if let Some(commit) = &restored.reverted_commit {
+                        println!("{}", style(format!("Reverted commit {commit}")).dim());
+                    }
+                    for path in &restored.restored_files {
+                        println!("{}", style(format!("Restored {}", path.display())).dim());
+                    }
+                    for path in &restored.removed_files {
+                        println!("{}", style(format!("Removed {}", path.display())).dim());
+                    }
+                    println!(
+                        "{}",
+                        style(format!(
+                            "Undid the last turn. The history has {} messages.",
+                            restored.messages.len()
+                        ))
+                        .dim()
+                    );
+                }
+                None => println!("Nothing to undo."),
+            },
+            Command::Model(None) => println!("model: {}", self.agent.config.model),
+            Command::Model(Some(model)) => {
+                println!(
+                    "{}",
+                    style(format!(
+                        "Switched from {} to {model}",
+                        self.agent.config.model
+                    ))
+                    .dim()
+                );
+                self.agent.config.model = model;
+            }
+            Command::Save => {
+                self.agent.save_session()?;
+                match &self.agent.session {
+                    Some(session) => println!(
+                        "Saved session {} to {}",
+                        style(&session.id).bold(),
+                        session.path.display()
+                    ),
+                    None => println!("This conversation has no session."),
+                }
+            }
+            Command::Load(None) => crate::print_sessions()?,
+            Command::Load(Some(id)) => {
+                let loaded = Session::load(&id)?;
+                self.save_session();
+                println!(
+                    "{}",
+                    style(format!(
+                        "Loaded session {} ({} messages)",
+                        loaded.session.id,
+                        loaded.messages.len()
+                    ))
+                    .dim()
+                );
+                // Like `--resume`, the LIF views in the loaded history are re-read on demand.
+                *self.agent.file_state_manager.lock().unwrap() = FileStateManager::new();
+                self.agent.messages = loaded.messages;
+                self.agent.session = Some(loaded.session);
+            }
+            Command::Files => {
+                let fsm = self.agent.file_state_manager.lock().unwrap();
+                let mut files: Vec<_> = fsm
+                    .open_files
+                    .values()
+                    .map(|state| (state.display_path(), state.get_short_hash().to_string()))
+                    .collect();
+                if files.is_empty() {
+                    println!("No files read yet.");
+                }
+                files.sort();
+                for (path, hash) in files {
+                    println!("{}  {}", style(hash).dim(), path.display());
+                }
+            }
+            Command::Tokens => {
+                println!(
+                    "History: ~{} of {} tokens",
+                    self.agent.estimated_tokens(),
+                    self.agent.config.context_budget_tokens
+                );
+                println!("Last turn: {}", self.agent.turn_usage);
+                println!("Session: {}", self.agent.session_usage);
+            }
+            Command::Config => print!("{}", toml::to_string_pretty(&self.agent.config)?),
+            Command::Help => {
+                for (usage, description) in HELP {
+                    println!("{:<14}{}", style(usage).bold(), description);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn save_session(&mut self) {
+        if let Err(e) = self.agent.save_session() {
+            eprintln!("{}", style(format!("Failed to save session: {e}")).yellow());
+        }
+    }
+}
+
+/// Shows each tool call with its preview and asks the user for confirmation,
+/// unless the call is auto-approved by the configuration.
+struct TerminalApprover<'a> {
+    input: &'a mut InputReader,
+    debug_tool_calls: bool,
+}
+
+#[async_trait]
+impl<'a> ToolApprover for TerminalApprover<'a> {
+    async fn approve(&mut self, request: ApprovalRequest<'_>) -> Result<Approval> {
+        println!(
+            "[{}]",
+            style(format!("tool: {}", request.tool_call.function_call.name)).magenta()
+        );
+        if self.debug_tool_calls {
+            let arguments = &request.tool_call.function_call.arguments;
+            let pretty_args = serde_json::from_str::<serde_json::Value>(arguments)
+                .and_then(|value| serde_json::to_string_pretty(&value))
+                .unwrap_or_else(|_| arguments.clone());
+            println!("{}", style(pretty_args).dim());
+        }
+        println!("{}", request.preview);
+
+        if request.auto_approved {
+            return Ok(Approval::Approved);
+        }
 
-        for (index, tool_call) in tool_calls.iter().enumerate() {
+        print!("\x07");
+        io::stdout().flush()?;
+
+        let prompt = format!("{} ", style("Execute this tool? [Y/n] ").dim());
+        let cancelled = match self.input.read_answer(&prompt).await {
+            Input::Line(answer) => answer.eq_ignore_ascii_case("n"),
+            Input::Interrupted => true,
+            Input::Eof => false,
+        };
+        if cancelled {
             println!(
-                "[{}]",
-                style(format!("tool: {}", tool_call.function_call.name)).magenta()
+                "{}",
+                style("Operation cancelled. Returning to input.").yellow()
             );
+            Ok(Approval::Cancelled)
+        } else {
+            Ok(Approval::Approved)
+        }
+    }
+}
 
-            // --- Preview ---
-            match tool_collection
-                .preview_tool_call(
-                    tool_call,
-                    &self.agent.config,
-                    self.agent.file_state_manager.clone(),
-                )
-                .await
-            {
-                Ok(preview) => println!("{preview}"),
-                Err(e) => {
-                    let error_message = format!("Preview failed, skipping: {e}");
-                    eprintln!("{}", style(&error_message).red());
-                    // Inform the agent that this tool failed.
-                    self.agent.messages.push(Message {
-                        role: "tool".to_string(),
-                        content: error_message,
-                        name: Some(tool_call.function_call.name.clone()),
-                        tool_call_id: Some(tool_call.id.clone()),
-                        tool_calls: None,
-                    });
-                    any_tool_run = true; // We "ran" it in the sense that we got a result for it.
-                    continue; // Skip to the next tool call
-                }
-            };
-
-            // --- Confirmation ---
-            let is_safe = tool_collection
-                .is_safe_for_auto_execute(tool_call, &self.agent.config)
-                .unwrap_or(false);
+/// What is being streamed of the current assistant message.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum StreamState {
+    /// Nothing yet, the `[assistant]` header is not printed.
+    Idle,
+    Reasoning,
+    Text,
+}
 
-            let confirmed = if self.agent.config.auto_execute && is_safe {
-                true
-            } else {
-                print!("\x07{} ", style("Execute this tool? [Y/n] ").dim());
-                io::stdout().flush()?;
-                tokio::select! {
-                            _ = tokio::signal::ctrl_c() => {
-                        if *ctrl_c_pressed {
-                            println!("\nShutting down...");
-                            process::exit(0);
-                        } else {
-                            println!("\nPress Ctrl+C again to exit.");
-                            *ctrl_c_pressed = true;
-                            // Abort and generate cancellation messages for remaining tools
-                            for remaining_tool_call in &tool_calls[index..] {
-                                self.agent.messages.push(Message {
-                                    role: "tool".to_string(),
-                                    content: "Tool execution cancelled by user.".to_string(),
-                                    name: Some(remaining_tool_call.function_call.name.clone()),
-                                    tool_call_id: Some(remaining_tool_call.id.clone()),
-                                    tool_calls: None,
-                                });
-                            }
-                            return Ok(ToolInteraction::Cancelled);
-                        }
-                    }
-                    line_opt = self.stdin_receiver.recv() => {
-                        let input = line_opt.flatten().unwrap_or_default();
-                        if input.eq_ignore_ascii_case("n") {
-                            println!("{}", style("Operation cancelled. Returning to input.").yellow());
-                            // User cancelled. Generate messages for this and all subsequent tools.
-                            for remaining_tool_call in &tool_calls[index..] {
-                                self.agent.messages.push(Message {
-                                    role: "tool".to_string(),
-                                    content: "Tool execution cancelled by user.".to_string(),
-                                    name: Some(remaining_tool_call.function_call.name.clone()),
-                                    tool_call_id: Some(remaining_tool_call.id.clone()),
-                                    tool_calls: None,
-                                });
-                            }
-                            return Ok(ToolInteraction::Cancelled); // We generated responses, so the agent needs to run.
-                        } else {
-                            *ctrl_c_pressed = false; // Reset on confirmation
-                            true
-                        }
-                    }
-                }
-            };
+/// The assistant message being streamed.
+struct Stream {
+    state: StreamState,
+    /// The unstyled text printed for the message so far, to erase it on a retry.
+    printed: String,
+}
 
-            // --- Execution ---
-            if confirmed {
-                let tool_future = tool_collection.execute_tool_call(
-                    tool_call,
-                    &self.agent.config,
-                    self.agent.file_state_manager.clone(),
-                );
+/// Renders agent events to the terminal: streamed assistant text and reasoning, live
+/// tool output and errors.
+pub struct TerminalRenderer {
+    debug_tool_calls: bool,
+    stream: Mutex<Stream>,
+}
 
-                let result_msg = tokio::select! {
-                    _ = tokio::signal::ctrl_c() => {
-                        if *ctrl_c_pressed {
-                            println!("\nShutting down...");
-                            process::exit(0);
-                        } else {
-                            println!("\nTool execution cancelled. Press Ctrl+C again to exit.");
-                            *ctrl_c_pressed = true;
-                            // Abort and generate cancellation messages for remaining tools
-                            for remaining_tool_call in &tool_calls[index..] {
-                                self.agent.messages.push(Message {
-                                    role: "tool".to_string(),
-                                    content: "Tool execution cancelled by user.".to_string(),
-                                    name: Some(remaining_tool_call.function_call.name.clone()),
-                                    tool_call_id: Some(remaining_tool_call.id.clone()),
-                                    tool_calls: None,
-                                });
-                            }
-                            return Ok(ToolInteraction::Cancelled);
-                        }
-                    }
-                    result = tool_future => result,
-                };
+impl TerminalRenderer {
+    pub fn new(debug_tool_calls: bool) -> Self {
+        Self {
+            debug_tool_calls,
+            stream: Mutex::new(Stream {
+                state: StreamState::Idle,
+                printed: String::new(),
+            }),
+        }
+    }
 
-                self.agent.messages.push(result_msg);
-                any_tool_run = true;
+    /// Prints a streamed delta, preceded by the header or separator needed in the given
+    /// state.
+    fn print_delta(&self, next: StreamState, text: &str) {
+        let mut stream = self.stream.lock().unwrap();
+        let separator = match (stream.state, next) {
+            (StreamState::Idle, _) => {
+                print!("\n[{}]\n", style("assistant").blue());
+                "\n[assistant]\n"
             }
+            (StreamState::Reasoning, StreamState::Text) => {
+                print!("\n\n");
+                "\n\n"
+            }
+            _ => "",
+        };
+        if next == StreamState::Reasoning {
+            print!("{}", style(text).dim());
+        } else {
+            print!("{text}");
         }
-        Ok(if any_tool_run {
-            ToolInteraction::ToolsExecuted
+        io::stdout().flush().ok();
+        stream.printed.push_str(separator);
+        stream.printed.push_str(text);
+        stream.state = next;
+    }
+
+    /// Ends the message being streamed, if any.
+    fn finish_message(&self) {
+        let mut stream = self.stream.lock().unwrap();
+        if stream.state != StreamState::Idle {
+            println!();
+            stream.state = StreamState::Idle;
+        }
+        stream.printed.clear();
+    }
+
+    /// Removes the message being streamed from the terminal, because it is repeated. If
+    /// stdout is not a terminal, the message is marked as discarded instead.
+    fn discard_message(&self) {
+        let mut stream = self.stream.lock().unwrap();
+        if stream.state == StreamState::Idle {
+            return;
+        }
+        let term = Term::stdout();
+        if term.is_term() {
+            let width = usize::from(term.size().1).max(1);
+            let rows: usize = stream
+                .printed
+                .split('\n')
+                .map(|line| measure_text_width(line).div_ceil(width).max(1))
+                .sum();
+            // The cursor is on the last row, after the partial response.
+            term.clear_line().ok();
+            term.clear_last_lines(rows - 1).ok();
         } else {
-            ToolInteraction::Cancelled
-        })
+            println!("\n{}", style("[partial response discarded]").dim());
+        }
+        stream.state = StreamState::Idle;
+        stream.printed.clear();
+    }
+}
+
+impl EventSink for TerminalRenderer {
+    fn emit(&self, event: AgentEvent) {
+        match event {
+            AgentEvent::RequestMessages { messages } => {
+                println!();
+                println!("{}", style("Messages being sent to API:").yellow().bold());
+                for message in &messages {
+                    let message_json = serde_json::to_string_pretty(message)
+                        .unwrap_or_else(|e| format!("Failed to serialize message: {e}"));
+                    println!("{message_json}");
+                }
+                println!();
+            }
+            AgentEvent::TextDelta { text } => self.print_delta(StreamState::Text, &text),
+            AgentEvent::ReasoningDelta { text } => self.print_delta(StreamState::Reasoning, &text),
+            AgentEvent::AssistantMessage { .. } => self.finish_message(),
+            AgentEvent::ToolOutput { stream, line } => match stream {
+                OutputStream::Stdout => println!("{line}"),
+                OutputStream::Stderr => eprintln!("{line}"),
+            },
+            AgentEvent::CommandExited { status } => println!("\n{}", style(status).bold()),
+            AgentEvent::ToolFinished {
+                tool_call,
+                result,
+                is_error,
+            } => {
+                if is_error {
+                    eprintln!(
+                        "{}",
+                        style(format!(
+                            "Error executing tool `{}`: {result}",
+                            tool_call.function_call.name
+                        ))
+                        .red()
+                    );
+                } else if self.debug_tool_calls {
+                    println!("{}", style(format!("Tool output:\n{result}")).dim());
+                }
+            }
+            AgentEvent::EditorStarted { model } => println!(
+                "{}",
+                style(format!("Handing the plan to the editor ({model})...")).dim()
+            ),
+            AgentEvent::Error { message } => eprintln!("{}", style(message).red()),
+            AgentEvent::Retrying {
+                attempt,
+                max_retries,
+                delay_ms,
+                error,
+            } => {
+                // The retry streams the whole response again.
+                self.discard_message();
+                eprintln!(
+                    "{}",
+                    style(format!(
+                        "{error}\nRetrying in {:.1}s ({attempt}/{max_retries})...",
+                        delay_ms as f64 / 1000.0
+                    ))
+                    .yellow()
+                );
+            }
+            AgentEvent::ModelFallback { from, to, reason } => {
+                self.finish_message();
+                eprintln!(
+                    "{}",
+                    style(format!("{from} failed: {reason}\nFalling back to {to}...")).yellow()
+                );
+            }
+            AgentEvent::AnsweredBy { model } => {
+                println!("{}", style(format!("(answered by {model})")).dim())
+            }
+            AgentEvent::TurnCommitted { commit, files } => println!(
+                "{}",
+                style(format!(
+                    "Committed {} ({} file{})",
+                    &commit[..commit.len().min(8)],
+                    files.len(),
+                    if files.len() == 1 { "" } else { "s" }
+                ))
+                .dim()
+            ),
+            AgentEvent::AutoCommitSkipped { reason } => eprintln!(
+                "{}",
+                style(format!("Not committing this turn: {reason}")).yellow()
+            ),
+            AgentEvent::HistoryCompacted {
+                strategy,
+                tokens_before,
+                tokens_after,
+            } => println!(
+                "{}",
+                style(format!(
+                    "History compacted ({strategy:?}): ~{tokens_before} -> ~{tokens_after} tokens"
+                ))
+                .dim()
+            ),
+            AgentEvent::ToolCallRepaired { tool_call, repairs } => {
+                self.finish_message();
+                println!(
+                    "{}",
+                    style(format!(
+                        "Repaired tool call `{}`: {}",
+                        tool_call.function_call.name,
+                        repairs.join(", ")
+                    ))
+                    .dim()
+                );
+            }
+            AgentEvent::ToolCallDelta { .. }
+            | AgentEvent::ToolStarted { .. }
+            | AgentEvent::Usage { .. }
+            | AgentEvent::SubagentUsage { .. } => {}
+        }
+    }
+}
+
+/// Exits on the second Ctrl+C in a row.
+fn handle_ctrl_c(ctrl_c_pressed: &mut bool) {
+    if *ctrl_c_pressed {
+        println!("\nShutting down...");
+        process::exit(0);
     }
+    println!("\nPress Ctrl+C again to exit.");
+    *ctrl_c_pressed = true;
 }
 
 async fn display_user_message(
@@ -324,35 +542,3 @@ async fn display_user_message(
 
     Ok(())
 }
-
-fn spawn_stdin_channel() -> mpsc::Receiver<Option<String>> {
-    let (tx, rx) = mpsc::channel(1);
-    tokio::spawn(async move {
-        loop {
-            let result = tokio::task::spawn_blocking(|| {
-                let mut buffer = String::new();
-                match io::stdin().read_line(&mut buffer) {
-                    Ok(0) => Ok(None), // EOF (Ctrl+D)
-                    Ok(_) => Ok(Some(buffer.trim().to_string())),
-                    Err(e) => Err(e),
-                }
-            })
-            .await;
-
-            match result {
-                Ok(Ok(line_opt)) => {
-                    if tx.send(line_opt).await.is_err() {
-                        // Receiver was dropped, so we can exit.
-                        break;
-                    }
-                }
-                _ => {
-                    // An error occurred, signal EOF and exit the task.
-                    tx.send(None).await.ok();
-                    break;
-                }
-            }
-        }
-    });
-    rx
-}
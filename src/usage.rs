@@ -0,0 +1,125 @@
+//! # Token Usage and Cost
+//!
+//! Every LLM request reports its token usage. The agent prices it and adds it up per
+//! turn and per session.
+//!
+//! The cost is calculated from the `pricing` table in the configuration. Requests of
+//! models without a price are still counted, but their cost is unknown.
+
+use crate::events::TokenUsage;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// The price of a model in USD per million tokens.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct ModelPricing {
+    pub prompt: f64,
+    pub completion: f64,
+}
+
+impl ModelPricing {
+    /// Calculates the cost of a request in USD.
+    pub fn cost(&self, usage: &TokenUsage) -> f64 {
+        (usage.prompt_tokens as f64 * self.prompt
+            + usage.completion_tokens as f64 * self.completion)
+            / 1_000_000.0
+    }
+}
+
+/// Accumulated token usage and cost of several requests.
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct UsageTotals {
+    pub requests: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub total_tokens: u64,
+    /// The cost in USD of all priced requests.
+    pub cost: f64,
+    /// The number of requests whose cost is unknown.
+    pub unpriced_requests: u64,
+}
+
+impl UsageTotals {
+    pub fn add(&mut self, usage: &TokenUsage) {
+        self.requests += 1;
+        self.prompt_tokens += usage.prompt_tokens;
+        self.completion_tokens += usage.completion_tokens;
+        self.total_tokens += usage.total_tokens;
+        match usage.cost {
+            Some(cost) => self.cost += cost,
+            None => self.unpriced_requests += 1,
+        }
+    }
+
+    /// The cost, e.g. `$0.0123`, `$0.0123 (2 unpriced)` or `cost unknown`.
+    pub fn cost_summary(&self) -> String {
+        if self.unpriced_requests == self.requests {
+            return "cost unknown".to_string();
+        }
+        let mut summary = format!("${:.4}", self.cost);
+        if self.unpriced_requests > 0 {
+            summary.push_str(&format!(" ({} unpriced)", self.unpriced_requests));
+        }
+        summary
+    }
+}
+
+impl fmt::Display for UsageTotals {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let plural = if self.requests == 1 { "" } else { "s" };
+        write!(
+            f,
+            "{} prompt + {} completion tokens in {} request{plural}",
+            self.prompt_tokens, self.completion_tokens, self.requests
+        )?;
+        write!(f, ", {}", self.cost_summary())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usage(prompt_tokens: u64, completion_tokens: u64, cost: Option<f64>) -> TokenUsage {
+        TokenUsage {
+            prompt_tokens,
+            completion_tokens,
+            total_tokens: prompt_tokens + completion_tokens,
+            cost,
+        }
+    }
+
+    #[test]
+    fn test_pricing_is_per_million_tokens() {
+        let pricing = ModelPricing {
+            prompt: 3.0,
+            completion: 15.0,
+        };
+        let cost = pricing.cost(&usage(1_000_000, 100_000, None));
+        assert!((cost - 4.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_totals_count_unpriced_requests() {
+        let mut totals = UsageTotals::default();
+        totals.add(&usage(100, 10, Some(0.01)));
+        totals.add(&usage(200, 20, None));
+
+        assert_eq!(totals.requests, 2);
+        assert_eq!(totals.prompt_tokens, 300);
+        assert_eq!(totals.total_tokens, 330);
+        assert_eq!(totals.unpriced_requests, 1);
+        assert_eq!(
+            totals.to_string(),
+            "300 prompt + 30 completion tokens in 2 requests, $0.0100 (1 unpriced)"
+        );
+    }
+
+    #[test]
+    fn test_cost_is_unknown_if_no_request_was_priced() {
+        let mut totals = UsageTotals::default();
+        totals.add(&usage(100, 10, None));
+
+        assert_eq!(totals.cost_summary(), "cost unknown");
+    }
+}
@@ -1,8 +1,26 @@
-use alors::{agent::Agent, config::Config, tool_collection::ToolCollection, tools::FileEditorTool};
-use anyhow::Result;
-use openrouter_api::models::tool::{FunctionCall, ToolCall};
-use serde_json::json;
-use std::{fs, sync::Arc};
+use alors::{
+    agent::{Agent, Approval, ApprovalRequest, StopReason, ToolApprover},
+    config::Config,
+    events::{AgentEvent, EventSink, TokenUsage},
+    file_state_manager::FileStateManager,
+    reasoning::ReasoningHistory,
+    scripted_client::{ScriptedClient, tool_call},
+    tool_collection::ToolCollection,
+    tools::{DelegateTool, FileCreatorTool, FileEditorTool, ListFilesTool, Tool},
+    usage::ModelPricing,
+};
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use openrouter_api::{
+    models::tool::{FunctionCall, FunctionDescription, ToolCall},
+    types::chat::Message,
+};
+use serde_json::{Value, json};
+use std::{
+    fs,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 use tempfile::tempdir;
 
 #[tokio::test]
@@ -54,3 +72,763 @@ async fn test_agent_uses_file_editor_tool() -> Result<()> {
 
     Ok(())
 }
+
+struct ApproveAll;
+
+#[async_trait::async_trait]
+impl ToolApprover for ApproveAll {
+    async fn approve(&mut self, _request: ApprovalRequest<'_>) -> Result<Approval> {
+        Ok(Approval::Approved)
+    }
+}
+
+#[tokio::test]
+async fn test_run_turn_stops_at_max_iterations() -> Result<()> {
+    let config = Config {
+        max_iterations: 0,
+        ..Default::default()
+    };
+    let mut agent = Agent::new(config, None, Arc::new(ToolCollection::new()));
+
+    let stop_reason = agent.run_turn("hello".to_string(), &mut ApproveAll).await?;
+
+    assert_eq!(stop_reason, StopReason::MaxIterations(0));
+    // The prompt is kept, so the next turn can continue from here.
+    assert_eq!(agent.messages.len(), 1);
+    assert_eq!(agent.messages[0].role, "user");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_run_turn_stops_a_tool_loop_after_max_iterations() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let config = Config {
+        max_iterations: 2,
+        accessible_paths: vec![temp_dir.path().to_string_lossy().to_string()],
+        ..Default::default()
+    };
+    let mut tool_collection = ToolCollection::new();
+    tool_collection.register(Box::new(ListFilesTool));
+    let list = |id: &str| tool_call(id, "list_files", json!({ "path": temp_dir.path() }));
+    // The model would keep calling tools forever.
+    let client = Arc::new(
+        ScriptedClient::new()
+            .with_tool_calls(vec![list("call_1")])
+            .with_tool_calls(vec![list("call_2")])
+            .with_tool_calls(vec![list("call_3")]),
+    );
+    let mut agent = Agent::new(config, Some(client.clone()), Arc::new(tool_collection));
+
+    let stop_reason = agent
+        .run_turn("explore".to_string(), &mut ApproveAll)
+        .await?;
+
+    assert_eq!(stop_reason, StopReason::MaxIterations(2));
+    assert_eq!(client.requests().len(), 2);
+    assert_eq!(client.remaining_responses(), 1);
+    let roles: Vec<&str> = agent.messages.iter().map(|m| m.role.as_str()).collect();
+    assert_eq!(roles, ["user", "assistant", "tool", "assistant", "tool"]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_truncated_arguments_are_refused() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let file_path = temp_dir.path().join("hello.txt");
+    let config = Config {
+        accessible_paths: vec![temp_dir.path().to_string_lossy().to_string()],
+        ..Default::default()
+    };
+    let mut tool_collection = ToolCollection::new();
+    tool_collection.register(Box::new(FileCreatorTool));
+
+    let mut truncated = tool_call(
+        "call_1",
+        "create_files",
+        json!({ "files": [{ "file_path": file_path, "content": "hello world\n" }] }),
+    );
+    let arguments = &truncated.function_call.arguments;
+    truncated.function_call.arguments = arguments[..arguments.find("world").unwrap()].to_string();
+    let client = Arc::new(
+        ScriptedClient::new()
+            .with_tool_calls(vec![truncated])
+            .with_text_response("I will retry."),
+    );
+    let mut agent = Agent::new(config, Some(client), Arc::new(tool_collection));
+
+    agent
+        .run_turn("create hello.txt".to_string(), &mut ApproveAll)
+        .await?;
+
+    assert!(!file_path.exists());
+    assert!(agent.messages[2].content.contains("are truncated"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_run_turn_with_scripted_client() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let file_path = temp_dir.path().join("hello.txt");
+    let config = Config {
+        accessible_paths: vec![temp_dir.path().to_string_lossy().to_string()],
+        ..Default::default()
+    };
+    let mut tool_collection = ToolCollection::new();
+    tool_collection.register(Box::new(FileCreatorTool));
+
+    let client = Arc::new(
+        ScriptedClient::new()
+            .with_tool_calls(vec![tool_call(
+                "call_1",
+                "create_files",
+                json!({ "files": [{ "file_path": file_path, "content": "hello\n" }] }),
+            )])
+            .with_text_response("Created hello.txt."),
+    );
+    let mut agent = Agent::new(config, Some(client.clone()), Arc::new(tool_collection));
+    let (events_tx, mut events_rx) = tokio::sync::mpsc::unbounded_channel();
+    agent.events = Arc::new(events_tx);
+
+    let stop_reason = agent
+        .run_turn("create hello.txt".to_string(), &mut ApproveAll)
+        .await?;
+
+    assert_eq!(stop_reason, StopReason::Done);
+    assert_eq!(fs::read_to_string(&file_path)?, "hello\n");
+    let roles: Vec<&str> = agent.messages.iter().map(|m| m.role.as_str()).collect();
+    assert_eq!(roles, ["user", "assistant", "tool", "assistant"]);
+    assert_eq!(agent.messages[3].content, "Created hello.txt.");
+
+    // The second request carries the tool result back to the model.
+    let requests = client.requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[1].messages.len(), 3);
+    assert_eq!(
+        requests[1].messages[2].tool_call_id.as_deref(),
+        Some("call_1")
+    );
+    assert_eq!(client.remaining_responses(), 0);
+
+    let mut text = String::new();
+    while let Ok(event) = events_rx.try_recv() {
+        if let AgentEvent::TextDelta { text: delta } = event {
+            text.push_str(&delta);
+        }
+    }
+    assert_eq!(text, "Created hello.txt.");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_tool_results_keep_order_around_read_only_calls() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let dir = temp_dir.path();
+    fs::create_dir(dir.join("a"))?;
+    fs::write(dir.join("a/one.txt"), "1")?;
+    let config = Config {
+        accessible_paths: vec![dir.to_string_lossy().to_string()],
+        ..Default::default()
+    };
+    let mut tool_collection = ToolCollection::new();
+    tool_collection.register(Box::new(ListFilesTool));
+    tool_collection.register(Box::new(FileCreatorTool));
+
+    let list =
+        |id: &str, path: &std::path::Path| tool_call(id, "list_files", json!({ "path": path }));
+    let client = Arc::new(
+        ScriptedClient::new()
+            .with_tool_calls(vec![
+                list("call_1", dir),
+                list("call_2", &dir.join("a")),
+                tool_call(
+                    "call_3",
+                    "create_files",
+                    json!({ "files": [{ "file_path": dir.join("a/two.txt"), "content": "2\n" }] }),
+                ),
+                // Runs after the file was created.
+                list("call_4", &dir.join("a")),
+                tool_call("call_5", "unknown_tool", json!({})),
+            ])
+            .with_text_response("Done."),
+    );
+    let mut agent = Agent::new(config, Some(client), Arc::new(tool_collection));
+
+    agent
+        .run_turn("look around".to_string(), &mut ApproveAll)
+        .await?;
+
+    let results: Vec<&Message> = agent.messages.iter().filter(|m| m.role == "tool").collect();
+    let ids: Vec<&str> = results
+        .iter()
+        .filter_map(|m| m.tool_call_id.as_deref())
+        .collect();
+    assert_eq!(ids, ["call_1", "call_2", "call_3", "call_4", "call_5"]);
+    assert!(!results[1].content.contains("two.txt"));
+    assert!(results[3].content.contains("two.txt"));
+    Ok(())
+}
+
+/// A read-only tool whose calls only succeed if two of them run at the same time.
+struct RendezvousTool {
+    barrier: Arc<tokio::sync::Barrier>,
+}
+
+#[async_trait]
+impl Tool for RendezvousTool {
+    fn name(&self) -> &'static str {
+        "rendezvous"
+    }
+
+    fn schema(&self) -> FunctionDescription {
+        FunctionDescription {
+            name: "rendezvous".to_string(),
+            description: None,
+            strict: None,
+            parameters: json!({ "type": "object", "properties": {} }),
+        }
+    }
+
+    fn preview(
+        &self,
+        _args: &Value,
+        _config: &Config,
+        _fsm: Arc<Mutex<FileStateManager>>,
+    ) -> Result<String> {
+        Ok(String::new())
+    }
+
+    async fn execute(
+        &self,
+        _args: &Value,
+        _config: &Config,
+        fsm: Arc<Mutex<FileStateManager>>,
+        _events: &dyn EventSink,
+    ) -> Result<String> {
+        // Like `read_files`, only the update of the file state holds the lock.
+        drop(fsm.lock().unwrap());
+        tokio::time::timeout(Duration::from_secs(5), self.barrier.wait())
+            .await
+            .map_err(|_| anyhow!("ran alone"))?;
+        Ok("met".to_string())
+    }
+
+    fn is_read_only(&self) -> bool {
+        true
+    }
+}
+
+#[tokio::test]
+async fn test_read_only_calls_overlap() -> Result<()> {
+    let mut tool_collection = ToolCollection::new();
+    tool_collection.register(Box::new(RendezvousTool {
+        barrier: Arc::new(tokio::sync::Barrier::new(2)),
+    }));
+    let client = Arc::new(
+        ScriptedClient::new()
+            .with_tool_calls(vec![
+                tool_call("call_1", "rendezvous", json!({})),
+                tool_call("call_2", "rendezvous", json!({})),
+            ])
+            .with_text_response("Done."),
+    );
+    let mut agent = Agent::new(Config::default(), Some(client), Arc::new(tool_collection));
+
+    agent.run_turn("meet".to_string(), &mut ApproveAll).await?;
+
+    let results: Vec<&str> = agent
+        .messages
+        .iter()
+        .filter(|m| m.role == "tool")
+        .map(|m| m.content.as_str())
+        .collect();
+    assert_eq!(results, ["met", "met"]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_run_turn_accumulates_usage_and_cost() -> Result<()> {
+    let usage = |prompt_tokens, completion_tokens, cost| TokenUsage {
+        prompt_tokens,
+        completion_tokens,
+        total_tokens: prompt_tokens + completion_tokens,
+        cost,
+    };
+    let mut config = Config::default();
+    config.pricing.insert(
+        config.model.clone(),
+        ModelPricing {
+            prompt: 1.0,
+            completion: 2.0,
+        },
+    );
+    let client = Arc::new(
+        ScriptedClient::new()
+            .with_text_response("first")
+            .with_usage(usage(1000, 500, None))
+            .with_text_response("second")
+            .with_usage(usage(2000, 100, Some(0.5))),
+    );
+    let mut agent = Agent::new(config, Some(client), Arc::new(ToolCollection::new()));
+
+    agent.run_turn("one".to_string(), &mut ApproveAll).await?;
+    // Priced from the configured table: 1000 * $1/M + 500 * $2/M.
+    assert!((agent.turn_usage.cost - 0.002).abs() < 1e-9);
+
+    agent.run_turn("two".to_string(), &mut ApproveAll).await?;
+    // The reported cost takes precedence over the table.
+    assert_eq!(agent.turn_usage.requests, 1);
+    assert!((agent.turn_usage.cost - 0.5).abs() < 1e-9);
+
+    assert_eq!(agent.session_usage.requests, 2);
+    assert_eq!(agent.session_usage.prompt_tokens, 3000);
+    assert_eq!(agent.session_usage.completion_tokens, 600);
+    assert!((agent.session_usage.cost - 0.502).abs() < 1e-9);
+    assert_eq!(agent.session_usage.unpriced_requests, 0);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_reasoning_history_keeps_only_the_last_reasoning() -> Result<()> {
+    let config = Config {
+        reasoning_history: ReasoningHistory::Last,
+        ..Default::default()
+    };
+    let client = Arc::new(
+        ScriptedClient::new()
+            .with_text_response("first")
+            .with_reasoning("thinking about one")
+            .with_text_response("second")
+            .with_reasoning("thinking about two")
+            .with_text_response("third"),
+    );
+    let mut agent = Agent::new(
+        config,
+        Some(client.clone()),
+        Arc::new(ToolCollection::new()),
+    );
+
+    for prompt in ["one", "two", "three"] {
+        agent.run_turn(prompt.to_string(), &mut ApproveAll).await?;
+    }
+
+    let requests = client.requests();
+    let contents: Vec<&str> = requests[2]
+        .messages
+        .iter()
+        .map(|m| m.content.as_str())
+        .collect();
+    assert_eq!(
+        contents,
+        [
+            "one",
+            "first",
+            "two",
+            "<think>\nthinking about two\n</think>\n\nsecond",
+            "three"
+        ]
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_text_tool_calls_are_parsed_for_configured_models() -> Result<()> {
+    let temp_dir = tempdir()?;
+    fs::write(temp_dir.path().join("notes.txt"), "hi")?;
+    let config = Config {
+        accessible_paths: vec![temp_dir.path().to_string_lossy().to_string()],
+        text_tool_call_models: vec![Config::default().model],
+        ..Default::default()
+    };
+    let mut tool_collection = ToolCollection::new();
+    tool_collection.register(Box::new(ListFilesTool));
+    let client = Arc::new(
+        ScriptedClient::new()
+            .with_text_response(&format!(
+                "Let me look.\n<tool_call>\n{}\n</tool_call>",
+                json!({ "name": "list_files", "arguments": { "path": temp_dir.path() } })
+            ))
+            .with_text_response("There is a notes.txt."),
+    );
+    let mut agent = Agent::new(config, Some(client.clone()), Arc::new(tool_collection));
+
+    agent
+        .run_turn("what is here?".to_string(), &mut ApproveAll)
+        .await?;
+
+    let roles: Vec<&str> = agent.messages.iter().map(|m| m.role.as_str()).collect();
+    assert_eq!(roles, ["user", "assistant", "tool", "assistant"]);
+    assert_eq!(agent.messages[1].content, "Let me look.");
+    assert!(agent.messages[2].content.contains("notes.txt"));
+
+    // The model never sees native tools or tool messages.
+    let requests = client.requests();
+    assert!(requests[1].tools.is_empty());
+    let sent_roles: Vec<&str> = requests[1]
+        .messages
+        .iter()
+        .map(|m| m.role.as_str())
+        .collect();
+    assert_eq!(sent_roles, ["system", "user", "assistant", "user"]);
+    assert!(requests[1].messages[0].content.contains("## list_files"));
+    assert!(requests[1].messages[2].content.contains("<tool_call>"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_malformed_tool_calls_are_repaired_before_execution() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let config = Config {
+        accessible_paths: vec![temp_dir.path().to_string_lossy().to_string()],
+        ..Default::default()
+    };
+    let mut tool_collection = ToolCollection::new();
+    tool_collection.register(Box::new(ListFilesTool));
+    let arguments = format!("{{'path': '{}',}}", temp_dir.path().display());
+    let client = Arc::new(
+        ScriptedClient::new()
+            .with_tool_calls(vec![ToolCall {
+                id: String::new(),
+                kind: "function".to_string(),
+                function_call: FunctionCall {
+                    name: "list_files".to_string(),
+                    arguments,
+                },
+            }])
+            .with_text_response("Empty."),
+    );
+    let mut agent = Agent::new(config, Some(client), Arc::new(tool_collection));
+    let (events_tx, mut events_rx) = tokio::sync::mpsc::unbounded_channel();
+    agent.events = Arc::new(events_tx);
+
+    agent
+        .run_turn("what is here?".to_string(), &mut ApproveAll)
+        .await?;
+
+    let tool_call = &agent.messages[1].tool_calls.as_ref().unwrap()[0];
+    assert_eq!(tool_call.id, "call_1_0");
+    assert!(serde_json::from_str::<serde_json::Value>(&tool_call.function_call.arguments).is_ok());
+    assert_eq!(agent.messages[2].tool_call_id.as_deref(), Some("call_1_0"));
+    assert!(!agent.messages[2].content.contains("Failed to parse"));
+
+    let mut repairs = Vec::new();
+    while let Ok(event) = events_rx.try_recv() {
+        if let AgentEvent::ToolCallRepaired { repairs: r, .. } = event {
+            repairs.extend(r);
+        }
+    }
+    assert_eq!(
+        repairs,
+        [
+            "added a missing id",
+            "replaced single quotes",
+            "removed trailing commas"
+        ]
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_delegated_task_returns_only_the_summary() -> Result<()> {
+    let temp_dir = tempdir()?;
+    fs::write(temp_dir.path().join("notes.txt"), "hi")?;
+    let config = Config {
+        accessible_paths: vec![temp_dir.path().to_string_lossy().to_string()],
+        subagent_model: Some("small-model".to_string()),
+        ..Default::default()
+    };
+    // The parent and the sub-agent share the client, so their responses interleave.
+    let client = Arc::new(
+        ScriptedClient::new()
+            .with_tool_calls(vec![tool_call(
+                "call_1",
+                "delegate_task",
+                json!({ "task": "What files are there?" }),
+            )])
+            .with_tool_calls(vec![tool_call(
+                "sub_1",
+                "list_files",
+                json!({ "path": temp_dir.path() }),
+            )])
+            .with_text_response("There is only notes.txt.")
+            .with_text_response("The directory contains notes.txt."),
+    );
+    let mut subagent_tools = ToolCollection::new();
+    subagent_tools.register(Box::new(ListFilesTool));
+    let mut tool_collection = ToolCollection::new();
+    tool_collection.register(Box::new(DelegateTool::new(
+        client.clone(),
+        Arc::new(subagent_tools),
+    )));
+    let mut agent = Agent::new(config, Some(client.clone()), Arc::new(tool_collection));
+
+    let stop_reason = agent
+        .run_turn("explore".to_string(), &mut ApproveAll)
+        .await?;
+
+    assert_eq!(stop_reason, StopReason::Done);
+    let roles: Vec<&str> = agent.messages.iter().map(|m| m.role.as_str()).collect();
+    assert_eq!(roles, ["user", "assistant", "tool", "assistant"]);
+    assert_eq!(agent.messages[2].content, "There is only notes.txt.");
+
+    let requests = client.requests();
+    let models: Vec<&str> = requests.iter().map(|r| r.model.as_str()).collect();
+    assert_eq!(
+        models,
+        [
+            "openai/gpt-4.1-mini",
+            "small-model",
+            "small-model",
+            "openai/gpt-4.1-mini"
+        ]
+    );
+    // The sub-agent starts with its own history and only sees its own tools.
+    assert_eq!(requests[1].messages[0].role, "system");
+    assert_eq!(requests[1].messages[1].content, "What files are there?");
+    assert_eq!(requests[1].tools.len(), 1);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_usage_of_a_sub_agent_counts_towards_the_parent() -> Result<()> {
+    let usage = |prompt_tokens, cost| TokenUsage {
+        prompt_tokens,
+        completion_tokens: 0,
+        total_tokens: prompt_tokens,
+        cost,
+    };
+    let config = Config {
+        subagent_model: Some("small-model".to_string()),
+        ..Default::default()
+    };
+    // The parent and the sub-agent share the client, so their responses interleave.
+    let client = Arc::new(
+        ScriptedClient::new()
+            .with_tool_calls(vec![tool_call(
+                "call_1",
+                "delegate_task",
+                json!({ "task": "What files are there?" }),
+            )])
+            .with_usage(usage(100, Some(0.1)))
+            .with_text_response("There are none.")
+            .with_usage(usage(20, Some(0.02)))
+            .with_text_response("The directory is empty.")
+            .with_usage(usage(200, Some(0.2))),
+    );
+    let mut tool_collection = ToolCollection::new();
+    tool_collection.register(Box::new(DelegateTool::new(
+        client.clone(),
+        Arc::new(ToolCollection::new()),
+    )));
+    let mut agent = Agent::new(config, Some(client), Arc::new(tool_collection));
+
+    agent
+        .run_turn("explore".to_string(), &mut ApproveAll)
+        .await?;
+
+    assert_eq!(agent.turn_usage.requests, 3);
+    assert_eq!(agent.turn_usage.prompt_tokens, 320);
+    assert!((agent.turn_usage.cost - 0.32).abs() < 1e-9);
+    assert_eq!(agent.session_usage, agent.turn_usage);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_architect_plans_and_editor_implements() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let file_path = temp_dir.path().join("hello.txt");
+    let config = Config {
+        accessible_paths: vec![temp_dir.path().to_string_lossy().to_string()],
+        architect_model: Some("strong-model".to_string()),
+        editor_model: Some("cheap-model".to_string()),
+        ..Default::default()
+    };
+    let mut tool_collection = ToolCollection::new();
+    tool_collection.register(Box::new(ListFilesTool));
+    tool_collection.register(Box::new(FileCreatorTool));
+    let client = Arc::new(
+        ScriptedClient::new()
+            .with_text_response("Create hello.txt containing `hello`.")
+            .with_tool_calls(vec![tool_call(
+                "call_1",
+                "create_files",
+                json!({ "files": [{ "file_path": file_path, "content": "hello\n" }] }),
+            )])
+            .with_text_response("Done."),
+    );
+    let mut agent = Agent::new(config, Some(client.clone()), Arc::new(tool_collection));
+
+    let stop_reason = agent
+        .run_turn("create hello.txt".to_string(), &mut ApproveAll)
+        .await?;
+
+    assert_eq!(stop_reason, StopReason::Done);
+    assert_eq!(fs::read_to_string(&file_path)?, "hello\n");
+
+    let requests = client.requests();
+    let models: Vec<&str> = requests.iter().map(|r| r.model.as_str()).collect();
+    assert_eq!(models, ["strong-model", "cheap-model", "cheap-model"]);
+    // The architect can only read.
+    let architect_tools: Vec<String> = requests[0]
+        .tools
+        .iter()
+        .map(|tool| match tool {
+            openrouter_api::models::tool::Tool::Function { function } => function.name.clone(),
+        })
+        .collect();
+    assert_eq!(architect_tools, ["list_files"]);
+    assert_eq!(requests[1].tools.len(), 2);
+    // The editor sees the plan, followed by the hand-off, which is not kept.
+    let editor_messages = &requests[1].messages;
+    assert_eq!(
+        editor_messages[editor_messages.len() - 2].content,
+        "Create hello.txt containing `hello`."
+    );
+    assert_eq!(editor_messages.last().unwrap().role, "system");
+    let roles: Vec<&str> = agent.messages.iter().map(|m| m.role.as_str()).collect();
+    assert_eq!(
+        roles,
+        ["user", "assistant", "assistant", "tool", "assistant"]
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_architect_delegates_to_a_sub_agent_without_architect_mode() -> Result<()> {
+    let config = Config {
+        architect_model: Some("strong-model".to_string()),
+        editor_model: Some("cheap-model".to_string()),
+        subagent_model: Some("small-model".to_string()),
+        ..Default::default()
+    };
+    // The parent and the sub-agent share the client, so their responses interleave.
+    let client = Arc::new(
+        ScriptedClient::new()
+            .with_tool_calls(vec![tool_call(
+                "call_1",
+                "delegate_task",
+                json!({ "task": "What files are there?" }),
+            )])
+            .with_text_response("There are none.")
+            .with_text_response("Create hello.txt.")
+            .with_text_response("Done."),
+    );
+    let mut tool_collection = ToolCollection::new();
+    tool_collection.register(Box::new(DelegateTool::new(
+        client.clone(),
+        Arc::new(ToolCollection::new()),
+    )));
+    let mut agent = Agent::new(config, Some(client.clone()), Arc::new(tool_collection));
+
+    agent
+        .run_turn("create hello.txt".to_string(), &mut ApproveAll)
+        .await?;
+
+    let requests = client.requests();
+    let models: Vec<&str> = requests.iter().map(|r| r.model.as_str()).collect();
+    assert_eq!(
+        models,
+        ["strong-model", "small-model", "strong-model", "cheap-model"]
+    );
+    assert_eq!(agent.messages[2].content, "There are none.");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_editor_is_skipped_without_a_plan() -> Result<()> {
+    let config = Config {
+        architect_model: Some("strong-model".to_string()),
+        editor_model: Some("cheap-model".to_string()),
+        ..Default::default()
+    };
+    let client = Arc::new(ScriptedClient::new().with_text_response(""));
+    let mut agent = Agent::new(
+        config,
+        Some(client.clone()),
+        Arc::new(ToolCollection::new()),
+    );
+
+    let stop_reason = agent
+        .run_turn("create hello.txt".to_string(), &mut ApproveAll)
+        .await?;
+
+    assert_eq!(stop_reason, StopReason::Done);
+    assert_eq!(client.requests().len(), 1);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_undo_turn_restores_files_and_history() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let created_path = temp_dir.path().join("created.txt");
+    let config = Config {
+        accessible_paths: vec![temp_dir.path().to_string_lossy().to_string()],
+        ..Default::default()
+    };
+    let mut tool_collection = ToolCollection::new();
+    tool_collection.register(Box::new(FileCreatorTool));
+    let client = Arc::new(
+        ScriptedClient::new()
+            .with_text_response("Hi!")
+            .with_tool_calls(vec![tool_call(
+                "call_1",
+                "create_files",
+                json!({ "files": [{ "file_path": created_path, "content": "new\n" }] }),
+            )])
+            .with_text_response("Created it."),
+    );
+    let mut agent = Agent::new(config, Some(client), Arc::new(tool_collection));
+
+    agent.run_turn("hello".to_string(), &mut ApproveAll).await?;
+    let history_after_first_turn = agent.messages.clone();
+    agent
+        .run_turn("create a file".to_string(), &mut ApproveAll)
+        .await?;
+    assert!(created_path.exists());
+
+    let restored = agent.undo_turn()?.expect("a checkpoint");
+
+    assert!(!created_path.exists());
+    assert_eq!(restored.removed_files.len(), 1);
+    assert_eq!(agent.messages.len(), history_after_first_turn.len());
+    assert_eq!(agent.messages.last().unwrap().content, "Hi!");
+    agent.undo_turn()?.expect("a checkpoint");
+    assert!(agent.messages.is_empty());
+    assert!(agent.undo_turn()?.is_none());
+    Ok(())
+}
+
+#[test]
+fn test_cancel_pending_tool_calls_answers_open_calls() {
+    let mut agent = Agent::new(Config::default(), None, Arc::new(ToolCollection::new()));
+    let tool_call = |id: &str| ToolCall {
+        id: id.to_string(),
+        kind: "function".to_string(),
+        function_call: FunctionCall {
+            name: "list_files".to_string(),
+            arguments: json!({ "path": "." }).to_string(),
+        },
+    };
+    agent.messages.push(Message {
+        role: "assistant".to_string(),
+        content: String::new(),
+        name: None,
+        tool_calls: Some(vec![tool_call("call_1"), tool_call("call_2")]),
+        tool_call_id: None,
+    });
+    agent.messages.push(Message {
+        role: "tool".to_string(),
+        content: "Files in `.`".to_string(),
+        name: Some("list_files".to_string()),
+        tool_call_id: Some("call_1".to_string()),
+        tool_calls: None,
+    });
+
+    agent.cancel_pending_tool_calls();
+
+    assert_eq!(agent.messages.len(), 3);
+    assert_eq!(agent.messages[2].tool_call_id.as_deref(), Some("call_2"));
+    assert_eq!(
+        agent.messages[2].content,
+        "Tool execution cancelled by user."
+    );
+}
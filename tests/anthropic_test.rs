@@ -0,0 +1,194 @@
+mod common;
+
+use alors::{
+    anthropic::AnthropicClient,
+    client::{ChatRequest, LlmClient},
+    events::{AgentEvent, NullSink},
+};
+use anyhow::Result;
+use common::{MockResponse, MockServer, message};
+use serde_json::json;
+use std::time::Duration;
+
+fn client(server: &MockServer) -> AnthropicClient {
+    AnthropicClient::new(
+        &server.base_url,
+        Some("test-key".to_string()),
+        reqwest::Client::builder()
+            .timeout(Duration::from_secs(5))
+            .build()
+            .unwrap(),
+    )
+}
+
+#[tokio::test]
+async fn test_streaming_tool_use_with_thinking() -> Result<()> {
+    let server = MockServer::start(vec![
+        MockResponse::sse(&[
+            (
+                "message_start",
+                json!({ "type": "message_start", "message": { "usage": { "input_tokens": 10, "cache_read_input_tokens": 90, "output_tokens": 1 } } }),
+            ),
+            (
+                "content_block_start",
+                json!({ "type": "content_block_start", "index": 0, "content_block": { "type": "thinking", "thinking": "", "signature": "" } }),
+            ),
+            (
+                "content_block_delta",
+                json!({ "type": "content_block_delta", "index": 0, "delta": { "type": "thinking_delta", "thinking": "I should list files." } }),
+            ),
+            (
+                "content_block_delta",
+                json!({ "type": "content_block_delta", "index": 0, "delta": { "type": "signature_delta", "signature": "sig-1" } }),
+            ),
+            (
+                "content_block_stop",
+                json!({ "type": "content_block_stop", "index": 0 }),
+            ),
+            (
+                "content_block_start",
+                json!({ "type": "content_block_start", "index": 1, "content_block": { "type": "text", "text": "" } }),
+            ),
+            (
+                "content_block_delta",
+                json!({ "type": "content_block_delta", "index": 1, "delta": { "type": "text_delta", "text": "Let me look." } }),
+            ),
+            (
+                "content_block_start",
+                json!({ "type": "content_block_start", "index": 2, "content_block": { "type": "tool_use", "id": "toolu_1", "name": "list_files", "input": {} } }),
+            ),
+            (
+                "content_block_delta",
+                json!({ "type": "content_block_delta", "index": 2, "delta": { "type": "input_json_delta", "partial_json": "{\"path\": " } }),
+            ),
+            (
+                "content_block_delta",
+                json!({ "type": "content_block_delta", "index": 2, "delta": { "type": "input_json_delta", "partial_json": "\".\"}" } }),
+            ),
+            ("ping", json!({ "type": "ping" })),
+            (
+                "message_delta",
+                json!({ "type": "message_delta", "delta": { "stop_reason": "tool_use" }, "usage": { "output_tokens": 25 } }),
+            ),
+            ("message_stop", json!({ "type": "message_stop" })),
+        ]),
+        MockResponse::sse(&[
+            (
+                "content_block_start",
+                json!({ "type": "content_block_start", "index": 0, "content_block": { "type": "text", "text": "" } }),
+            ),
+            (
+                "content_block_delta",
+                json!({ "type": "content_block_delta", "index": 0, "delta": { "type": "text_delta", "text": "One file." } }),
+            ),
+            ("message_stop", json!({ "type": "message_stop" })),
+        ]),
+    ])
+    .await;
+    let client = client(&server);
+    let (events_tx, mut events_rx) = tokio::sync::mpsc::unbounded_channel();
+
+    let mut messages = vec![message("system", "be brief"), message("user", "list files")];
+    let request = ChatRequest {
+        model: "claude-sonnet-4-5".to_string(),
+        messages: messages.clone(),
+        tools: Vec::new(),
+    };
+    let response = client.chat_completion_stream(request, &events_tx).await?;
+    let assistant = response.message.expect("a message");
+
+    assert_eq!(assistant.content, "Let me look.");
+    let tool_calls = assistant.tool_calls.clone().unwrap();
+    assert_eq!(tool_calls.len(), 1);
+    assert_eq!(tool_calls[0].id, "toolu_1");
+    assert_eq!(tool_calls[0].function_call.name, "list_files");
+    assert_eq!(tool_calls[0].function_call.arguments, r#"{"path":"."}"#);
+
+    let mut text = String::new();
+    while let Ok(event) = events_rx.try_recv() {
+        if let AgentEvent::TextDelta { text: delta } = event {
+            text.push_str(&delta);
+        }
+    }
+    assert_eq!(text, "Let me look.");
+    let usage = response.usage.expect("usage");
+    assert_eq!(usage.prompt_tokens, 100);
+    assert_eq!(usage.completion_tokens, 25);
+
+    // The follow-up request must replay the thinking block before the tool use.
+    messages.push(assistant);
+    let mut tool_result = message("tool", "a.txt");
+    tool_result.tool_call_id = Some("toolu_1".to_string());
+    messages.push(tool_result);
+    let request = ChatRequest {
+        model: "claude-sonnet-4-5".to_string(),
+        messages,
+        tools: Vec::new(),
+    };
+    let answer = client.chat_completion_stream(request, &NullSink).await?;
+    assert_eq!(answer.message.unwrap().content, "One file.");
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[0].path, "/v1/messages");
+    assert_eq!(requests[0].header("x-api-key"), Some("test-key"));
+    assert!(requests[0].header("anthropic-version").is_some());
+
+    let body = requests[1].json();
+    assert_eq!(body["stream"], true);
+    assert_eq!(body["system"][0]["text"], "be brief");
+    let assistant_content = &body["messages"][1]["content"];
+    assert_eq!(assistant_content[0]["type"], "thinking");
+    assert_eq!(assistant_content[0]["signature"], "sig-1");
+    assert_eq!(assistant_content[2]["type"], "tool_use");
+    assert_eq!(body["messages"][2]["content"][0]["type"], "tool_result");
+    assert_eq!(body["messages"][2]["content"][0]["tool_use_id"], "toolu_1");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_non_streaming_response() -> Result<()> {
+    let server = MockServer::start(vec![MockResponse::json(json!({
+        "id": "msg_1",
+        "type": "message",
+        "role": "assistant",
+        "content": [{ "type": "text", "text": "Hello!" }],
+        "stop_reason": "end_turn",
+        "usage": { "input_tokens": 12, "output_tokens": 3 }
+    }))])
+    .await;
+
+    let request = ChatRequest {
+        model: "claude-sonnet-4-5".to_string(),
+        messages: vec![message("user", "hi")],
+        tools: Vec::new(),
+    };
+    let answer = client(&server).chat_completion(request, &NullSink).await?;
+
+    assert_eq!(answer.message.unwrap().content, "Hello!");
+    assert_eq!(answer.usage.unwrap().prompt_tokens, 12);
+    assert_eq!(server.requests()[0].json()["stream"], false);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_http_error_is_reported() {
+    let server = MockServer::start(vec![MockResponse::error(
+        400,
+        r#"{"type":"error","error":{"type":"invalid_request_error","message":"max_tokens is too large"}}"#,
+    )])
+    .await;
+
+    let request = ChatRequest {
+        model: "claude-sonnet-4-5".to_string(),
+        messages: vec![message("user", "hi")],
+        tools: Vec::new(),
+    };
+    let error = client(&server)
+        .chat_completion_stream(request, &NullSink)
+        .await
+        .unwrap_err();
+
+    assert!(error.to_string().contains("400"));
+    assert!(error.to_string().contains("max_tokens is too large"));
+}
@@ -0,0 +1,147 @@
+mod common;
+
+use alors::{
+    client::{ChatRequest, initialize_client},
+    config::{Config, ConfigLayer},
+    events::NullSink,
+};
+use anyhow::Result;
+use common::{MockResponse, MockServer, message};
+use serde_json::json;
+
+/// The configuration that results from the given `config.toml`.
+fn config(file: &str) -> Config {
+    let layer: ConfigLayer = toml::from_str(file).unwrap();
+    let mut config = Config::default();
+    config.merge(&layer);
+    config
+}
+
+fn request(model: &str) -> ChatRequest {
+    ChatRequest {
+        model: model.to_string(),
+        messages: vec![message("user", "hi")],
+        tools: Vec::new(),
+    }
+}
+
+fn anthropic_answer() -> MockResponse {
+    MockResponse::json(json!({
+        "id": "msg_1",
+        "type": "message",
+        "role": "assistant",
+        "content": [{ "type": "text", "text": "Hello!" }],
+        "stop_reason": "end_turn",
+        "usage": { "input_tokens": 12, "output_tokens": 3 }
+    }))
+}
+
+#[tokio::test]
+async fn test_user_defined_backend_is_selected_with_its_headers() -> Result<()> {
+    let server = MockServer::start(vec![anthropic_answer()]).await;
+    let config = config(&format!(
+        r#"
+        backend = "gateway"
+
+        [backends.gateway]
+        api = "anthropic"
+        base_url = "{}"
+        api_key_command = "echo gateway-key"
+        headers = {{ "X-Team" = "platform" }}
+        "#,
+        server.base_url
+    ));
+
+    let answer = initialize_client(&config)?
+        .chat_completion(request("claude-sonnet-4-5"), &NullSink)
+        .await?;
+
+    assert_eq!(answer.message.unwrap().content, "Hello!");
+    let requests = server.requests();
+    assert_eq!(requests[0].path, "/v1/messages");
+    assert_eq!(requests[0].header("x-api-key"), Some("gateway-key"));
+    assert_eq!(requests[0].header("x-team"), Some("platform"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_base_url_overrides_the_backend() -> Result<()> {
+    let server = MockServer::start(vec![anthropic_answer()]).await;
+    let config = config(&format!(
+        r#"
+        backend = "gateway"
+        base_url = "{}"
+
+        [backends.gateway]
+        api = "anthropic"
+        base_url = "http://llm.invalid/v1/"
+        "#,
+        server.base_url
+    ));
+
+    initialize_client(&config)?
+        .chat_completion(request("claude-sonnet-4-5"), &NullSink)
+        .await?;
+
+    assert_eq!(server.requests()[0].path, "/v1/messages");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_requests_go_through_the_proxy() -> Result<()> {
+    let proxy = MockServer::start(vec![anthropic_answer()]).await;
+    let config = config(&format!(
+        r#"
+        backend = "gateway"
+
+        [backends.gateway]
+        api = "anthropic"
+        base_url = "http://llm.invalid/v1/"
+        proxy = "{}"
+        "#,
+        proxy.base_url.trim_end_matches("/v1/")
+    ));
+
+    initialize_client(&config)?
+        .chat_completion(request("claude-sonnet-4-5"), &NullSink)
+        .await?;
+
+    // A proxy gets the absolute URL of the request.
+    assert_eq!(proxy.requests()[0].path, "http://llm.invalid/v1/messages");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_openai_backends_send_the_headers_through_the_proxy() -> Result<()> {
+    let proxy = MockServer::start(vec![MockResponse::json(json!({
+        "id": "gen-1",
+        "choices": [{
+            "index": 0,
+            "message": { "role": "assistant", "content": "Hello!" },
+            "finish_reason": "stop"
+        }]
+    }))])
+    .await;
+    let config = config(&format!(
+        r#"
+        backend = "gateway"
+
+        [backends.gateway]
+        base_url = "http://llm.invalid/v1/"
+        headers = {{ "X-Team" = "platform" }}
+        proxy = "{}"
+        "#,
+        proxy.base_url.trim_end_matches("/v1/")
+    ));
+
+    let answer = initialize_client(&config)?
+        .chat_completion(request("qwen3-coder"), &NullSink)
+        .await?;
+
+    assert_eq!(answer.message.unwrap().content, "Hello!");
+    let requests = proxy.requests();
+    assert_eq!(requests[0].path, "http://llm.invalid/v1/chat/completions");
+    assert_eq!(requests[0].header("x-team"), Some("platform"));
+    assert_eq!(requests[0].header("authorization"), None);
+    Ok(())
+}
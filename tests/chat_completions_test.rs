@@ -0,0 +1,306 @@
+mod common;
+
+use alors::{
+    backend::Api,
+    client::{ChatCompletionsClient, ChatRequest, LlmClient},
+    events::{AgentEvent, NullSink},
+    reasoning::ReasoningEffort,
+    streaming_executor::RetryPolicy,
+};
+use anyhow::Result;
+use common::{MockResponse, MockServer, message};
+use serde_json::json;
+use std::time::Duration;
+
+fn request() -> ChatRequest {
+    ChatRequest {
+        model: "openai/gpt-4.1-mini".to_string(),
+        messages: vec![message("user", "list files")],
+        tools: Vec::new(),
+    }
+}
+
+fn client(server: &MockServer) -> ChatCompletionsClient {
+    ChatCompletionsClient::new(
+        Api::Openai,
+        &server.base_url,
+        Some("test-key".to_string()),
+        reqwest::Client::builder()
+            .timeout(Duration::from_secs(5))
+            .build()
+            .unwrap(),
+    )
+    .with_retry_policy(RetryPolicy {
+        max_retries: 3,
+        initial_delay: Duration::from_millis(1),
+        max_delay: Duration::from_millis(10),
+    })
+}
+
+#[tokio::test]
+async fn test_streaming_collects_text_tool_calls_and_usage() -> Result<()> {
+    let server = MockServer::start(vec![MockResponse::data_stream(&[
+        json!({ "model": "openai/gpt-4.1", "choices": [{ "index": 0, "delta": { "role": "assistant", "content": "Let me " } }] }),
+        json!({ "choices": [{ "index": 0, "delta": { "content": "look." } }] }),
+        json!({ "choices": [{ "index": 0, "delta": { "tool_calls": [{ "index": 0, "id": "call_1", "type": "function", "function": { "name": "list_files", "arguments": "" } }] } }] }),
+        json!({ "choices": [{ "index": 0, "delta": { "tool_calls": [{ "index": 0, "function": { "arguments": "{\"path\": \".\"}" } }] } }] }),
+        json!({ "choices": [{ "index": 0, "delta": {}, "finish_reason": "tool_calls" }], "usage": { "prompt_tokens": 120, "completion_tokens": 30, "total_tokens": 150 } }),
+    ])])
+    .await;
+    let (events_tx, mut events_rx) = tokio::sync::mpsc::unbounded_channel();
+
+    let response = client(&server)
+        .chat_completion_stream(request(), &events_tx)
+        .await?;
+
+    let message = response.message.expect("a message");
+    assert_eq!(message.content, "Let me look.");
+    let tool_calls = message.tool_calls.unwrap();
+    assert_eq!(tool_calls[0].id, "call_1");
+    assert_eq!(tool_calls[0].function_call.name, "list_files");
+    assert_eq!(tool_calls[0].function_call.arguments, r#"{"path": "."}"#);
+
+    // The model that answered, not the requested one.
+    assert_eq!(response.model.as_deref(), Some("openai/gpt-4.1"));
+    let usage = response.usage.expect("usage");
+    assert_eq!(usage.prompt_tokens, 120);
+    assert_eq!(usage.completion_tokens, 30);
+    assert_eq!(usage.cost, None);
+
+    let mut deltas = 0;
+    while let Ok(event) = events_rx.try_recv() {
+        if let AgentEvent::TextDelta { .. } = event {
+            deltas += 1;
+        }
+    }
+    assert_eq!(deltas, 2);
+
+    let requests = server.requests();
+    assert_eq!(requests[0].path, "/v1/chat/completions");
+    assert_eq!(requests[0].header("authorization"), Some("Bearer test-key"));
+    assert_eq!(requests[0].json()["stream"], true);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_streaming_separates_think_tags_from_text() -> Result<()> {
+    let server = MockServer::start(vec![MockResponse::data_stream(&[
+        json!({ "choices": [{ "index": 0, "delta": { "content": "<thi" } }] }),
+        json!({ "choices": [{ "index": 0, "delta": { "content": "nk>Hmm.</think>\n\nHi" } }] }),
+        json!({ "choices": [{ "index": 0, "delta": { "content": " there." } }] }),
+    ])])
+    .await;
+    let (events_tx, mut events_rx) = tokio::sync::mpsc::unbounded_channel();
+
+    let response = client(&server)
+        .chat_completion_stream(request(), &events_tx)
+        .await?;
+
+    assert_eq!(response.reasoning.as_deref(), Some("Hmm."));
+    assert_eq!(response.message.expect("a message").content, "Hi there.");
+
+    let mut reasoning = String::new();
+    while let Ok(event) = events_rx.try_recv() {
+        match event {
+            AgentEvent::ReasoningDelta { text } => reasoning.push_str(&text),
+            AgentEvent::TextDelta { text } => assert!(!text.contains("think")),
+            _ => {}
+        }
+    }
+    assert_eq!(reasoning, "Hmm.");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_streaming_reads_the_reasoning_field() -> Result<()> {
+    let server = MockServer::start(vec![MockResponse::data_stream(&[
+        json!({ "choices": [{ "index": 0, "delta": { "content": "", "reasoning": "The user " } }] }),
+        json!({ "choices": [{ "index": 0, "delta": { "content": null, "reasoning": "greets me." } }] }),
+        json!({ "choices": [{ "index": 0, "delta": { "content": "Hi!", "reasoning": null } }] }),
+    ])])
+    .await;
+    let (events_tx, mut events_rx) = tokio::sync::mpsc::unbounded_channel();
+
+    let response = client(&server)
+        .with_reasoning_effort(Some(ReasoningEffort::High))
+        .chat_completion_stream(request(), &events_tx)
+        .await?;
+
+    assert_eq!(response.reasoning.as_deref(), Some("The user greets me."));
+    assert_eq!(response.message.expect("a message").content, "Hi!");
+    let mut reasoning = String::new();
+    while let Ok(event) = events_rx.try_recv() {
+        if let AgentEvent::ReasoningDelta { text } = event {
+            reasoning.push_str(&text);
+        }
+    }
+    assert_eq!(reasoning, "The user greets me.");
+    // Only OpenRouter takes the `reasoning` object.
+    let body = server.requests()[0].json();
+    assert_eq!(body["reasoning_effort"], "high");
+    assert!(body.get("reasoning").is_none());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_non_streaming_reads_the_reasoning_field() -> Result<()> {
+    let server = MockServer::start(vec![MockResponse::json(json!({
+        "id": "gen-1",
+        "choices": [{
+            "index": 0,
+            "message": { "role": "assistant", "content": "Hi!", "reasoning": "The user greets me." },
+            "finish_reason": "stop"
+        }]
+    }))])
+    .await;
+
+    let response = ChatCompletionsClient::new(
+        Api::Openrouter,
+        &server.base_url,
+        None,
+        reqwest::Client::new(),
+    )
+    .with_reasoning_effort(Some(ReasoningEffort::Low))
+    .chat_completion(request(), &NullSink)
+    .await?;
+
+    assert_eq!(response.reasoning.as_deref(), Some("The user greets me."));
+    assert_eq!(response.message.expect("a message").content, "Hi!");
+    let request = &server.requests()[0];
+    assert_eq!(request.json()["reasoning"], json!({ "effort": "low" }));
+    assert_eq!(request.header("authorization"), None);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_streamed_tool_calls_keep_stream_order() -> Result<()> {
+    let server = MockServer::start(vec![MockResponse::data_stream(&[
+        json!({ "choices": [{ "index": 0, "delta": { "tool_calls": [{ "index": 0, "id": "call_z", "function": { "name": "list_files", "arguments": "{}" } }] } }] }),
+        json!({ "choices": [{ "index": 0, "delta": { "tool_calls": [{ "index": 1, "function": { "name": "read_files", "arguments": "{\"file_paths\": [" } }] } }] }),
+        json!({ "choices": [{ "index": 0, "delta": { "tool_calls": [{ "index": 1, "function": { "arguments": "]}" } }] } }] }),
+        json!({ "choices": [{ "index": 0, "delta": { "tool_calls": [{ "index": 2, "id": "call_a", "function": { "name": "list_files", "arguments": "{}" } }] } }] }),
+    ])])
+    .await;
+
+    let response = client(&server)
+        .chat_completion_stream(request(), &NullSink)
+        .await?;
+
+    let tool_calls = response.message.unwrap().tool_calls.unwrap();
+    let names: Vec<&str> = tool_calls
+        .iter()
+        .map(|tc| tc.function_call.name.as_str())
+        .collect();
+    assert_eq!(names, ["list_files", "read_files", "list_files"]);
+    let ids: Vec<&str> = tool_calls.iter().map(|tc| tc.id.as_str()).collect();
+    // The missing id is left to the agent's normalization.
+    assert_eq!(ids, ["call_z", "", "call_a"]);
+    assert_eq!(
+        tool_calls[1].function_call.arguments,
+        r#"{"file_paths": []}"#
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_non_streaming_response_with_usage() -> Result<()> {
+    let server = MockServer::start(vec![MockResponse::json(json!({
+        "id": "gen-1",
+        "model": "openai/gpt-4.1",
+        "choices": [{
+            "index": 0,
+            "message": { "role": "assistant", "content": "", "tool_calls": [{
+                "id": "call_1",
+                "type": "function",
+                "function": { "name": "list_files", "arguments": "{}" }
+            }] },
+            "finish_reason": "tool_calls"
+        }],
+        "usage": { "prompt_tokens": 12, "completion_tokens": 3, "total_tokens": 15 }
+    }))])
+    .await;
+
+    let response = client(&server)
+        .chat_completion(request(), &NullSink)
+        .await?;
+
+    let message = response.message.expect("a message");
+    assert_eq!(message.content, "");
+    assert_eq!(
+        message.tool_calls.unwrap()[0].function_call.name,
+        "list_files"
+    );
+    assert_eq!(response.model.as_deref(), Some("openai/gpt-4.1"));
+    let usage = response.usage.expect("usage");
+    assert_eq!(usage.total_tokens, 15);
+    assert_eq!(usage.cost, None);
+    assert_eq!(server.requests()[0].json()["stream"], false);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rate_limits_and_server_errors_are_retried() -> Result<()> {
+    let server = MockServer::start(vec![
+        MockResponse::error(429, r#"{"error":{"message":"rate limited"}}"#),
+        MockResponse::error(503, r#"{"error":{"message":"unavailable"}}"#),
+        MockResponse::data_stream(&[
+            json!({ "choices": [{ "index": 0, "delta": { "content": "Hello" } }] }),
+        ]),
+    ])
+    .await;
+    let (events_tx, mut events_rx) = tokio::sync::mpsc::unbounded_channel();
+
+    let response = client(&server)
+        .chat_completion_stream(request(), &events_tx)
+        .await?;
+
+    assert_eq!(response.message.unwrap().content, "Hello");
+    assert_eq!(server.requests().len(), 3);
+
+    let mut retries = Vec::new();
+    while let Ok(event) = events_rx.try_recv() {
+        if let AgentEvent::Retrying { attempt, .. } = event {
+            retries.push(attempt);
+        }
+    }
+    assert_eq!(retries, [1, 2]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_client_errors_are_not_retried() {
+    let server = MockServer::start(vec![
+        MockResponse::error(400, r#"{"error":{"message":"bad request"}}"#),
+        MockResponse::json(json!({ "choices": [] })),
+    ])
+    .await;
+
+    client(&server)
+        .chat_completion(request(), &NullSink)
+        .await
+        .unwrap_err();
+
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn test_retries_are_limited() {
+    let server = MockServer::start(vec![
+        MockResponse::error(500, "boom"),
+        MockResponse::error(502, "boom"),
+        MockResponse::error(503, "boom"),
+    ])
+    .await;
+
+    client(&server)
+        .with_retry_policy(RetryPolicy {
+            max_retries: 1,
+            initial_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(1),
+        })
+        .chat_completion_stream(request(), &NullSink)
+        .await
+        .unwrap_err();
+
+    assert_eq!(server.requests().len(), 2);
+}
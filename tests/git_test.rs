@@ -0,0 +1,236 @@
+use alors::{
+    agent::{Agent, Approval, ApprovalRequest, ToolApprover},
+    config::Config,
+    git::{self, RepoState},
+    reasoning::ReasoningHistory,
+    scripted_client::{ScriptedClient, tool_call},
+    tool_collection::ToolCollection,
+    tools::FileCreatorTool,
+};
+use anyhow::Result;
+use serde_json::json;
+use std::{
+    fs,
+    path::Path,
+    process::Command,
+    sync::{Arc, Mutex, PoisonError},
+    time::Duration,
+};
+use tempfile::{TempDir, tempdir};
+
+/// Serializes the tests that change the current directory, which all tests of this file
+/// share.
+static CURRENT_DIR: Mutex<()> = Mutex::new(());
+
+fn run_git(dir: &Path, args: &[&str]) -> String {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "git {args:?} failed");
+    String::from_utf8_lossy(&output.stdout).to_string()
+}
+
+/// A repository with one committed file, `README.md`.
+fn repo() -> TempDir {
+    let dir = tempdir().unwrap();
+    let path = dir.path();
+    run_git(path, &["init", "--quiet"]);
+    run_git(path, &["config", "user.name", "Test"]);
+    run_git(path, &["config", "user.email", "test@example.com"]);
+    run_git(path, &["config", "commit.gpgsign", "false"]);
+    fs::write(path.join("README.md"), "hello\n").unwrap();
+    run_git(path, &["add", "README.md"]);
+    run_git(path, &["commit", "--quiet", "-m", "Initial commit"]);
+    dir
+}
+
+#[test]
+fn test_commit_turn_commits_only_the_written_files() -> Result<()> {
+    let repo = repo();
+    let root = repo.path();
+    let repo_state = RepoState::read(root)?;
+    fs::create_dir(root.join("src"))?;
+    fs::write(root.join("src/main.rs"), "fn main() {}\n")?;
+
+    let commit = repo_state.commit_turn(
+        &[root.join("src/main.rs")],
+        "Add a main function",
+        "Added it.",
+        "test-model",
+    )?;
+
+    assert_eq!(run_git(root, &["rev-parse", "HEAD"]).trim(), commit);
+    assert_eq!(
+        run_git(root, &["show", "--name-only", "--format=", "HEAD"]),
+        "src/main.rs\n"
+    );
+    assert!(
+        run_git(root, &["log", "-1", "--format=%B"]).contains("Generated-by: alors (test-model)")
+    );
+    assert_eq!(run_git(root, &["status", "--porcelain"]), "");
+    Ok(())
+}
+
+#[test]
+fn test_commit_turn_refuses_a_dirty_working_tree() -> Result<()> {
+    let repo = repo();
+    let root = repo.path();
+    fs::write(root.join("README.md"), "changed by the user\n")?;
+    let repo_state = RepoState::read(root)?;
+    fs::write(root.join("notes.txt"), "written by the agent\n")?;
+
+    let error = repo_state
+        .commit_turn(&[root.join("notes.txt")], "Take notes", "", "test-model")
+        .unwrap_err();
+
+    assert!(error.to_string().contains("README.md"));
+    assert_eq!(run_git(root, &["rev-list", "--count", "HEAD"]).trim(), "1");
+    Ok(())
+}
+
+#[test]
+fn test_revert_undoes_a_commit_and_aborts_on_conflicts() -> Result<()> {
+    let repo = repo();
+    let root = repo.path();
+    let readme = root.join("README.md");
+
+    fs::write(&readme, "hello agent\n")?;
+    let commit = RepoState::read(root)?.commit_turn(&[readme.clone()], "Greet", "", "m")?;
+    git::revert(root, &commit)?;
+    assert_eq!(fs::read_to_string(&readme)?, "hello\n");
+
+    fs::write(&readme, "hello again\n")?;
+    let commit = RepoState::read(root)?.commit_turn(&[readme.clone()], "Greet", "", "m")?;
+    // The user changes the same line afterwards, so the revert conflicts.
+    fs::write(&readme, "hello user\n")?;
+    run_git(root, &["commit", "--quiet", "-am", "Change the greeting"]);
+
+    assert!(git::revert(root, &commit).is_err());
+    assert_eq!(fs::read_to_string(&readme)?, "hello user\n");
+    assert_eq!(run_git(root, &["status", "--porcelain"]), "");
+    assert!(!root.join(".git/REVERT_HEAD").exists());
+    Ok(())
+}
+
+struct ApproveAll;
+
+#[async_trait::async_trait]
+impl ToolApprover for ApproveAll {
+    async fn approve(&mut self, _request: ApprovalRequest<'_>) -> Result<Approval> {
+        Ok(Approval::Approved)
+    }
+}
+
+/// Approves the first tool call and never answers the second one, like a user who
+/// presses Ctrl+C at the confirmation.
+struct ApproveOnce {
+    approved: bool,
+}
+
+#[async_trait::async_trait]
+impl ToolApprover for ApproveOnce {
+    async fn approve(&mut self, _request: ApprovalRequest<'_>) -> Result<Approval> {
+        if self.approved {
+            std::future::pending::<()>().await;
+        }
+        self.approved = true;
+        Ok(Approval::Approved)
+    }
+}
+
+#[tokio::test]
+#[allow(clippy::await_holding_lock)]
+async fn test_aborted_turn_commits_the_files_written_so_far() -> Result<()> {
+    let _current_dir = CURRENT_DIR.lock().unwrap_or_else(PoisonError::into_inner);
+    let repo = repo();
+    let root = repo.path().canonicalize()?;
+    std::env::set_current_dir(&root)?;
+    let config = Config {
+        accessible_paths: vec![root.to_string_lossy().to_string()],
+        git_auto_commit: true,
+        ..Default::default()
+    };
+    let mut tool_collection = ToolCollection::new();
+    tool_collection.register(Box::new(FileCreatorTool));
+    let create = |id: &str, name: &str| {
+        tool_call(
+            id,
+            "create_files",
+            json!({ "files": [{ "file_path": root.join(name), "content": "x\n" }] }),
+        )
+    };
+    let client = Arc::new(
+        ScriptedClient::new()
+            .with_tool_calls(vec![create("call_1", "one.txt")])
+            .with_tool_calls(vec![create("call_2", "two.txt")]),
+    );
+    let mut agent = Agent::new(config, Some(client), Arc::new(tool_collection));
+
+    let mut approver = ApproveOnce { approved: false };
+    let turn = agent.run_turn("Create two files".to_string(), &mut approver);
+    assert!(
+        tokio::time::timeout(Duration::from_millis(500), turn)
+            .await
+            .is_err()
+    );
+    agent.abort_turn();
+
+    assert_eq!(
+        run_git(&root, &["show", "--name-only", "--format=%s", "HEAD"]),
+        "Create two files\n\none.txt\n"
+    );
+    assert!(!root.join("two.txt").exists());
+    Ok(())
+}
+
+#[tokio::test]
+#[allow(clippy::await_holding_lock)]
+async fn test_commit_message_has_only_the_answer_of_the_turn() -> Result<()> {
+    let _current_dir = CURRENT_DIR.lock().unwrap_or_else(PoisonError::into_inner);
+    let repo = repo();
+    let root = repo.path().canonicalize()?;
+    std::env::set_current_dir(&root)?;
+    let config = Config {
+        accessible_paths: vec![root.to_string_lossy().to_string()],
+        git_auto_commit: true,
+        reasoning_history: ReasoningHistory::All,
+        ..Default::default()
+    };
+    let mut tool_collection = ToolCollection::new();
+    tool_collection.register(Box::new(FileCreatorTool));
+    let create = |name: &str| {
+        tool_call(
+            "call_1",
+            "create_files",
+            json!({ "files": [{ "file_path": root.join(name), "content": "x\n" }] }),
+        )
+    };
+    let client = Arc::new(
+        ScriptedClient::new()
+            .with_text_response("An answer of an earlier turn.")
+            .with_tool_calls(vec![create("one.txt")])
+            .with_text_response("")
+            .with_tool_calls(vec![create("two.txt")])
+            .with_text_response("Created two.txt.")
+            .with_reasoning("The user wants a file."),
+    );
+    let mut agent = Agent::new(config, Some(client), Arc::new(tool_collection));
+
+    agent.run_turn("Hello".to_string(), &mut ApproveAll).await?;
+    agent
+        .run_turn("Create one.txt".to_string(), &mut ApproveAll)
+        .await?;
+    let message = run_git(&root, &["log", "-1", "--format=%B"]);
+    assert!(message.starts_with("Create one.txt\n\nFiles:"));
+
+    agent
+        .run_turn("Create two.txt".to_string(), &mut ApproveAll)
+        .await?;
+    let message = run_git(&root, &["log", "-1", "--format=%B"]);
+    assert!(message.starts_with("Create two.txt\n\nCreated two.txt.\n\nFiles:"));
+    assert!(!message.contains("The user wants a file."));
+    Ok(())
+}